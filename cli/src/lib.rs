@@ -1,8 +1,16 @@
 use brrrt_core::{
-    elf32::{Error, SectionName, ELF},
-    memory::MemoryError,
+    devices::{Clock, FramebufferConfig, PixelFormat},
+    elf32::{Error, SectionName, Segment, Symbols, ELF},
+    linux::{Image, LINUX_RAM_SIZE},
+    machine::{Virt, VIRT_RAM_SIZE, VIRT_RTC, VIRT_VIRTIO_SLOTS},
+    memory::{
+        Backend, CacheConfig, Fault, FileMode, MemoryError, RegionKind, Replacement, WritePolicy,
+        DEFAULT_MEMORY_POOL_SIZE, PAGE_SIZE,
+    },
     rv32i::instr::instruction::InstructionError,
-    Program, VM,
+    stack::{Stack, DEFAULT_STACK_SIZE},
+    vfs::guest_path,
+    MemoryMap, Program, Register, VM,
 };
 use std::{env, fs};
 
@@ -12,6 +20,22 @@ pub enum RuntimeError {
     Read,
     Load,
     Execution,
+    AccessFault(Fault),
+}
+
+impl RuntimeError {
+    /// Process exit status for the error, following sysexits(3) so it
+    /// stands apart from the guest's own 0 and 1. A guest can still return
+    /// 64 to 77 itself, so those are ambiguous.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Usage => 64,
+            Self::Load => 65,
+            Self::Read => 66,
+            Self::Execution => 70,
+            Self::AccessFault(_) => 77,
+        }
+    }
 }
 
 impl From<std::io::Error> for RuntimeError {
@@ -35,22 +59,28 @@ impl From<Error> for RuntimeError {
 }
 
 impl From<InstructionError> for RuntimeError {
-    fn from(_e: InstructionError) -> Self {
+    fn from(e: InstructionError) -> Self {
         #[cfg(feature = "trace")]
         {
-            eprintln!("Instruction error: {:?}", _e);
+            eprintln!("Instruction error: {:?}", e);
+        }
+        match e {
+            InstructionError::AccessFault(fault) => Self::AccessFault(fault),
+            _ => Self::Execution,
         }
-        Self::Execution
     }
 }
 
 impl From<MemoryError> for RuntimeError {
-    fn from(_e: MemoryError) -> Self {
+    fn from(e: MemoryError) -> Self {
         #[cfg(feature = "trace")]
         {
-            eprintln!("Memory error: {:?}", _e);
+            eprintln!("Memory error: {:?}", e);
+        }
+        match e {
+            MemoryError::AccessFault(fault) => Self::AccessFault(fault),
+            _ => Self::Execution,
         }
-        Self::Execution
     }
 }
 
@@ -69,62 +99,1023 @@ impl From<RuntimeError> for String {
         format!(
             "Runtime error: {}",
             match e {
-                RuntimeError::Usage => "unexpected usage",
-                RuntimeError::Read => "read error",
-                RuntimeError::Load => "load error",
-                RuntimeError::Execution => "execution aborted",
+                RuntimeError::Usage => "unexpected usage".to_owned(),
+                RuntimeError::Read => "read error".to_owned(),
+                RuntimeError::Load => "load error".to_owned(),
+                RuntimeError::Execution => "execution aborted".to_owned(),
+                RuntimeError::AccessFault(fault) => fault.to_string(),
             }
         )
     }
 }
 
-pub fn load_program() -> Result<Program, RuntimeError> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("USAGE:");
-        eprintln!("\t{}: <PROGRAM_BINFILE>", args[0]);
-        Err(RuntimeError::Usage)
+/// Command line options shared by the binaries.
+#[derive(Debug)]
+pub struct Options {
+    pub path: String,
+    pub memory: MemoryMap,
+    /// Report uses of uninitialized memory.
+    pub shadow: bool,
+    pub stack: Option<Stack>,
+    pub icache: Option<CacheConfig>,
+    pub dcache: Option<CacheConfig>,
+    /// Host files to map into guest memory, by base address.
+    pub files: Vec<(u32, String, FileMode)>,
+    /// Granularity of access counters, in bytes.
+    pub stats: Option<u32>,
+    /// Where to write access counters as CSV.
+    pub heatmap: Option<String>,
+    /// Base address of a 16550 console on stdio.
+    pub uart: Option<u32>,
+    /// virtio-mmio block devices, by base address.
+    pub disks: Vec<(u32, String, FileMode)>,
+    /// SPI controllers, by base address, each with a flash image on chip
+    /// select 0.
+    pub spi_flash: Vec<(u32, String, FileMode)>,
+    /// Base address and geometry of a framebuffer.
+    pub framebuffer: Option<(u32, FramebufferConfig)>,
+    /// Where to write presented frames as PPM; `%d` is replaced with the
+    /// frame number, otherwise only the last frame is written.
+    pub frames: Option<String>,
+    /// Base address and time source of a goldfish RTC.
+    pub rtc: Option<(u32, Clock)>,
+    /// Base address of a SiFive test finisher.
+    pub finisher: Option<u32>,
+    /// Named board to build instead of individually placed devices.
+    pub machine: Option<Machine>,
+    /// Images for the board's virtio slots, in order.
+    pub drives: Vec<(String, FileMode)>,
+    /// Kernel command line passed in the device tree.
+    pub bootargs: Option<String>,
+    /// Answer ECALLs as SBI firmware.
+    pub sbi: bool,
+    /// Answer ECALLs as Linux system calls.
+    pub linux: bool,
+    /// Host directory the guest sees as `/`.
+    pub root: Option<(String, FileMode)>,
+    /// In-memory guest files, as guest path and the host file to copy.
+    pub guest_files: Vec<(String, String)>,
+    /// Arguments for the guest after its own path, from past `--`.
+    pub args: Vec<String>,
+    /// Guest environment, as `NAME=VALUE`.
+    pub env: Vec<String>,
+}
+
+/// Machine profiles selectable with `--machine`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Machine {
+    /// QEMU's `virt` board.
+    Virt,
+}
+
+impl Options {
+    pub fn from_env() -> Result<Self, RuntimeError> {
+        let args: Vec<String> = env::args().collect();
+        let options = Self::parse(&args);
+        if options.is_err() {
+            usage(&args[0]);
+        }
+        options
+    }
+
+    pub fn parse(args: &[String]) -> Result<Self, RuntimeError> {
+        let mut path = None;
+        let mut memory: Option<MemoryMap> = None;
+        let mut shadow = false;
+        let mut stack = None;
+        let (mut icache, mut dcache) = (None, None);
+        let mut miss_penalty = None;
+        let mut files = Vec::new();
+        let mut stats = None;
+        let mut heatmap = None;
+        let mut uart = None;
+        let mut disks = Vec::new();
+        let mut spi_flash = Vec::new();
+        let mut framebuffer = None;
+        let mut frames = None;
+        let mut rtc = None;
+        let mut finisher = None;
+        let mut machine = None;
+        let mut drives = Vec::new();
+        let mut bootargs = None;
+        let mut sbi = false;
+        let mut linux = false;
+        let mut root = None;
+        let mut guest_files = Vec::new();
+        let mut guest_args = Vec::new();
+        let mut env = Vec::new();
+        let mut args = args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ram" | "--rom" => {
+                    let (base, size, backend) = args
+                        .next()
+                        .and_then(|x| parse_region(x))
+                        .ok_or(RuntimeError::Usage)?;
+                    let kind = if arg == "--ram" {
+                        RegionKind::Ram
+                    } else {
+                        RegionKind::Rom
+                    };
+                    let map = memory.take().unwrap_or_default();
+                    memory = Some(map.region(kind, base, size, backend));
+                }
+                "--shadow" => shadow = true,
+                "--sbi" => sbi = true,
+                "--linux" => linux = true,
+                "--env" => {
+                    let var = args.next().ok_or(RuntimeError::Usage)?;
+                    if !var.contains('=') {
+                        return Err(RuntimeError::Usage);
+                    }
+                    env.push(var.to_owned());
+                }
+                // Everything else belongs to the guest
+                "--" => guest_args.extend(args.by_ref().cloned()),
+                "--root" => {
+                    let (path, mode) = parse_mode(args.next().ok_or(RuntimeError::Usage)?);
+                    if path.is_empty() {
+                        return Err(RuntimeError::Usage);
+                    }
+                    root = Some((path.to_owned(), mode));
+                }
+                "--guest-file" => {
+                    guest_files.push(
+                        args.next()
+                            .and_then(|x| parse_guest_file(x))
+                            .ok_or(RuntimeError::Usage)?,
+                    );
+                }
+                "--stack" => {
+                    stack = Some(
+                        args.next()
+                            .and_then(|x| parse_stack(x))
+                            .ok_or(RuntimeError::Usage)?,
+                    );
+                }
+                "--icache" | "--dcache" => {
+                    let config = args
+                        .next()
+                        .and_then(|x| parse_cache(x))
+                        .ok_or(RuntimeError::Usage)?;
+                    if arg == "--icache" {
+                        icache = Some(config);
+                    } else {
+                        dcache = Some(config);
+                    }
+                }
+                "--map-file" => {
+                    files.push(
+                        args.next()
+                            .and_then(|x| parse_file(x))
+                            .ok_or(RuntimeError::Usage)?,
+                    );
+                }
+                "--virtio-blk" => {
+                    disks.push(
+                        args.next()
+                            .and_then(|x| parse_file(x))
+                            .ok_or(RuntimeError::Usage)?,
+                    );
+                }
+                "--spi-flash" => {
+                    spi_flash.push(
+                        args.next()
+                            .and_then(|x| parse_file(x))
+                            .ok_or(RuntimeError::Usage)?,
+                    );
+                }
+                "--framebuffer" => {
+                    framebuffer = Some(
+                        args.next()
+                            .and_then(|x| parse_framebuffer(x))
+                            .ok_or(RuntimeError::Usage)?,
+                    );
+                }
+                "--frames" => frames = Some(args.next().ok_or(RuntimeError::Usage)?.to_owned()),
+                "--rtc" => {
+                    rtc = Some(
+                        args.next()
+                            .and_then(|x| parse_rtc(x))
+                            .ok_or(RuntimeError::Usage)?,
+                    );
+                }
+                "--finisher" => {
+                    finisher = Some(
+                        args.next()
+                            .and_then(|x| parse_address(x))
+                            .ok_or(RuntimeError::Usage)?,
+                    );
+                }
+                "--machine" => {
+                    machine = match args.next().map(|x| x.as_str()) {
+                        Some("virt") => Some(Machine::Virt),
+                        _ => return Err(RuntimeError::Usage),
+                    };
+                }
+                "--drive" => {
+                    let (path, mode) = parse_mode(args.next().ok_or(RuntimeError::Usage)?);
+                    if path.is_empty() {
+                        return Err(RuntimeError::Usage);
+                    }
+                    drives.push((path.to_owned(), mode));
+                }
+                "--bootargs" => bootargs = Some(args.next().ok_or(RuntimeError::Usage)?.to_owned()),
+                "--stats" => {
+                    stats = Some(
+                        args.next()
+                            .and_then(|x| match x.as_str() {
+                                "page" => Some(PAGE_SIZE),
+                                "line" => Some(CacheConfig::default().line),
+                                x => parse_address(x).filter(|g| g.is_power_of_two()),
+                            })
+                            .ok_or(RuntimeError::Usage)?,
+                    );
+                }
+                "--heatmap" => heatmap = Some(args.next().ok_or(RuntimeError::Usage)?.to_owned()),
+                "--uart" => {
+                    uart = Some(
+                        args.next()
+                            .and_then(|x| parse_address(x))
+                            .ok_or(RuntimeError::Usage)?,
+                    );
+                }
+                "--miss-penalty" => {
+                    miss_penalty = Some(
+                        args.next()
+                            .and_then(|x| parse_number(x))
+                            .ok_or(RuntimeError::Usage)?,
+                    );
+                }
+                x if x.starts_with("--") => return Err(RuntimeError::Usage),
+                x if path.is_none() => path = Some(x.to_owned()),
+                _ => return Err(RuntimeError::Usage),
+            }
+        }
+        // The board places these itself; only its own RTC address is accepted
+        let conflicts = match machine {
+            Some(Machine::Virt) => {
+                uart.is_some()
+                    || finisher.is_some()
+                    || !disks.is_empty()
+                    || rtc.is_some_and(|(base, _)| base != VIRT_RTC)
+                    || drives.len() > VIRT_VIRTIO_SLOTS
+            }
+            None => !drives.is_empty() || bootargs.is_some(),
+        };
+        // Both would answer ECALL, and a board runs firmware, not programs
+        let files_without_linux = !linux && (root.is_some() || !guest_files.is_empty());
+        if conflicts || linux && (sbi || machine.is_some()) || files_without_linux {
+            return Err(RuntimeError::Usage);
+        }
+        if let Some(cycles) = miss_penalty {
+            for config in [icache.as_mut(), dcache.as_mut()].into_iter().flatten() {
+                config.miss_penalty = cycles;
+            }
+        }
+        Ok(Self {
+            path: path.ok_or(RuntimeError::Usage)?,
+            memory: memory.unwrap_or_else(|| match machine {
+                Some(Machine::Virt) => Virt::memory_map(VIRT_RAM_SIZE),
+                None if linux => MemoryMap::default().sparse_ram(0, LINUX_RAM_SIZE),
+                None => MemoryMap::default().ram(0, DEFAULT_MEMORY_POOL_SIZE as u64),
+            }),
+            shadow,
+            stack,
+            icache,
+            dcache,
+            files,
+            stats: stats.or(heatmap.as_ref().map(|_| PAGE_SIZE)),
+            heatmap,
+            uart,
+            disks,
+            spi_flash,
+            framebuffer,
+            frames,
+            rtc,
+            finisher,
+            machine,
+            drives,
+            bootargs,
+            sbi,
+            linux,
+            root,
+            guest_files,
+            args: guest_args,
+            env,
+        })
+    }
+
+    /// Configured stack, or else one at the top of the highest RAM region.
+    ///
+    /// The default stack grows down to at most [`DEFAULT_STACK_SIZE`] and
+    /// never into the image ending at `image_end`: a one-page guard, smaller
+    /// only when RAM is, keeps it from running into data or the heap. On a
+    /// board the stack ends below its device tree.
+    pub fn stack(&self, image_end: u32) -> Option<Stack> {
+        self.stack.or_else(|| {
+            let top = self.memory.stack_top()?;
+            let region = self
+                .memory
+                .regions()
+                .iter()
+                .filter(|r| r.kind == RegionKind::Ram)
+                .max_by_key(|r| r.base)?;
+            // Keep the top addressable when RAM runs up to the end of the address space
+            let top = match self.machine {
+                Some(Machine::Virt) => Virt::fdt_address(top),
+                None if top == 0 => 0u32.wrapping_sub(16),
+                None => top,
+            };
+            // An image elsewhere leaves the whole region to the stack
+            let floor = if (region.base..top).contains(&image_end) {
+                image_end
+            } else {
+                region.base
+            };
+            let room = top.checked_sub(floor)?;
+            let guard = PAGE_SIZE.min((room / 4) & !0xf);
+            let base = top
+                .saturating_sub(DEFAULT_STACK_SIZE)
+                .max((floor + guard).checked_next_multiple_of(16)?);
+            // Rounding up can leave no room below an unaligned top
+            let size = top.checked_sub(base).filter(|&size| size > 0)?;
+            Some(Stack { base, size, guard })
+        })
+    }
+}
+
+fn usage(cmd: &str) {
+    eprintln!("USAGE:");
+    eprintln!("\t{}: [OPTIONS] <PROGRAM_BINFILE> [-- <ARGS>...]", cmd);
+    eprintln!("OPTIONS:");
+    eprintln!("\t--ram <BASE>:<SIZE>[:sparse]\tadd a RAM region (repeatable)");
+    eprintln!("\t--rom <BASE>:<SIZE>[:sparse]\tadd a ROM region (repeatable)");
+    eprintln!("\t--shadow\t\t\treport uses of uninitialized memory");
+    eprintln!("\t--stack <BASE>:<SIZE>[:<GUARD>]\tstack region, with a 4K guard below by default");
+    eprintln!(
+        "\t--icache <SIZE>:<WAYS>:<LINE>[:lru|fifo|random][:wb|wt]\tsimulate an instruction cache"
+    );
+    eprintln!("\t--dcache <SIZE>:<WAYS>:<LINE>[:lru|fifo|random][:wb|wt]\tsimulate a data cache");
+    eprintln!("\t--map-file <BASE>:<PATH>[:ro|rw|sync]\tmap a host file, read-only by default");
+    eprintln!("\t--stats <page|line|BYTES>\tcount accesses per block and print the hottest");
+    eprintln!("\t--heatmap <PATH>\t\twrite access counts as CSV, per page by default");
+    eprintln!("\t--uart <BASE>\t\t\t16550 console on stdio, e.g. at 0x10000000");
+    eprintln!("\t--virtio-blk <BASE>:<IMAGE>[:ro|rw|sync]\tvirtio block device; rw keeps writes in memory");
+    eprintln!(
+        "\t--spi-flash <BASE>:<IMAGE>[:ro|rw|sync]\tSiFive SPI controller with a NOR flash on CS0"
+    );
+    eprintln!("\t--framebuffer <BASE>:<W>x<H>[:gray8|rgb565|xrgb8888]\tlinear framebuffer");
+    eprintln!("\t--frames <PATH>\t\t\twrite presented frames as PPM, one per %d or just the last");
+    eprintln!("\t--rtc <BASE>[:host|virtual[:<NS>]]\tgoldfish RTC; virtual (default) advances NS per instruction");
+    eprintln!("\t--finisher <BASE>\t\ttest finisher; a failure code becomes the exit status");
+    eprintln!("\t--machine virt\t\t\tQEMU virt board with a device tree, 128M RAM by default");
+    eprintln!("\t--drive <IMAGE>[:ro|rw|sync]\tdisk in the next virtio slot of the board");
+    eprintln!("\t--bootargs <ARGS>\t\tkernel command line for the board's device tree");
+    eprintln!("\t--sbi\t\t\t\tserve SBI calls, with the console on stdio");
+    eprintln!("\t--linux\t\t\t\tserve Linux system calls, 64M RAM by default");
+    eprintln!(
+        "\t--root <DIR>[:ro|rw|sync]\t\thost directory as the guest's /, read-only by default"
+    );
+    eprintln!("\t--guest-file <PATH>=<HOST>\tin-memory guest file with a copy of a host file");
+    eprintln!("\t--env <NAME>=<VALUE>\t\tguest environment variable (repeatable)");
+    eprintln!("\t--miss-penalty <CYCLES>\t\tcycles per cache miss (default 10)");
+    eprintln!("EXIT STATUS:");
+    eprintln!("\tthe guest's status, or 64-77 (sysexits) when brrrt itself fails;");
+    eprintln!("\ta guest returning 64-77 can't be told apart from those");
+}
+
+/// Parses decimal or `0x` prefixed hex numbers, with optional K/M/G suffix.
+fn parse_number(raw: &str) -> Option<u64> {
+    let (raw, multiplier) = match raw.chars().last()? {
+        'K' | 'k' => (&raw[..raw.len() - 1], 1 << 10),
+        'M' | 'm' => (&raw[..raw.len() - 1], 1 << 20),
+        'G' | 'g' => (&raw[..raw.len() - 1], 1 << 30),
+        _ => (raw, 1u64),
+    };
+    let value = if let Some(hex) = raw.strip_prefix("0x") {
+        u64::from_str_radix(&hex.replace('_', ""), 16).ok()?
     } else {
-        load_program_from(&args[1])
+        raw.replace('_', "").parse::<u64>().ok()?
+    };
+    value.checked_mul(multiplier)
+}
+
+fn parse_address(raw: &str) -> Option<u32> {
+    parse_number(raw)?.try_into().ok()
+}
+
+fn parse_region(raw: &str) -> Option<(u32, u64, Backend)> {
+    let mut parts = raw.split(':');
+    let base = parse_address(parts.next()?)?;
+    let size = parse_number(parts.next()?)?;
+    let backend = match parts.next() {
+        None => Backend::Flat,
+        Some("sparse") => Backend::Sparse,
+        Some(_) => return None,
+    };
+    Some((base, size, backend))
+}
+
+fn parse_stack(raw: &str) -> Option<Stack> {
+    let mut parts = raw.split(':');
+    let base = parse_address(parts.next()?)?;
+    let size = parse_address(parts.next()?)?;
+    let guard = match parts.next() {
+        None => PAGE_SIZE,
+        Some(guard) => parse_address(guard)?,
+    };
+    if parts.next().is_some() || base.checked_add(size).is_none() {
+        return None;
     }
+    Some(Stack { base, size, guard })
+}
+
+/// Parses `BASE[:host|virtual[:NS_PER_INSTRUCTION]]`.
+fn parse_rtc(raw: &str) -> Option<(u32, Clock)> {
+    let mut parts = raw.split(':');
+    let base = parse_address(parts.next()?)?;
+    let clock = match (parts.next(), parts.next()) {
+        (None, _) | (Some("virtual"), None) => Clock::default(),
+        (Some("virtual"), Some(ns)) => Clock::Virtual {
+            start: 0,
+            ns_per_tick: parse_number(ns)?,
+        },
+        (Some("host"), None) => Clock::Host,
+        _ => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((base, clock))
+}
+
+/// Parses `BASE:WxH[:FORMAT]`.
+fn parse_framebuffer(raw: &str) -> Option<(u32, FramebufferConfig)> {
+    let mut parts = raw.split(':');
+    let base = parse_address(parts.next()?)?;
+    let (width, height) = parts.next()?.split_once('x')?;
+    let format = match parts.next() {
+        None | Some("xrgb8888") => PixelFormat::Xrgb8888,
+        Some("rgb565") => PixelFormat::Rgb565,
+        Some("gray8") => PixelFormat::Gray8,
+        _ => return None,
+    };
+    let config = FramebufferConfig {
+        width: width.parse().ok().filter(|&w| w > 0)?,
+        height: height.parse().ok().filter(|&h| h > 0)?,
+        format,
+    };
+    if parts.next().is_some() || base as u64 + config.size() > 1 << 32 {
+        return None;
+    }
+    Some((base, config))
+}
+
+/// Splits an optional `:ro`, `:rw` or `:sync` suffix off a path.
+fn parse_mode(raw: &str) -> (&str, FileMode) {
+    match raw.rsplit_once(':') {
+        Some((path, "ro")) => (path, FileMode::ReadOnly),
+        Some((path, "rw")) => (path, FileMode::Private),
+        Some((path, "sync")) => (path, FileMode::Sync),
+        _ => (raw, FileMode::ReadOnly),
+    }
+}
+
+/// Guest path and host file of `--guest-file`; the guest path can't leave `/`.
+fn parse_guest_file(raw: &str) -> Option<(String, String)> {
+    let (guest, host) = raw.split_once('=')?;
+    let guest = guest_path(guest)?;
+    (!host.is_empty()).then(|| (guest, host.to_owned()))
+}
+
+/// `BASE:PATH[:MODE]`, where the path itself may contain colons.
+fn parse_file(raw: &str) -> Option<(u32, String, FileMode)> {
+    let (base, rest) = raw.split_once(':')?;
+    let (path, mode) = parse_mode(rest);
+    if path.is_empty() {
+        return None;
+    }
+    Some((parse_address(base)?, path.to_owned(), mode))
+}
+
+fn parse_cache(raw: &str) -> Option<CacheConfig> {
+    let mut parts = raw.split(':');
+    let mut config = CacheConfig {
+        size: parse_address(parts.next()?)?,
+        ways: parse_address(parts.next()?)?,
+        line: parse_address(parts.next()?)?,
+        ..Default::default()
+    };
+    for part in parts {
+        match part {
+            "lru" => config.replacement = Replacement::Lru,
+            "fifo" => config.replacement = Replacement::Fifo,
+            "random" => config.replacement = Replacement::Random,
+            "wb" => config.write = WritePolicy::WriteBack,
+            "wt" => config.write = WritePolicy::WriteThrough,
+            _ => return None,
+        }
+    }
+    config.is_valid().then_some(config)
+}
+
+pub fn load_program() -> Result<Program, RuntimeError> {
+    load_program_from(&Options::from_env()?.path)
 }
 
 fn load_program_from(path: &str) -> Result<Program, RuntimeError> {
     let mut prg: Program = Default::default();
-    let src = fs::read(path)?.into_iter().enumerate();
-    for (i, x) in src {
-        prg.write(i as u32, x);
-    }
+    prg.write_slice(0, &fs::read(path)?);
     Ok(prg)
 }
 
 pub fn load_execution_set(program: &mut Program, vm: &mut VM) -> Result<(), RuntimeError> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("USAGE:");
-        eprintln!("\t{}: <PROGRAM_BINFILE>", args[0]);
-        Err(RuntimeError::Usage)
-    } else {
-        load_execution_set_from(&args[1], program, vm)
-    }
+    load_execution_set_from(&Options::from_env()?.path, program, vm)
 }
 
-fn load_execution_set_from(
+pub fn load_execution_set_from(
     path: &str,
     program: &mut Program,
     vm: &mut VM,
 ) -> Result<(), RuntimeError> {
     let executable = std::fs::read(path)?;
     let elf = ELF::parse(&executable)?;
-    if let Some(data) = elf.get(SectionName::Rodata) {
-        for (i, &x) in data.get(&executable).iter().enumerate() {
-            vm.ram.set_word_at(i as u32 * 4, x)?;
-        }
+    let segments: Vec<&Segment> = elf.segments().iter().filter(|s| s.is_load()).collect();
+    if let Some(text) = elf.get(SectionName::Text) {
+        program.set_base(text.address());
+        program.write_slice(0, text.get(&executable));
+    }
+    program.load(vm)?;
+    if elf.entry() != 0 {
+        vm.cpu.register.set(Register::PC, elf.entry());
     }
-    if let Some(data) = elf.get(SectionName::Text) {
-        for (i, &x) in data.get(&executable).iter().enumerate() {
-            program.write(i as u32, x as u8);
+
+    if !segments.is_empty() {
+        for (i, segment) in segments.iter().enumerate() {
+            let data = segment.get(&executable);
+            vm.memory.load(segment.address(), data)?;
+            let bss = segment.size().saturating_sub(data.len() as u32);
+            let start = end_of(segment.address(), data.len() as u32)?;
+            let end = end_of(start, bss)?;
+            // Page by page, so a bogus memsz fails on the first unmapped
+            // page rather than allocating all of it up front
+            let zeros = [0; PAGE_SIZE as usize];
+            for at in (start..end).step_by(PAGE_SIZE as usize) {
+                let n = (end - at).min(PAGE_SIZE);
+                vm.memory.load(at, &zeros[..n as usize])?;
+            }
+            vm.memory.protect(
+                &format!("segment{}", i),
+                segment.address(),
+                segment.size() as u64,
+                segment.permissions(),
+            );
         }
+        return Ok(());
+    }
+
+    if let Some(text) = elf.get(SectionName::Text) {
+        vm.memory.protect(
+            ".text",
+            text.address(),
+            text.size() as u64,
+            text.permissions(),
+        );
+    }
+    if let (Some(rodata), Some(address)) = (elf.get(SectionName::Rodata), rodata_address(&elf)?) {
+        vm.memory.load(address, rodata.get(&executable))?;
+        vm.memory.protect(
+            ".rodata",
+            address,
+            rodata.size() as u64,
+            rodata.permissions(),
+        );
+    }
+    Ok(())
+}
+
+/// End of `size` bytes at `address`, which a loadable ELF keeps below 4 GiB.
+fn end_of(address: u32, size: u32) -> Result<u32, RuntimeError> {
+    address.checked_add(size).ok_or(RuntimeError::Load)
+}
+
+/// Where .rodata gets loaded, if the ELF has one.
+fn rodata_address(elf: &ELF) -> Result<Option<u32>, RuntimeError> {
+    let Some(rodata) = elf.get(SectionName::Rodata) else {
+        return Ok(None);
+    };
+    let address = rodata.address();
+    let Some(text) = elf.get(SectionName::Text) else {
+        return Ok(Some(address));
+    };
+    // Unlinked objects have every section at 0: keep them clear of the code.
+    let text_end = end_of(text.address(), text.size())?;
+    if address < text_end && end_of(address, rodata.size())? > text.address() {
+        let align = rodata.align().max(1);
+        let moved = text_end.div_ceil(align).checked_mul(align);
+        return moved.map(Some).ok_or(RuntimeError::Load);
     }
+    Ok(Some(address))
+}
+
+/// Puts the guest's arguments, environment and auxiliary vector on its
+/// stack, with the program path as `argv[0]`.
+pub fn push_initial_stack_from(
+    path: &str,
+    args: &[String],
+    env: &[String],
+    vm: &mut VM,
+) -> Result<(), RuntimeError> {
+    let executable = std::fs::read(path)?;
+    let elf = ELF::parse(&executable)?;
+    let image = Image {
+        entry: elf.entry(),
+        phdr: elf.program_headers(),
+        phent: elf.phent() as u32,
+        phnum: elf.phnum() as u32,
+    };
+    let mut argv = vec![path.to_owned()];
+    argv.extend_from_slice(args);
+    vm.push_initial_stack(&argv, env, &image)?;
     Ok(())
 }
+
+/// Address right past the highest byte the ELF loads, including BSS.
+pub fn image_end_from(path: &str) -> Result<u32, RuntimeError> {
+    let executable = std::fs::read(path)?;
+    let elf = ELF::parse(&executable)?;
+    let segments: Vec<&Segment> = elf.segments().iter().filter(|s| s.is_load()).collect();
+    if !segments.is_empty() {
+        return segments
+            .iter()
+            .try_fold(0, |end, s| Ok(end.max(end_of(s.address(), s.size())?)));
+    }
+    // Placed the way load_execution_set_from places them
+    let text = elf.get(SectionName::Text);
+    let text_end = text.map(|t| end_of(t.address(), t.size())).transpose()?;
+    let rodata_end = match (elf.get(SectionName::Rodata), rodata_address(&elf)?) {
+        (Some(rodata), Some(address)) => Some(end_of(address, rodata.size())?),
+        _ => None,
+    };
+    Ok(text_end.max(rodata_end).unwrap_or(0))
+}
+
+/// Symbol table of the ELF at `path`, for naming addresses in reports.
+pub fn load_symbols_from(path: &str) -> Result<Symbols, RuntimeError> {
+    let executable = std::fs::read(path)?;
+    Ok(ELF::parse(&executable)?.symbols(&executable))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use brrrt_core::memory::{Access, AccessKind, Permissions};
+
+    fn args(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_numbers() {
+        assert_eq!(parse_number("1312"), Some(1312));
+        assert_eq!(parse_number("0x8000_0000"), Some(0x8000_0000));
+        assert_eq!(parse_number("64K"), Some(64 * 1024));
+        assert_eq!(parse_number("4G"), Some(1 << 32));
+        assert_eq!(parse_address("4G"), None);
+        assert_eq!(parse_number("wat"), None);
+    }
+
+    #[test]
+    fn parse_default_memory() {
+        let options = Options::parse(&args(&["brrrt", "prg.out"])).expect("valid options");
+        assert_eq!(options.path, "prg.out");
+        assert_eq!(options.memory.stack_top(), Some(DEFAULT_MEMORY_POOL_SIZE));
+        assert!(!options.shadow);
+    }
+
+    #[test]
+    fn parse_memory_regions() {
+        let options = Options::parse(&args(&[
+            "brrrt",
+            "--rom",
+            "0:4K",
+            "--ram",
+            "0x80000000:1M",
+            "--shadow",
+            "prg.out",
+        ]))
+        .expect("valid options");
+        assert!(options.shadow);
+        assert_eq!(options.memory.regions().len(), 2);
+        assert_eq!(options.memory.stack_top(), Some(0x8010_0000));
+    }
+
+    #[test]
+    fn parse_sparse_region() {
+        let options = Options::parse(&args(&["brrrt", "--ram", "0:4G:sparse", "prg.out"]))
+            .expect("valid options");
+        assert_eq!(options.memory.regions()[0].backend, Backend::Sparse);
+        assert_eq!(options.memory.regions()[0].size, 1 << 32);
+        assert!(Options::parse(&args(&["brrrt", "--ram", "0:4G:wat", "prg.out"])).is_err());
+    }
+
+    #[test]
+    fn parse_stack_region() {
+        let options = Options::parse(&args(&["brrrt", "--stack", "0x100:0x300", "prg.out"]))
+            .expect("valid options");
+        assert_eq!(
+            options.stack(0),
+            Some(Stack {
+                base: 0x100,
+                size: 0x300,
+                guard: PAGE_SIZE,
+            })
+        );
+        let options = Options::parse(&args(&["brrrt", "--stack", "0:1K:0", "prg.out"]))
+            .expect("valid options");
+        assert_eq!(options.stack(0).map(|s| s.guard), Some(0));
+        assert!(Options::parse(&args(&["brrrt", "--stack", "0xffffff00:1K", "prg.out"])).is_err());
+    }
+
+    #[test]
+    fn default_stack_stays_above_image() {
+        let options = Options::parse(&args(&[
+            "brrrt",
+            "--ram",
+            "0:4K",
+            "--ram",
+            "0xffff0000:64K",
+            "prg.out",
+        ]))
+        .expect("valid options");
+        let stack = options.stack(0xffff_1234).expect("has RAM");
+        assert_eq!(stack.base, 0xffff_2240);
+        assert_eq!(stack.guard, PAGE_SIZE);
+        assert_eq!(stack.top(), 0xffff_fff0);
+        // Image in another region
+        assert_eq!(options.stack(0x100).map(|s| s.base), Some(0xffff_1000));
+
+        let options =
+            Options::parse(&args(&["brrrt", "--ram", "0:64M", "prg.out"])).expect("valid options");
+        let stack = options.stack(0x2000).expect("has RAM");
+        assert_eq!(stack.size, DEFAULT_STACK_SIZE);
+
+        // Tiny RAM still gets a guard
+        let options = Options::parse(&args(&["brrrt", "prg.out"])).expect("valid options");
+        let stack = options.stack(0x100).expect("has RAM");
+        assert_eq!((stack.base, stack.guard), (0x1c0, 0xc0));
+
+        // Nothing fits between an image and an unaligned top
+        let options =
+            Options::parse(&args(&["brrrt", "--ram", "0:1000", "prg.out"])).expect("valid options");
+        assert!(options.stack(999).is_none());
+    }
+
+    #[test]
+    fn parse_caches() {
+        let options = Options::parse(&args(&[
+            "brrrt",
+            "--icache",
+            "8K:2:32",
+            "--dcache",
+            "16K:4:64:fifo:wt",
+            "--miss-penalty",
+            "20",
+            "--uart",
+            "0x10000000",
+            "--finisher",
+            "0x100000",
+            "prg.out",
+        ]))
+        .expect("valid options");
+        let icache = options.icache.expect("icache set");
+        assert_eq!((icache.size, icache.ways, icache.line), (8192, 2, 32));
+        assert_eq!(icache.miss_penalty, 20);
+        assert_eq!(options.uart, Some(0x1000_0000));
+        assert_eq!(options.finisher, Some(0x10_0000));
+        let dcache = options.dcache.expect("dcache set");
+        assert_eq!(dcache.replacement, Replacement::Fifo);
+        assert_eq!(dcache.write, WritePolicy::WriteThrough);
+        assert!(Options::parse(&args(&["brrrt", "--icache", "8K:3:32", "prg.out"])).is_err());
+        assert!(Options::parse(&args(&["brrrt", "--icache", "8K:2:32:plru", "prg.out"])).is_err());
+    }
+
+    #[test]
+    fn parse_mapped_files() {
+        let options = Options::parse(&args(&[
+            "brrrt",
+            "--map-file",
+            "0x20000000:table.bin",
+            "--map-file",
+            "0x30000000:C:/vectors.bin:sync",
+            "--virtio-blk",
+            "0x10001000:disk.img:rw",
+            "--spi-flash",
+            "0x10014000:flash.bin:sync",
+            "prg.out",
+        ]))
+        .expect("valid options");
+        assert_eq!(
+            options.files,
+            vec![
+                (0x2000_0000, "table.bin".to_owned(), FileMode::ReadOnly),
+                (0x3000_0000, "C:/vectors.bin".to_owned(), FileMode::Sync),
+            ]
+        );
+        assert_eq!(
+            options.disks,
+            vec![(0x1000_1000, "disk.img".to_owned(), FileMode::Private)]
+        );
+        assert_eq!(
+            options.spi_flash,
+            vec![(0x1001_4000, "flash.bin".to_owned(), FileMode::Sync)]
+        );
+        assert!(Options::parse(&args(&["brrrt", "--map-file", "0x100", "prg.out"])).is_err());
+    }
+
+    #[test]
+    fn parse_framebuffer_options() {
+        let options = Options::parse(&args(&[
+            "brrrt",
+            "--framebuffer",
+            "0x50000000:320x200:rgb565",
+            "--frames",
+            "out-%d.ppm",
+            "prg.out",
+        ]))
+        .expect("valid options");
+        let (base, config) = options.framebuffer.expect("framebuffer set");
+        assert_eq!(base, 0x5000_0000);
+        assert_eq!((config.width, config.height), (320, 200));
+        assert_eq!(config.format, PixelFormat::Rgb565);
+        assert_eq!(options.frames.as_deref(), Some("out-%d.ppm"));
+        for bad in [
+            "0x50000000:320",
+            "0x50000000:0x200",
+            "0x50000000:8x8:rgb332",
+            "0:1073741824x1",
+            "0:4294967295x4294967295",
+        ] {
+            assert!(Options::parse(&args(&["brrrt", "--framebuffer", bad, "prg.out"])).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_rtc_clocks() {
+        let rtc = |raw: &str| {
+            Options::parse(&args(&["brrrt", "--rtc", raw, "prg.out"])).map(|o| o.rtc.unwrap())
+        };
+        assert_eq!(rtc("0x101000").unwrap(), (0x10_1000, Clock::default()));
+        assert_eq!(rtc("0x101000:host").unwrap(), (0x10_1000, Clock::Host));
+        assert_eq!(
+            rtc("0x101000:virtual:1000").unwrap().1,
+            Clock::Virtual {
+                start: 0,
+                ns_per_tick: 1000
+            }
+        );
+        assert!(rtc("0x101000:host:5").is_err());
+        assert!(rtc("0x101000:tai").is_err());
+    }
+
+    #[test]
+    fn parse_linux() {
+        let options =
+            Options::parse(&args(&["brrrt", "--linux", "prg.out"])).expect("valid options");
+        assert!(options.linux);
+        let ram = &options.memory.regions()[0];
+        assert_eq!((ram.base, ram.size), (0, LINUX_RAM_SIZE));
+        assert!(Options::parse(&args(&["brrrt", "--linux", "--sbi", "prg.out"])).is_err());
+
+        let options = Options::parse(&args(&[
+            "brrrt",
+            "--linux",
+            "--root",
+            "sysroot:rw",
+            "--guest-file",
+            "etc/../etc/motd=motd.txt",
+            "prg.out",
+        ]))
+        .expect("valid options");
+        assert_eq!(
+            options.root,
+            Some(("sysroot".to_owned(), FileMode::Private))
+        );
+        assert_eq!(
+            options.guest_files,
+            vec![("/etc/motd".to_owned(), "motd.txt".to_owned())]
+        );
+        for bad in [
+            &["--linux", "--guest-file", "/../motd=motd.txt"][..],
+            &["--linux", "--guest-file", "/motd"],
+            &["--root", "sysroot"],
+        ] {
+            let mut argv = vec!["brrrt"];
+            argv.extend_from_slice(bad);
+            argv.push("prg.out");
+            assert!(Options::parse(&args(&argv)).is_err());
+        }
+        assert!(
+            Options::parse(&args(&["brrrt", "--linux", "--machine", "virt", "prg.out"])).is_err()
+        );
+    }
+
+    #[test]
+    fn errors_have_distinct_exit_codes() {
+        let codes: Vec<i32> = [
+            RuntimeError::Usage,
+            RuntimeError::Load,
+            RuntimeError::Read,
+            RuntimeError::Execution,
+            RuntimeError::AccessFault(Fault {
+                pc: 0,
+                address: 0,
+                access: Access::Word,
+                kind: AccessKind::Write,
+                region: "rom".to_owned(),
+                permissions: Permissions::new(true, false, false),
+            }),
+        ]
+        .iter()
+        .map(|e| e.exit_code())
+        .collect();
+        for (i, code) in codes.iter().enumerate() {
+            assert!(*code > 1 && *code < 126);
+            assert!(!codes[i + 1..].contains(code));
+        }
+    }
+
+    #[test]
+    fn parse_guest_arguments() {
+        let options = Options::parse(&args(&[
+            "brrrt", "--env", "HOME=/", "prg.out", "--", "-v", "--ram", "input",
+        ]))
+        .expect("valid options");
+        assert_eq!(options.path, "prg.out");
+        assert_eq!(options.args, args(&["-v", "--ram", "input"]));
+        assert_eq!(options.env, args(&["HOME=/"]));
+        assert!(Options::parse(&args(&["brrrt", "--env", "HOME", "prg.out"])).is_err());
+    }
+
+    #[test]
+    fn parse_virt_machine() {
+        let options = Options::parse(&args(&[
+            "brrrt",
+            "--machine",
+            "virt",
+            "--drive",
+            "rootfs.img:rw",
+            "--bootargs",
+            "console=ttyS0",
+            "--rtc",
+            "0x101000:host",
+            "--sbi",
+            "prg.out",
+        ]))
+        .expect("valid options");
+        assert_eq!(options.machine, Some(Machine::Virt));
+        assert_eq!(
+            options.drives,
+            vec![("rootfs.img".to_owned(), FileMode::Private)]
+        );
+        assert_eq!(options.bootargs.as_deref(), Some("console=ttyS0"));
+        assert!(options.sbi);
+        assert_eq!(options.memory.regions()[0].base, 0x8000_0000);
+        let stack = options.stack(0x8020_0000).expect("default stack");
+        assert_eq!(stack.top(), 0x87e0_0000);
+
+        for conflict in [
+            &["--uart", "0x10000000"][..],
+            &["--rtc", "0x200000"],
+            &["--virtio-blk", "0x10001000:disk.img"],
+        ] {
+            let mut argv = vec!["brrrt", "--machine", "virt"];
+            argv.extend_from_slice(conflict);
+            argv.push("prg.out");
+            assert!(Options::parse(&args(&argv)).is_err());
+        }
+        assert!(Options::parse(&args(&["brrrt", "--drive", "disk.img", "prg.out"])).is_err());
+        assert!(Options::parse(&args(&["brrrt", "--machine", "sifive_u", "prg.out"])).is_err());
+    }
+
+    #[test]
+    fn parse_stats() {
+        let options =
+            Options::parse(&args(&["brrrt", "--stats", "line", "prg.out"])).expect("valid options");
+        assert_eq!(options.stats, Some(64));
+        assert_eq!(options.heatmap, None);
+        assert_eq!(options.uart, None);
+        let options = Options::parse(&args(&["brrrt", "--heatmap", "heat.csv", "prg.out"]))
+            .expect("valid options");
+        assert_eq!(options.stats, Some(PAGE_SIZE));
+        assert_eq!(options.heatmap.as_deref(), Some("heat.csv"));
+        assert!(Options::parse(&args(&["brrrt", "--stats", "48", "prg.out"])).is_err());
+    }
+
+    #[test]
+    fn parse_invalid_region() {
+        assert!(Options::parse(&args(&["brrrt", "--ram", "0x80000000", "prg.out"])).is_err());
+        assert!(Options::parse(&args(&["brrrt", "--ram", "0:16"])).is_err());
+    }
+}
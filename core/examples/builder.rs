@@ -29,6 +29,7 @@ fn from_builder() -> Vec<u32> {
 fn main() -> Result<(), String> {
     let mut vm: VM = Default::default();
     let program = Program::from_asm(&from_builder());
+    program.load(&mut vm)?;
 
     #[cfg(feature = "trace")]
     {
@@ -41,7 +42,7 @@ fn main() -> Result<(), String> {
     eprintln!("X01: {} (expected 13)", vm.cpu.register.get(Register::X1));
     eprintln!("X02: {} (expected 25)", vm.cpu.register.get(Register::X2));
     eprintln!("X16: {} (expected 0)", vm.cpu.register.get(Register::X16));
-    eprintln!("M@0: {} (expected 25)", vm.ram.byte_at(0).unwrap());
+    eprintln!("M@0: {} (expected 25)", vm.memory.byte_at(0).unwrap());
 
     Ok(())
 }
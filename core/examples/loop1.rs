@@ -14,6 +14,7 @@ fn main() -> Result<(), String> {
     let program = Program::from_asm(&[
         0x00c00093, 0x00110113, 0xfe209ee3, 0x00108093, 0x00208133, 0x00282023,
     ]);
+    program.load(&mut vm)?;
 
     #[cfg(feature = "trace")]
     {
@@ -26,7 +27,7 @@ fn main() -> Result<(), String> {
     eprintln!("X01: {} (expected 13)", vm.cpu.register.get(Register::X1));
     eprintln!("X02: {} (expected 25)", vm.cpu.register.get(Register::X2));
     eprintln!("X16: {} (expected 0)", vm.cpu.register.get(Register::X16));
-    eprintln!("M@0: {} (expected 25)", vm.ram.byte_at(0).unwrap());
+    eprintln!("M@0: {} (expected 25)", vm.memory.byte_at(0).unwrap());
 
     Ok(())
 }
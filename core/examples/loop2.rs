@@ -17,6 +17,7 @@ fn main() -> Result<(), String> {
         0x00300093, 0x00508093, 0x00408093, 0x00110113, 0xfe209ee3, 0x00108093, 0x00208133,
         0x00282023,
     ]);
+    program.load(&mut vm)?;
 
     #[cfg(feature = "trace")]
     {
@@ -29,7 +30,7 @@ fn main() -> Result<(), String> {
     eprintln!("X01: {} (expected 13)", vm.cpu.register.get(Register::X1));
     eprintln!("X02: {} (expected 25)", vm.cpu.register.get(Register::X2));
     eprintln!("X16: {} (expected 0)", vm.cpu.register.get(Register::X16));
-    eprintln!("M@0: {} (expected 25)", vm.ram.byte_at(0).unwrap());
+    eprintln!("M@0: {} (expected 25)", vm.memory.byte_at(0).unwrap());
 
     Ok(())
 }
@@ -8,6 +8,7 @@ fn main() -> Result<(), String> {
         0x00c08113, // addi x2, x1, 12
         0x00282023, // sw x2, 0(x16)
     ]);
+    program.load(&mut vm)?;
 
     #[cfg(feature = "trace")]
     {
@@ -20,7 +21,7 @@ fn main() -> Result<(), String> {
     eprintln!("X01: {} (expected 13)", vm.cpu.register.get(Register::X1));
     eprintln!("X02: {} (expected 25)", vm.cpu.register.get(Register::X2));
     eprintln!("X16: {} (expected 0)", vm.cpu.register.get(Register::X16));
-    eprintln!("M@0: {} (expected 25)", vm.ram.byte_at(0).unwrap());
+    eprintln!("M@0: {} (expected 25)", vm.memory.byte_at(0).unwrap());
 
     Ok(())
 }
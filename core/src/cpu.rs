@@ -1,6 +1,4 @@
-use crate::memory::DEFAULT_MEMORY_POOL_SIZE;
-
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct CPU {
     pub register: Registers,
 }
@@ -14,14 +12,14 @@ impl CPU {
     }
 
     /// Initialize stack pointer
-    pub fn initialize(&mut self) {
-        self.register.set(Register::X2, DEFAULT_MEMORY_POOL_SIZE);
+    pub fn initialize(&mut self, stack_top: u32) {
+        self.register.set(Register::X2, stack_top);
     }
 }
 
 pub const REGISTER_INCREMENT: u32 = 4;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Registers {
     data: [u32; 33],
 }
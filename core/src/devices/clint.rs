@@ -0,0 +1,135 @@
+use super::IrqLine;
+use crate::memory::{Access, Device, MemoryError};
+
+const MSIP: u32 = 0x0000;
+const MTIMECMP: u32 = 0x4000;
+const MTIME: u32 = 0xbff8;
+
+/// Register window of a single-hart CLINT.
+pub const CLINT_SIZE: u64 = 0x10000;
+
+/// Core-local interruptor for one hart.
+///
+/// `mtime` advances by one on every tick. The software and timer interrupt
+/// lines are only driven, not delivered: the core has no trap support.
+#[derive(Debug, Default)]
+pub struct Clint {
+    mtime: u64,
+    mtimecmp: u64,
+    msip: bool,
+    soft_irq: Option<IrqLine>,
+    timer_irq: Option<IrqLine>,
+}
+
+impl Clint {
+    pub fn new() -> Self {
+        Self {
+            mtimecmp: u64::MAX,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_soft_irq(mut self, irq: IrqLine) -> Self {
+        self.soft_irq = Some(irq);
+        self
+    }
+
+    pub fn with_timer_irq(mut self, irq: IrqLine) -> Self {
+        self.timer_irq = Some(irq);
+        self
+    }
+
+    fn update_irqs(&self) {
+        if let Some(irq) = &self.soft_irq {
+            irq.set(self.msip);
+        }
+        if let Some(irq) = &self.timer_irq {
+            irq.set(self.mtime >= self.mtimecmp);
+        }
+    }
+
+    fn half(value: u64, offset: u32) -> u32 {
+        (value >> (offset % 8 * 8)) as u32
+    }
+
+    fn set_half(value: &mut u64, offset: u32, half: u32) {
+        let shift = offset % 8 * 8;
+        *value = (*value & !(0xffff_ffff << shift)) | (half as u64) << shift;
+    }
+}
+
+impl Device for Clint {
+    fn read(&mut self, offset: u32, access: Access) -> Result<u32, MemoryError> {
+        if access != Access::Word {
+            return Err(MemoryError::LoadAddress(access));
+        }
+        Ok(match offset {
+            MSIP => self.msip as u32,
+            MTIMECMP | 0x4004 => Self::half(self.mtimecmp, offset),
+            MTIME | 0xbffc => Self::half(self.mtime, offset),
+            _ => 0,
+        })
+    }
+
+    fn write(&mut self, offset: u32, access: Access, value: u32) -> Result<(), MemoryError> {
+        if access != Access::Word {
+            return Err(MemoryError::StoreAddress(access));
+        }
+        match offset {
+            MSIP => self.msip = value & 1 != 0,
+            MTIMECMP | 0x4004 => Self::set_half(&mut self.mtimecmp, offset, value),
+            MTIME | 0xbffc => Self::set_half(&mut self.mtime, offset, value),
+            _ => {}
+        }
+        self.update_irqs();
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.mtime = self.mtime.wrapping_add(1);
+        self.update_irqs();
+    }
+
+    fn reset(&mut self) {
+        self.mtime = 0;
+        self.mtimecmp = u64::MAX;
+        self.msip = false;
+        self.update_irqs();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod clint {
+    use super::*;
+    use crate::{testing, Bus};
+
+    const BASE: u32 = 0x0200_0000;
+
+    #[test]
+    fn timer_fires_at_compare_value() {
+        let (soft, timer) = (IrqLine::default(), IrqLine::default());
+        let mut m = testing::attach(
+            "clint",
+            BASE,
+            CLINT_SIZE,
+            Clint::new()
+                .with_soft_irq(soft.clone())
+                .with_timer_irq(timer.clone()),
+        );
+
+        m.write(BASE + MTIMECMP + 4, Access::Word, 0).unwrap();
+        m.write(BASE + MTIMECMP, Access::Word, 2).unwrap();
+        m.tick();
+        assert!(!timer.is_raised());
+        m.tick();
+        assert!(timer.is_raised());
+        assert_eq!(m.read(BASE + MTIME, Access::Word).unwrap(), 2);
+
+        m.write(BASE + MTIME + 4, Access::Word, 1).unwrap();
+        assert_eq!(m.read(BASE + MTIME + 4, Access::Word).unwrap(), 1);
+
+        m.write(BASE + MSIP, Access::Word, 1).unwrap();
+        assert!(soft.is_raised());
+    }
+}
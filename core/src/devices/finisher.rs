@@ -0,0 +1,91 @@
+use crate::{
+    memory::{Access, Device, Memory, MemoryError},
+    Halt,
+};
+
+const FAIL: u32 = 0x3333;
+const PASS: u32 = 0x5555;
+const RESET: u32 = 0x7777;
+
+/// Register window of a SiFive test finisher.
+pub const FINISHER_SIZE: u64 = 0x1000;
+
+/// SiFive-test-compatible finisher.
+///
+/// Writing 0x5555 powers off, 0x7777 reboots and `(code << 16) | 0x3333`
+/// fails with `code`; the VM stops with the matching [`Halt`]. Other values
+/// are ignored.
+#[derive(Debug, Default)]
+pub struct TestFinisher {
+    request: Option<Halt>,
+}
+
+impl TestFinisher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Device for TestFinisher {
+    fn read(&mut self, _offset: u32, _access: Access) -> Result<u32, MemoryError> {
+        Ok(0)
+    }
+
+    fn write(&mut self, offset: u32, access: Access, value: u32) -> Result<(), MemoryError> {
+        if offset != 0 || access != Access::Word {
+            return Ok(());
+        }
+        self.request = match value & 0xffff {
+            FAIL => Some(Halt::Fail((value >> 16) as u16)),
+            PASS => Some(Halt::PowerOff),
+            RESET => Some(Halt::Reboot),
+            _ => None,
+        };
+        Ok(())
+    }
+
+    fn pending(&self) -> bool {
+        self.request.is_some()
+    }
+
+    fn service(&mut self, memory: &mut Memory) {
+        if let Some(reason) = self.request.take() {
+            memory.request_halt(reason);
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod finisher {
+    use super::*;
+    use crate::{testing, Bus};
+
+    const BASE: u32 = 0x0010_0000;
+
+    fn attach() -> Memory {
+        testing::attach("test", BASE, FINISHER_SIZE, TestFinisher::new())
+    }
+
+    #[test]
+    fn writes_request_halt() {
+        let mut m = attach();
+        for (value, halt) in [
+            (0x5555, Halt::PowerOff),
+            (0x7777, Halt::Reboot),
+            (0x002a_3333, Halt::Fail(42)),
+        ] {
+            m.write(BASE, Access::Word, value).unwrap();
+            assert_eq!(m.take_halt(), Some(halt));
+        }
+    }
+
+    #[test]
+    fn other_values_are_ignored() {
+        let mut m = attach();
+        m.write(BASE, Access::Word, 0x1234).unwrap();
+        m.write(BASE, Access::HalfWord, 0x5555).unwrap();
+        m.write(BASE + 4, Access::Word, 0x5555).unwrap();
+        assert_eq!(m.take_halt(), None);
+    }
+}
@@ -0,0 +1,337 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use super::SpiDevice;
+use crate::memory::FileMode;
+
+const PAGE_PROGRAM: u8 = 0x02;
+const READ: u8 = 0x03;
+const WRITE_DISABLE: u8 = 0x04;
+const READ_STATUS: u8 = 0x05;
+const WRITE_ENABLE: u8 = 0x06;
+const FAST_READ: u8 = 0x0b;
+const SECTOR_ERASE: u8 = 0x20;
+const CHIP_ERASE_ALT: u8 = 0x60;
+const ENABLE_RESET: u8 = 0x66;
+const RESET: u8 = 0x99;
+const JEDEC_ID: u8 = 0x9f;
+const RELEASE_POWER_DOWN: u8 = 0xab;
+const CHIP_ERASE: u8 = 0xc7;
+const BLOCK_ERASE: u8 = 0xd8;
+
+const STATUS_WEL: u8 = 1 << 1;
+
+const PAGE: u32 = 0x100;
+const SECTOR: u32 = 0x1000;
+const BLOCK: u32 = 0x1_0000;
+
+/// Winbond W25Q-style manufacturer and memory type.
+const WINBOND: [u8; 2] = [0xef, 0x40];
+
+/// SPI NOR flash backed by a host file.
+///
+/// Speaks the common 3-byte address command set: READ, FAST_READ, PAGE
+/// PROGRAM, 4K/64K/chip erase, WREN/WRDI, RDSR and JEDEC ID. Program and
+/// erase complete instantly, so the busy bit never shows. Programming only
+/// clears bits and erasing sets them, as on a real part. [`FileMode`] decides
+/// where changes go: nowhere, memory only, or back to the file when the chip
+/// is deselected.
+pub struct SpiFlash {
+    data: Vec<u8>,
+    path: PathBuf,
+    mode: FileMode,
+    id: [u8; 3],
+    write_enabled: bool,
+    command: Option<u8>,
+    address: u32,
+    /// Bytes clocked in after the opcode.
+    count: usize,
+    /// Range changed by the current command, written back on deselect.
+    dirty: Option<(u32, u32)>,
+    /// Last failed write-back, kept since the bus can't report it.
+    error: Option<io::ErrorKind>,
+}
+
+impl SpiFlash {
+    pub fn open(path: impl AsRef<Path>, mode: FileMode) -> io::Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+        if data.is_empty() {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        // Capacity byte is log2 of the size, rounded up
+        let capacity = (data.len() as u64).next_power_of_two().trailing_zeros() as u8;
+        Ok(Self {
+            data,
+            path: path.to_path_buf(),
+            mode,
+            id: [WINBOND[0], WINBOND[1], capacity],
+            write_enabled: false,
+            command: None,
+            address: 0,
+            count: 0,
+            dirty: None,
+            error: None,
+        })
+    }
+
+    /// Manufacturer, memory type and capacity reported by JEDEC ID.
+    pub fn with_jedec_id(mut self, id: [u8; 3]) -> Self {
+        self.id = id;
+        self
+    }
+
+    fn size(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    fn status(&self) -> u8 {
+        if self.write_enabled {
+            STATUS_WEL
+        } else {
+            0
+        }
+    }
+
+    fn read(&self, address: u32) -> u8 {
+        self.data[(address % self.size()) as usize]
+    }
+
+    fn writable(&self) -> bool {
+        self.write_enabled && self.mode != FileMode::ReadOnly
+    }
+
+    fn mark(&mut self, start: u32, end: u32) {
+        self.dirty = Some(match self.dirty {
+            Some((s, e)) => (s.min(start), e.max(end)),
+            None => (start, end),
+        });
+    }
+
+    fn program(&mut self, offset: usize, byte: u8) {
+        // Wraps within the page
+        let page = self.address & !(PAGE - 1);
+        let address = (page + (self.address + offset as u32) % PAGE) % self.size();
+        self.data[address as usize] &= byte;
+        self.mark(address, address + 1);
+    }
+
+    fn erase(&mut self, size: u32) {
+        let start = (self.address % self.size()) & !(size - 1);
+        let end = (start + size).min(self.size());
+        self.data[start as usize..end as usize].fill(0xff);
+        self.mark(start, end);
+    }
+
+    /// Runs erase commands, which take effect when the chip is deselected.
+    fn finish(&mut self, command: u8) {
+        if !self.writable() {
+            return;
+        }
+        match command {
+            SECTOR_ERASE if self.count >= 3 => self.erase(SECTOR),
+            BLOCK_ERASE if self.count >= 3 => self.erase(BLOCK),
+            CHIP_ERASE | CHIP_ERASE_ALT => {
+                self.data.fill(0xff);
+                self.mark(0, self.size());
+            }
+            _ => return,
+        }
+        self.write_enabled = false;
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        let Some((start, end)) = self.dirty.take() else {
+            return Ok(());
+        };
+        if self.mode != FileMode::Sync {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new().write(true).open(&self.path)?;
+        file.seek(SeekFrom::Start(start as u64))?;
+        file.write_all(&self.data[start as usize..end as usize])
+    }
+}
+
+impl SpiDevice for SpiFlash {
+    fn select(&mut self) {
+        self.command = None;
+        self.address = 0;
+        self.count = 0;
+    }
+
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let Some(command) = self.command else {
+            match byte {
+                WRITE_ENABLE => self.write_enabled = true,
+                WRITE_DISABLE => self.write_enabled = false,
+                RESET => self.write_enabled = false,
+                _ => {}
+            }
+            self.command = Some(byte);
+            return 0xff;
+        };
+        let index = self.count;
+        self.count += 1;
+        if index < 3
+            && matches!(
+                command,
+                READ | FAST_READ | PAGE_PROGRAM | SECTOR_ERASE | BLOCK_ERASE
+            )
+        {
+            self.address = self.address << 8 | byte as u32;
+            return 0xff;
+        }
+        match command {
+            JEDEC_ID => self.id.get(index).copied().unwrap_or(0),
+            READ_STATUS => self.status(),
+            READ => self.read(self.address.wrapping_add(index as u32 - 3)),
+            // One dummy byte after the address
+            FAST_READ if index == 3 => 0xff,
+            FAST_READ => self.read(self.address.wrapping_add(index as u32 - 4)),
+            PAGE_PROGRAM => {
+                if self.writable() {
+                    self.program(index - 3, byte);
+                }
+                0xff
+            }
+            _ => 0xff,
+        }
+    }
+
+    fn deselect(&mut self) {
+        let Some(command) = self.command.take() else {
+            return;
+        };
+        match command {
+            PAGE_PROGRAM if self.count > 3 => self.write_enabled = false,
+            // Accepted for drivers that issue them; there's no power-down
+            // or reset state to leave.
+            RELEASE_POWER_DOWN | ENABLE_RESET => {}
+            _ => self.finish(command),
+        }
+        if let Err(e) = self.sync() {
+            #[cfg(feature = "trace")]
+            eprintln!("SPI flash {}: {}", self.path.display(), e);
+            self.error = Some(e.kind());
+        }
+    }
+
+    fn last_error(&self) -> Option<io::ErrorKind> {
+        self.error
+    }
+
+    fn reset(&mut self) {
+        self.write_enabled = false;
+        self.command = None;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod flash {
+    use super::*;
+
+    fn image(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("brrrt-{}-flash-{}", std::process::id(), name));
+        let mut data = vec![0xff; 2 * BLOCK as usize];
+        data[0x1000..0x1004].copy_from_slice(b"boot");
+        fs::write(&path, data).expect("temp image");
+        path
+    }
+
+    fn command(flash: &mut SpiFlash, bytes: &[u8]) -> Vec<u8> {
+        flash.select();
+        let reply = bytes.iter().map(|&b| flash.transfer(b)).collect();
+        flash.deselect();
+        reply
+    }
+
+    #[test]
+    fn identifies_and_reads() {
+        let path = image("read");
+        let mut flash = SpiFlash::open(&path, FileMode::ReadOnly).expect("image opens");
+
+        assert_eq!(
+            command(&mut flash, &[JEDEC_ID, 0, 0, 0])[1..],
+            [0xef, 0x40, 0x11]
+        );
+        assert_eq!(
+            command(&mut flash, &[READ, 0, 0x10, 0, 0, 0, 0, 0])[4..],
+            *b"boot"
+        );
+        assert_eq!(
+            command(&mut flash, &[FAST_READ, 0, 0x10, 1, 0, 0, 0])[5..],
+            *b"oo"
+        );
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn program_and_erase_need_write_enable() {
+        let path = image("private");
+        let mut flash = SpiFlash::open(&path, FileMode::Private).expect("image opens");
+
+        command(&mut flash, &[PAGE_PROGRAM, 0, 0, 0, 0x12]);
+        assert_eq!(flash.data[0], 0xff);
+
+        command(&mut flash, &[WRITE_ENABLE]);
+        assert_eq!(command(&mut flash, &[READ_STATUS, 0])[1], STATUS_WEL);
+        // Wraps around to the start of the page
+        command(&mut flash, &[PAGE_PROGRAM, 0, 0, 0xff, 0x12, 0x34]);
+        assert_eq!(flash.data[0xff], 0x12);
+        assert_eq!(flash.data[0], 0x34);
+        assert_eq!(command(&mut flash, &[READ_STATUS, 0])[1], 0);
+
+        // Only clears bits
+        command(&mut flash, &[WRITE_ENABLE]);
+        command(&mut flash, &[PAGE_PROGRAM, 0, 0, 0, 0xf0]);
+        assert_eq!(flash.data[0], 0x30);
+
+        command(&mut flash, &[WRITE_ENABLE]);
+        command(&mut flash, &[SECTOR_ERASE, 0, 0x10, 0x80]);
+        assert!(flash.data[0x1000..0x2000].iter().all(|&b| b == 0xff));
+        assert_eq!(flash.data[0], 0x30);
+
+        command(&mut flash, &[WRITE_ENABLE]);
+        command(&mut flash, &[CHIP_ERASE]);
+        assert!(flash.data.iter().all(|&b| b == 0xff));
+
+        assert_eq!(fs::read(&path).unwrap()[0x1000..0x1004], *b"boot");
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn sync_writes_back_and_read_only_ignores_writes() {
+        let path = image("sync");
+        let mut flash = SpiFlash::open(&path, FileMode::Sync).expect("image opens");
+        command(&mut flash, &[WRITE_ENABLE]);
+        command(&mut flash, &[SECTOR_ERASE, 0, 0x10, 0]);
+        command(&mut flash, &[WRITE_ENABLE]);
+        command(&mut flash, &[PAGE_PROGRAM, 0, 0x10, 0, b'n', b'e', b'w']);
+        assert_eq!(fs::read(&path).unwrap()[0x1000..0x1004], *b"new\xff");
+
+        let mut flash = SpiFlash::open(&path, FileMode::ReadOnly).expect("image opens");
+        command(&mut flash, &[WRITE_ENABLE]);
+        command(&mut flash, &[SECTOR_ERASE, 0, 0x10, 0]);
+        assert_eq!(flash.data[0x1000..0x1003], *b"new");
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn failed_write_back_is_kept() {
+        let path = image("gone");
+        let mut flash = SpiFlash::open(&path, FileMode::Sync).expect("image opens");
+        fs::remove_file(&path).unwrap();
+
+        command(&mut flash, &[WRITE_ENABLE]);
+        assert_eq!(flash.last_error(), None);
+        command(&mut flash, &[SECTOR_ERASE, 0, 0x10, 0]);
+        command(&mut flash, &[READ_STATUS]);
+        assert_eq!(flash.last_error(), Some(io::ErrorKind::NotFound));
+    }
+}
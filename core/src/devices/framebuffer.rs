@@ -0,0 +1,289 @@
+use crate::memory::{Access, Device, MemoryError};
+
+const WIDTH: u32 = 0x00;
+const HEIGHT: u32 = 0x04;
+const FORMAT: u32 = 0x08;
+const STRIDE: u32 = 0x0c;
+const PRESENT: u32 = 0x10;
+
+/// Offset of the first pixel; registers live below it.
+pub const FB_PIXELS: u32 = 0x1000;
+
+/// Layout of a pixel in framebuffer memory, little-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PixelFormat {
+    /// One byte of luminance.
+    Gray8,
+    /// 5 bits red, 6 green, 5 blue.
+    Rgb565,
+    /// Blue in the low byte, the top byte is ignored.
+    #[default]
+    Xrgb8888,
+}
+
+impl PixelFormat {
+    pub fn bytes(&self) -> u32 {
+        match self {
+            Self::Gray8 => 1,
+            Self::Rgb565 => 2,
+            Self::Xrgb8888 => 4,
+        }
+    }
+
+    fn rgb(&self, pixel: &[u8]) -> [u8; 3] {
+        match self {
+            Self::Gray8 => [pixel[0]; 3],
+            Self::Rgb565 => {
+                let p = u16::from_le_bytes([pixel[0], pixel[1]]);
+                let (r, g, b) = ((p >> 11) as u8, (p >> 5) as u8 & 0x3f, p as u8 & 0x1f);
+                [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+            }
+            Self::Xrgb8888 => [pixel[2], pixel[1], pixel[0]],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FramebufferConfig {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+}
+
+impl Default for FramebufferConfig {
+    fn default() -> Self {
+        Self {
+            width: 320,
+            height: 240,
+            format: PixelFormat::default(),
+        }
+    }
+}
+
+impl FramebufferConfig {
+    /// Bytes per row, saturating for rows too wide to address.
+    pub fn stride(&self) -> u32 {
+        self.width.saturating_mul(self.format.bytes())
+    }
+
+    /// Register window including pixel memory, saturating like the stride.
+    pub fn size(&self) -> u64 {
+        let row = self.width as u64 * self.format.bytes() as u64;
+        row.saturating_mul(self.height as u64)
+            .saturating_add(FB_PIXELS as u64)
+    }
+}
+
+/// Presented image as 8-bit RGB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    /// Frames presented before this one.
+    pub number: u32,
+    pub rgb: Vec<u8>,
+}
+
+impl Frame {
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let at = (y * self.width + x) as usize * 3;
+        [self.rgb[at], self.rgb[at + 1], self.rgb[at + 2]]
+    }
+
+    /// Binary PPM (P6) encoding.
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        ppm.extend_from_slice(&self.rgb);
+        ppm
+    }
+}
+
+/// Linear framebuffer.
+///
+/// Registers (32-bit, read-only except PRESENT) describe the geometry; pixels
+/// start at [`FB_PIXELS`] and rows are packed. Writing PRESENT hands the
+/// current contents to the present callback, reading it gives the number of
+/// frames presented so far.
+pub struct Framebuffer {
+    config: FramebufferConfig,
+    pixels: Vec<u8>,
+    presented: u32,
+    on_present: Option<Box<dyn FnMut(Frame)>>,
+}
+
+impl Framebuffer {
+    pub fn new(config: FramebufferConfig) -> Self {
+        Self {
+            config,
+            pixels: vec![0; (config.size() - FB_PIXELS as u64) as usize],
+            presented: 0,
+            on_present: None,
+        }
+    }
+
+    pub fn on_present(mut self, f: impl FnMut(Frame) + 'static) -> Self {
+        self.on_present = Some(Box::new(f));
+        self
+    }
+
+    /// Current contents, whether presented or not.
+    pub fn frame(&self) -> Frame {
+        let format = self.config.format;
+        Frame {
+            width: self.config.width,
+            height: self.config.height,
+            number: self.presented,
+            rgb: self
+                .pixels
+                .chunks(format.bytes() as usize)
+                .flat_map(|p| format.rgb(p))
+                .collect(),
+        }
+    }
+
+    fn present(&mut self) {
+        let frame = self.frame();
+        self.presented += 1;
+        if let Some(f) = &mut self.on_present {
+            f(frame);
+        }
+    }
+}
+
+impl Device for Framebuffer {
+    fn read(&mut self, offset: u32, access: Access) -> Result<u32, MemoryError> {
+        if offset >= FB_PIXELS {
+            let at = (offset - FB_PIXELS) as usize;
+            let n = access.size() as usize;
+            // The window it's attached with may be larger than the pixels
+            let pixels = self
+                .pixels
+                .get(at..at + n)
+                .ok_or(MemoryError::LoadAddress(access))?;
+            let mut value = [0; 4];
+            value[..n].copy_from_slice(pixels);
+            return Ok(u32::from_le_bytes(value));
+        }
+        if access != Access::Word {
+            return Err(MemoryError::LoadAddress(access));
+        }
+        Ok(match offset {
+            WIDTH => self.config.width,
+            HEIGHT => self.config.height,
+            FORMAT => self.config.format as u32,
+            STRIDE => self.config.stride(),
+            PRESENT => self.presented,
+            _ => 0,
+        })
+    }
+
+    fn write(&mut self, offset: u32, access: Access, value: u32) -> Result<(), MemoryError> {
+        if offset >= FB_PIXELS {
+            let at = (offset - FB_PIXELS) as usize;
+            let n = access.size() as usize;
+            let pixels = self
+                .pixels
+                .get_mut(at..at + n)
+                .ok_or(MemoryError::StoreAddress(access))?;
+            pixels.copy_from_slice(&value.to_le_bytes()[..n]);
+            return Ok(());
+        }
+        if access != Access::Word {
+            return Err(MemoryError::StoreAddress(access));
+        }
+        if offset == PRESENT {
+            self.present();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod framebuffer {
+    use super::*;
+    use crate::{testing, Bus, Memory};
+    use std::{cell::RefCell, rc::Rc};
+
+    const BASE: u32 = 0x5000_0000;
+
+    fn attach(config: FramebufferConfig) -> (Memory, Rc<RefCell<Vec<Frame>>>) {
+        let frames = Rc::new(RefCell::new(Vec::new()));
+        let sink = frames.clone();
+        let fb = Framebuffer::new(config).on_present(move |f| sink.borrow_mut().push(f));
+        (testing::attach("fb", BASE, config.size(), fb), frames)
+    }
+
+    #[test]
+    fn registers_describe_geometry() {
+        let config = FramebufferConfig {
+            width: 4,
+            height: 3,
+            format: PixelFormat::Rgb565,
+        };
+        let (mut m, _) = attach(config);
+
+        assert_eq!(m.read(BASE + WIDTH, Access::Word).unwrap(), 4);
+        assert_eq!(m.read(BASE + HEIGHT, Access::Word).unwrap(), 3);
+        assert_eq!(m.read(BASE + STRIDE, Access::Word).unwrap(), 8);
+        assert_eq!(m.read(BASE + FORMAT, Access::Word).unwrap(), 1);
+        assert!(m.read(BASE + FB_PIXELS + 24, Access::Byte).is_err());
+    }
+
+    #[test]
+    fn present_converts_pixels() {
+        let config = FramebufferConfig {
+            width: 2,
+            height: 2,
+            format: PixelFormat::Xrgb8888,
+        };
+        let (mut m, frames) = attach(config);
+
+        m.write(BASE + FB_PIXELS, Access::Word, 0x00ff_8000)
+            .unwrap();
+        m.write(BASE + FB_PIXELS + 12, Access::Byte, 0x7f).unwrap();
+        m.write(BASE + PRESENT, Access::Word, 1).unwrap();
+        m.write(BASE + FB_PIXELS, Access::Word, 0).unwrap();
+        m.write(BASE + PRESENT, Access::Word, 1).unwrap();
+
+        let frames = frames.borrow();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].pixel(0, 0), [0xff, 0x80, 0]);
+        assert_eq!(frames[0].pixel(1, 1), [0, 0, 0x7f]);
+        assert_eq!(frames[1].pixel(0, 0), [0, 0, 0]);
+        assert_eq!(frames[1].number, 1);
+        assert_eq!(m.read(BASE + PRESENT, Access::Word).unwrap(), 2);
+    }
+
+    #[test]
+    fn oversized_window_ends_at_the_pixels() {
+        let config = FramebufferConfig {
+            width: 2,
+            height: 1,
+            format: PixelFormat::Gray8,
+        };
+        let mut m = testing::attach("fb", BASE, config.size() + 0x1000, Framebuffer::new(config));
+
+        assert!(m.write(BASE + FB_PIXELS + 1, Access::Byte, 1).is_ok());
+        assert!(m.write(BASE + FB_PIXELS, Access::Word, 1).is_err());
+        assert!(m.read(BASE + FB_PIXELS + 2, Access::Byte).is_err());
+    }
+
+    #[test]
+    fn rgb565_expands_to_full_range() {
+        assert_eq!(PixelFormat::Rgb565.rgb(&[0xff, 0xff]), [0xff; 3]);
+        assert_eq!(PixelFormat::Rgb565.rgb(&[0x00, 0xf8]), [0xff, 0, 0]);
+        assert_eq!(PixelFormat::Gray8.rgb(&[0x42]), [0x42; 3]);
+    }
+
+    #[test]
+    fn ppm_encoding() {
+        let frame = Framebuffer::new(FramebufferConfig {
+            width: 1,
+            height: 1,
+            format: PixelFormat::Gray8,
+        })
+        .frame();
+        assert_eq!(frame.to_ppm(), b"P6\n1 1\n255\n\0\0\0");
+    }
+}
@@ -0,0 +1,57 @@
+mod clint;
+mod finisher;
+mod flash;
+mod framebuffer;
+mod plic;
+mod rtc;
+mod spi;
+mod uart;
+mod virtio;
+
+use std::{
+    cell::Cell,
+    io::{self, Read},
+    rc::Rc,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+pub use clint::{Clint, CLINT_SIZE};
+pub use finisher::{TestFinisher, FINISHER_SIZE};
+pub use flash::SpiFlash;
+pub use framebuffer::{Frame, Framebuffer, FramebufferConfig, PixelFormat, FB_PIXELS};
+pub use plic::{Plic, PLIC_SIZE, PLIC_SOURCES};
+pub use rtc::{Clock, GoldfishRtc, RTC_SIZE};
+pub use spi::{SpiController, SpiDevice, SPI_CHIP_SELECTS, SPI_SIZE};
+pub use uart::{Uart16550, UART_SIZE};
+pub use virtio::{VirtioBlock, VirtioEmpty, VIRTIO_SIZE};
+
+/// Level-triggered interrupt line, shared between a device and whatever
+/// consumes its interrupts.
+#[derive(Debug, Clone, Default)]
+pub struct IrqLine(Rc<Cell<bool>>);
+
+impl IrqLine {
+    pub fn set(&self, level: bool) {
+        self.0.set(level);
+    }
+
+    pub fn is_raised(&self) -> bool {
+        self.0.get()
+    }
+}
+
+/// Bytes typed on the host's stdin, read on a thread of their own so that
+/// consoles can poll for input without blocking.
+pub fn stdin_channel() -> Receiver<u8> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for byte in io::stdin().lock().bytes() {
+            let Ok(byte) = byte else { break };
+            if tx.send(byte).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
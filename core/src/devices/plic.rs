@@ -0,0 +1,274 @@
+use super::IrqLine;
+use crate::memory::{Access, Device, MemoryError};
+
+const PRIORITY: u32 = 0x00_0000;
+const PENDING: u32 = 0x00_1000;
+const ENABLE: u32 = 0x00_2000;
+const ENABLE_STRIDE: u32 = 0x80;
+const CONTEXT: u32 = 0x20_0000;
+const CONTEXT_STRIDE: u32 = 0x1000;
+
+/// Register window of a PLIC, as on QEMU's `virt` board.
+pub const PLIC_SIZE: u64 = 0x60_0000;
+
+/// Highest source number; source 0 doesn't exist.
+pub const PLIC_SOURCES: usize = 95;
+
+/// Contexts of a single hart: machine and supervisor mode.
+const CONTEXTS: usize = 2;
+
+#[derive(Debug, Default, Clone)]
+struct Context {
+    enable: [u32; 4],
+    threshold: u32,
+    irq: Option<IrqLine>,
+}
+
+/// Platform-level interrupt controller for one hart.
+///
+/// Sources are level-triggered [`IrqLine`]s sampled on every access and tick.
+/// A claimed source isn't pending again until it is completed. Context
+/// outputs are only driven, not delivered: the core has no trap support.
+#[derive(Debug)]
+pub struct Plic {
+    sources: Vec<Option<IrqLine>>,
+    priority: Vec<u32>,
+    pending: [u32; 4],
+    claimed: [u32; 4],
+    contexts: [Context; CONTEXTS],
+}
+
+impl Default for Plic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plic {
+    pub fn new() -> Self {
+        Self {
+            sources: vec![None; PLIC_SOURCES + 1],
+            priority: vec![0; PLIC_SOURCES + 1],
+            pending: [0; 4],
+            claimed: [0; 4],
+            contexts: Default::default(),
+        }
+    }
+
+    /// Wires `irq` to source `id`, which must be in `1..=PLIC_SOURCES`.
+    pub fn connect(mut self, id: usize, irq: IrqLine) -> Self {
+        assert!((1..=PLIC_SOURCES).contains(&id), "no PLIC source {}", id);
+        self.sources[id] = Some(irq);
+        self
+    }
+
+    /// Output of context 0 (machine mode) or 1 (supervisor mode).
+    pub fn with_irq(mut self, context: usize, irq: IrqLine) -> Self {
+        self.contexts[context].irq = Some(irq);
+        self
+    }
+
+    fn bit(id: usize) -> (usize, u32) {
+        (id / 32, 1 << (id % 32))
+    }
+
+    fn sample(&mut self) {
+        for (id, irq) in self.sources.iter().enumerate() {
+            let Some(irq) = irq else { continue };
+            let (word, bit) = Self::bit(id);
+            if irq.is_raised() && self.claimed[word] & bit == 0 {
+                self.pending[word] |= bit;
+            } else if !irq.is_raised() {
+                self.pending[word] &= !bit;
+            }
+        }
+        for c in 0..CONTEXTS {
+            let raised = self.best(c).is_some();
+            if let Some(irq) = &self.contexts[c].irq {
+                irq.set(raised);
+            }
+        }
+    }
+
+    /// Highest-priority pending source enabled for `context`, lowest ID first.
+    fn best(&self, context: usize) -> Option<usize> {
+        let ctx = &self.contexts[context];
+        (1..=PLIC_SOURCES)
+            .filter(|&id| {
+                let (word, bit) = Self::bit(id);
+                self.pending[word] & ctx.enable[word] & bit != 0
+                    && self.priority[id] > ctx.threshold
+            })
+            .fold(None, |best: Option<usize>, id| match best {
+                Some(b) if self.priority[b] >= self.priority[id] => Some(b),
+                _ => Some(id),
+            })
+    }
+
+    fn claim(&mut self, context: usize) -> u32 {
+        let Some(id) = self.best(context) else {
+            return 0;
+        };
+        let (word, bit) = Self::bit(id);
+        self.pending[word] &= !bit;
+        self.claimed[word] |= bit;
+        id as u32
+    }
+
+    fn complete(&mut self, id: u32) {
+        if (1..=PLIC_SOURCES as u32).contains(&id) {
+            let (word, bit) = Self::bit(id as usize);
+            self.claimed[word] &= !bit;
+        }
+    }
+
+    /// Context and register offset of an address in the context area.
+    fn context_register(offset: u32) -> Option<(usize, u32)> {
+        let context = ((offset.checked_sub(CONTEXT)?) / CONTEXT_STRIDE) as usize;
+        (context < CONTEXTS).then_some((context, offset % CONTEXT_STRIDE))
+    }
+
+    fn enable_word(offset: u32) -> Option<(usize, usize)> {
+        let relative = offset.checked_sub(ENABLE)?;
+        let context = (relative / ENABLE_STRIDE) as usize;
+        let word = (relative % ENABLE_STRIDE / 4) as usize;
+        (context < CONTEXTS && word < 4).then_some((context, word))
+    }
+}
+
+impl Device for Plic {
+    fn read(&mut self, offset: u32, access: Access) -> Result<u32, MemoryError> {
+        if access != Access::Word {
+            return Err(MemoryError::LoadAddress(access));
+        }
+        self.sample();
+        let value = match offset {
+            PRIORITY..PENDING => self
+                .priority
+                .get((offset / 4) as usize)
+                .copied()
+                .unwrap_or(0),
+            PENDING..ENABLE => self
+                .pending
+                .get(((offset - PENDING) / 4) as usize)
+                .copied()
+                .unwrap_or(0),
+            ENABLE..CONTEXT => Self::enable_word(offset)
+                .map_or(0, |(context, word)| self.contexts[context].enable[word]),
+            _ => match Self::context_register(offset) {
+                Some((context, 0)) => self.contexts[context].threshold,
+                Some((context, 4)) => self.claim(context),
+                _ => 0,
+            },
+        };
+        self.sample();
+        Ok(value)
+    }
+
+    fn write(&mut self, offset: u32, access: Access, value: u32) -> Result<(), MemoryError> {
+        if access != Access::Word {
+            return Err(MemoryError::StoreAddress(access));
+        }
+        match offset {
+            PRIORITY..PENDING => {
+                if let Some(p) = self.priority.get_mut((offset / 4) as usize) {
+                    *p = value & 7;
+                }
+                self.priority[0] = 0;
+            }
+            // Pending bits are read-only
+            PENDING..ENABLE => {}
+            ENABLE..CONTEXT => {
+                if let Some((context, word)) = Self::enable_word(offset) {
+                    // Source 0 doesn't exist
+                    let mask = if word == 0 { !1 } else { !0 };
+                    self.contexts[context].enable[word] = value & mask;
+                }
+            }
+            _ => match Self::context_register(offset) {
+                Some((context, 0)) => self.contexts[context].threshold = value & 7,
+                Some((_, 4)) => self.complete(value),
+                _ => {}
+            },
+        }
+        self.sample();
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.sample();
+    }
+
+    fn reset(&mut self) {
+        self.priority.fill(0);
+        self.pending = [0; 4];
+        self.claimed = [0; 4];
+        for context in &mut self.contexts {
+            context.enable = [0; 4];
+            context.threshold = 0;
+        }
+        self.sample();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod plic {
+    use super::*;
+    use crate::{testing, Bus, Memory};
+
+    const BASE: u32 = 0x0c00_0000;
+
+    fn attach(sources: &[(usize, &IrqLine)], output: &IrqLine) -> Memory {
+        let mut plic = Plic::new().with_irq(0, output.clone());
+        for &(id, irq) in sources {
+            plic = plic.connect(id, irq.clone());
+        }
+        testing::attach("plic", BASE, PLIC_SIZE, plic)
+    }
+
+    #[test]
+    fn claim_returns_highest_priority() {
+        let (uart, disk, out) = (IrqLine::default(), IrqLine::default(), IrqLine::default());
+        let mut m = attach(&[(10, &uart), (1, &disk)], &out);
+        m.write(BASE + 4 * 10, Access::Word, 1).unwrap();
+        m.write(BASE + 4, Access::Word, 3).unwrap();
+        m.write(BASE + ENABLE, Access::Word, 1 << 10 | 1 << 1)
+            .unwrap();
+
+        uart.set(true);
+        disk.set(true);
+        m.tick();
+        assert!(out.is_raised());
+        assert_eq!(
+            m.read(BASE + PENDING, Access::Word).unwrap(),
+            1 << 10 | 1 << 1
+        );
+
+        let claim = BASE + CONTEXT + 4;
+        assert_eq!(m.read(claim, Access::Word).unwrap(), 1);
+        assert_eq!(m.read(claim, Access::Word).unwrap(), 10);
+        assert_eq!(m.read(claim, Access::Word).unwrap(), 0);
+        assert!(!out.is_raised());
+
+        // Still asserted after completion, so pending again
+        m.write(claim, Access::Word, 10).unwrap();
+        assert!(out.is_raised());
+        assert_eq!(m.read(claim, Access::Word).unwrap(), 10);
+    }
+
+    #[test]
+    fn threshold_masks_low_priorities() {
+        let (uart, out) = (IrqLine::default(), IrqLine::default());
+        let mut m = attach(&[(10, &uart)], &out);
+        m.write(BASE + 4 * 10, Access::Word, 2).unwrap();
+        m.write(BASE + ENABLE, Access::Word, 1 << 10).unwrap();
+        m.write(BASE + CONTEXT, Access::Word, 2).unwrap();
+
+        uart.set(true);
+        m.tick();
+        assert!(!out.is_raised());
+        m.write(BASE + CONTEXT, Access::Word, 1).unwrap();
+        assert!(out.is_raised());
+    }
+}
@@ -0,0 +1,209 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::IrqLine;
+use crate::memory::{Access, Device, MemoryError};
+
+const TIME_LOW: u32 = 0x00;
+const TIME_HIGH: u32 = 0x04;
+const ALARM_LOW: u32 = 0x08;
+const ALARM_HIGH: u32 = 0x0c;
+const IRQ_ENABLED: u32 = 0x10;
+const CLEAR_ALARM: u32 = 0x14;
+const ALARM_STATUS: u32 = 0x18;
+const CLEAR_INTERRUPT: u32 = 0x1c;
+
+/// Register window of a goldfish RTC.
+pub const RTC_SIZE: u64 = 0x1000;
+
+/// Where the RTC gets its time from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clock {
+    /// Host wall-clock time.
+    Host,
+    /// `start` plus `ns_per_tick` for every retired instruction, so runs
+    /// are reproducible.
+    Virtual { start: u64, ns_per_tick: u64 },
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        // 100 MHz, counting from the epoch
+        Self::Virtual {
+            start: 0,
+            ns_per_tick: 10,
+        }
+    }
+}
+
+/// Goldfish-compatible real-time clock, counting nanoseconds since the
+/// Unix epoch.
+///
+/// Reading TIME_LOW latches the high half for the following TIME_HIGH read.
+/// The alarm is checked on every tick.
+pub struct GoldfishRtc {
+    clock: Clock,
+    ticks: u64,
+    irq: Option<IrqLine>,
+    time_high: u32,
+    alarm: u64,
+    armed: bool,
+    irq_enabled: bool,
+    interrupt: bool,
+}
+
+impl GoldfishRtc {
+    pub fn new(clock: Clock) -> Self {
+        Self {
+            clock,
+            ticks: 0,
+            irq: None,
+            time_high: 0,
+            alarm: 0,
+            armed: false,
+            irq_enabled: false,
+            interrupt: false,
+        }
+    }
+
+    pub fn with_irq(mut self, irq: IrqLine) -> Self {
+        self.irq = Some(irq);
+        self
+    }
+
+    pub fn now(&self) -> u64 {
+        match self.clock {
+            Clock::Host => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64),
+            Clock::Virtual { start, ns_per_tick } => {
+                start.wrapping_add(self.ticks.wrapping_mul(ns_per_tick))
+            }
+        }
+    }
+
+    fn check_alarm(&mut self) {
+        if self.armed && self.now() >= self.alarm {
+            self.armed = false;
+            self.interrupt = true;
+        }
+        if let Some(irq) = &self.irq {
+            irq.set(self.interrupt && self.irq_enabled);
+        }
+    }
+}
+
+impl Device for GoldfishRtc {
+    fn read(&mut self, offset: u32, access: Access) -> Result<u32, MemoryError> {
+        if access != Access::Word {
+            return Err(MemoryError::LoadAddress(access));
+        }
+        Ok(match offset {
+            TIME_LOW => {
+                let now = self.now();
+                self.time_high = (now >> 32) as u32;
+                now as u32
+            }
+            TIME_HIGH => self.time_high,
+            ALARM_LOW => self.alarm as u32,
+            ALARM_HIGH => (self.alarm >> 32) as u32,
+            IRQ_ENABLED => self.irq_enabled as u32,
+            ALARM_STATUS => self.armed as u32,
+            _ => 0,
+        })
+    }
+
+    fn write(&mut self, offset: u32, access: Access, value: u32) -> Result<(), MemoryError> {
+        if access != Access::Word {
+            return Err(MemoryError::StoreAddress(access));
+        }
+        match offset {
+            // Writing ALARM_LOW arms the alarm, as on goldfish
+            ALARM_LOW => {
+                self.alarm = (self.alarm & !0xffff_ffff) | value as u64;
+                self.armed = true;
+            }
+            ALARM_HIGH => self.alarm = (self.alarm & 0xffff_ffff) | (value as u64) << 32,
+            IRQ_ENABLED => self.irq_enabled = value & 1 != 0,
+            CLEAR_ALARM => self.armed = false,
+            CLEAR_INTERRUPT => self.interrupt = false,
+            // The time registers are read-only
+            _ => {}
+        }
+        self.check_alarm();
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.ticks += 1;
+        self.check_alarm();
+    }
+
+    /// Time keeps running, as a real RTC's does.
+    fn reset(&mut self) {
+        self.time_high = 0;
+        self.alarm = 0;
+        self.armed = false;
+        self.irq_enabled = false;
+        self.interrupt = false;
+        self.check_alarm();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod rtc {
+    use super::*;
+    use crate::{testing, Bus, Memory};
+
+    const BASE: u32 = 0x0010_1000;
+
+    fn attach(rtc: GoldfishRtc) -> Memory {
+        testing::attach("rtc", BASE, RTC_SIZE, rtc)
+    }
+
+    fn time(m: &mut Memory) -> u64 {
+        let low = m.read(BASE + TIME_LOW, Access::Word).unwrap() as u64;
+        let high = m.read(BASE + TIME_HIGH, Access::Word).unwrap() as u64;
+        high << 32 | low
+    }
+
+    #[test]
+    fn virtual_clock_follows_ticks() {
+        let mut m = attach(GoldfishRtc::new(Clock::Virtual {
+            start: 0xffff_fff0,
+            ns_per_tick: 8,
+        }));
+
+        assert_eq!(time(&mut m), 0xffff_fff0);
+        m.tick();
+        m.tick();
+        assert_eq!(time(&mut m), 0x1_0000_0000);
+    }
+
+    #[test]
+    fn host_clock_is_after_epoch() {
+        let mut m = attach(GoldfishRtc::new(Clock::Host));
+        // 2020-01-01
+        assert!(time(&mut m) > 1_577_836_800_000_000_000);
+    }
+
+    #[test]
+    fn alarm_raises_interrupt() {
+        let irq = IrqLine::default();
+        let mut m = attach(GoldfishRtc::new(Clock::default()).with_irq(irq.clone()));
+
+        m.write(BASE + IRQ_ENABLED, Access::Word, 1).unwrap();
+        m.write(BASE + ALARM_HIGH, Access::Word, 0).unwrap();
+        m.write(BASE + ALARM_LOW, Access::Word, 30).unwrap();
+        assert_eq!(m.read(BASE + ALARM_STATUS, Access::Word).unwrap(), 1);
+        m.tick();
+        m.tick();
+        assert!(!irq.is_raised());
+        m.tick();
+        assert!(irq.is_raised());
+        assert_eq!(m.read(BASE + ALARM_STATUS, Access::Word).unwrap(), 0);
+
+        m.write(BASE + CLEAR_INTERRUPT, Access::Word, 1).unwrap();
+        assert!(!irq.is_raised());
+    }
+}
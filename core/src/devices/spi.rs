@@ -0,0 +1,344 @@
+use std::{collections::VecDeque, io};
+
+use super::IrqLine;
+use crate::memory::{Access, Device, MemoryError};
+
+const CSID: u32 = 0x10;
+const CSDEF: u32 = 0x14;
+const CSMODE: u32 = 0x18;
+const FMT: u32 = 0x40;
+const TXDATA: u32 = 0x48;
+const RXDATA: u32 = 0x4c;
+const TXMARK: u32 = 0x50;
+const RXMARK: u32 = 0x54;
+const IE: u32 = 0x70;
+const IP: u32 = 0x74;
+
+const CSMODE_AUTO: u32 = 0;
+const CSMODE_HOLD: u32 = 2;
+
+const FMT_ENDIAN_LSB: u32 = 1 << 2;
+const FMT_DIR_TX: u32 = 1 << 3;
+const FMT_RESET: u32 = 8 << 16;
+
+const FIFO_EMPTY: u32 = 1 << 31;
+const IP_TXWM: u32 = 1;
+const IP_RXWM: u32 = 2;
+
+const FIFO_DEPTH: usize = 8;
+
+/// Chip selects per controller.
+pub const SPI_CHIP_SELECTS: usize = 4;
+
+/// Register window of a SiFive SPI controller.
+pub const SPI_SIZE: u64 = 0x1000;
+
+/// Peripheral on an SPI bus.
+///
+/// A transaction runs from `select` to `deselect`; each `transfer` clocks one
+/// byte out to the peripheral and returns the byte it shifted back.
+pub trait SpiDevice {
+    fn select(&mut self) {}
+    fn transfer(&mut self, byte: u8) -> u8;
+    fn deselect(&mut self) {}
+    /// Returns to the power-on state, as on a reboot.
+    fn reset(&mut self) {}
+    /// Last failure the bus had no way to report, e.g. a write-back.
+    fn last_error(&self) -> Option<io::ErrorKind> {
+        None
+    }
+}
+
+/// SiFive-compatible SPI controller, as on the FE310.
+///
+/// Transfers complete as soon as TXDATA is written, so the transmit FIFO is
+/// never full. Serial clock and delay settings are accepted and ignored, as
+/// are the memory-mapped flash registers.
+pub struct SpiController {
+    devices: Vec<Option<Box<dyn SpiDevice>>>,
+    irq: Option<IrqLine>,
+    csid: u32,
+    csdef: u32,
+    csmode: u32,
+    fmt: u32,
+    txmark: u32,
+    rxmark: u32,
+    ie: u32,
+    rx: VecDeque<u8>,
+    /// Chip select held asserted in HOLD mode.
+    selected: Option<usize>,
+}
+
+impl Default for SpiController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpiController {
+    pub fn new() -> Self {
+        Self {
+            devices: (0..SPI_CHIP_SELECTS).map(|_| None).collect(),
+            irq: None,
+            csid: 0,
+            csdef: (1 << SPI_CHIP_SELECTS) - 1,
+            csmode: CSMODE_AUTO,
+            fmt: FMT_RESET,
+            txmark: 0,
+            rxmark: 0,
+            ie: 0,
+            rx: VecDeque::new(),
+            selected: None,
+        }
+    }
+
+    /// Connects `device` to chip select `cs`.
+    ///
+    /// # Panics
+    ///
+    /// If `cs` isn't below [`SPI_CHIP_SELECTS`].
+    pub fn with_device(mut self, cs: usize, device: Box<dyn SpiDevice>) -> Self {
+        self.devices[cs] = Some(device);
+        self
+    }
+
+    pub fn with_irq(mut self, irq: IrqLine) -> Self {
+        self.irq = Some(irq);
+        self
+    }
+
+    fn pending(&self) -> u32 {
+        let tx = if self.txmark > 0 { IP_TXWM } else { 0 };
+        let rx = if self.rx.len() as u32 > self.rxmark {
+            IP_RXWM
+        } else {
+            0
+        };
+        tx | rx
+    }
+
+    fn update_irq(&self) {
+        if let Some(irq) = &self.irq {
+            irq.set(self.pending() & self.ie != 0);
+        }
+    }
+
+    fn device(&mut self, cs: usize) -> Option<&mut Box<dyn SpiDevice>> {
+        self.devices.get_mut(cs).and_then(|d| d.as_mut())
+    }
+
+    fn release(&mut self) {
+        if let Some(cs) = self.selected.take() {
+            if let Some(device) = self.device(cs) {
+                device.deselect();
+            }
+        }
+    }
+
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let lsb_first = self.fmt & FMT_ENDIAN_LSB != 0;
+        let out = if lsb_first { byte.reverse_bits() } else { byte };
+        let cs = self.csid as usize;
+        let reply = match self.csmode {
+            CSMODE_AUTO => self.device(cs).map(|device| {
+                device.select();
+                let reply = device.transfer(out);
+                device.deselect();
+                reply
+            }),
+            CSMODE_HOLD => {
+                if self.selected.is_none() {
+                    self.selected = Some(cs);
+                    if let Some(device) = self.device(cs) {
+                        device.select();
+                    }
+                }
+                self.device(cs).map(|device| device.transfer(out))
+            }
+            // OFF: nothing is selected, the bus floats high
+            _ => None,
+        }
+        .unwrap_or(0xff);
+        if lsb_first {
+            reply.reverse_bits()
+        } else {
+            reply
+        }
+    }
+}
+
+impl Device for SpiController {
+    fn read(&mut self, offset: u32, access: Access) -> Result<u32, MemoryError> {
+        if access != Access::Word {
+            return Err(MemoryError::LoadAddress(access));
+        }
+        let value = match offset {
+            CSID => self.csid,
+            CSDEF => self.csdef,
+            CSMODE => self.csmode,
+            FMT => self.fmt,
+            // Never full
+            TXDATA => 0,
+            RXDATA => self.rx.pop_front().map_or(FIFO_EMPTY, |b| b as u32),
+            TXMARK => self.txmark,
+            RXMARK => self.rxmark,
+            IE => self.ie,
+            IP => self.pending(),
+            _ => 0,
+        };
+        self.update_irq();
+        Ok(value)
+    }
+
+    fn write(&mut self, offset: u32, access: Access, value: u32) -> Result<(), MemoryError> {
+        if access != Access::Word {
+            return Err(MemoryError::StoreAddress(access));
+        }
+        match offset {
+            CSID => {
+                if value != self.csid {
+                    self.release();
+                }
+                self.csid = value % SPI_CHIP_SELECTS as u32;
+            }
+            CSDEF => self.csdef = value & ((1 << SPI_CHIP_SELECTS) - 1),
+            CSMODE => {
+                self.csmode = value & 3;
+                if self.csmode != CSMODE_HOLD {
+                    self.release();
+                }
+            }
+            FMT => self.fmt = value,
+            TXDATA => {
+                let reply = self.exchange(value as u8);
+                if self.fmt & FMT_DIR_TX == 0 && self.rx.len() < FIFO_DEPTH {
+                    self.rx.push_back(reply);
+                }
+            }
+            TXMARK => self.txmark = value & 7,
+            RXMARK => self.rxmark = value & 7,
+            IE => self.ie = value & (IP_TXWM | IP_RXWM),
+            _ => {}
+        }
+        self.update_irq();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.release();
+        self.csid = 0;
+        self.csdef = (1 << SPI_CHIP_SELECTS) - 1;
+        self.csmode = CSMODE_AUTO;
+        self.fmt = FMT_RESET;
+        self.txmark = 0;
+        self.rxmark = 0;
+        self.ie = 0;
+        self.rx.clear();
+        for device in self.devices.iter_mut().flatten() {
+            device.reset();
+        }
+        self.update_irq();
+    }
+
+    fn last_error(&self) -> Option<MemoryError> {
+        let devices = self.devices.iter().flatten();
+        devices
+            .filter_map(|d| d.last_error())
+            .next()
+            .map(MemoryError::Io)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod spi {
+    use super::*;
+    use crate::{testing, Bus, Memory};
+    use std::{cell::RefCell, rc::Rc};
+
+    const BASE: u32 = 0x1001_4000;
+
+    /// Replies with the byte it got, offset by the bytes seen in the
+    /// transaction, and logs selects as `[` and deselects as `]`.
+    #[derive(Clone, Default)]
+    struct Echo(Rc<RefCell<(String, u8)>>);
+
+    impl SpiDevice for Echo {
+        fn select(&mut self) {
+            let mut state = self.0.borrow_mut();
+            state.0.push('[');
+            state.1 = 0;
+        }
+
+        fn transfer(&mut self, byte: u8) -> u8 {
+            let mut state = self.0.borrow_mut();
+            state.1 += 1;
+            byte + state.1
+        }
+
+        fn deselect(&mut self) {
+            self.0.borrow_mut().0.push(']');
+        }
+    }
+
+    fn attach(echo: &Echo) -> Memory {
+        let mut m = testing::attach(
+            "spi",
+            BASE,
+            SPI_SIZE,
+            SpiController::new().with_device(1, Box::new(echo.clone())),
+        );
+        m.write(BASE + CSID, Access::Word, 1).unwrap();
+        m
+    }
+
+    #[test]
+    fn auto_mode_selects_per_byte() {
+        let echo = Echo::default();
+        let mut m = attach(&echo);
+
+        m.write(BASE + TXDATA, Access::Word, 10).unwrap();
+        m.write(BASE + TXDATA, Access::Word, 10).unwrap();
+        assert_eq!(m.read(BASE + RXDATA, Access::Word).unwrap(), 11);
+        assert_eq!(m.read(BASE + RXDATA, Access::Word).unwrap(), 11);
+        assert_eq!(m.read(BASE + RXDATA, Access::Word).unwrap(), FIFO_EMPTY);
+        assert_eq!(echo.0.borrow().0, "[][]");
+    }
+
+    #[test]
+    fn hold_mode_keeps_device_selected() {
+        let echo = Echo::default();
+        let mut m = attach(&echo);
+
+        m.write(BASE + CSMODE, Access::Word, CSMODE_HOLD).unwrap();
+        m.write(BASE + TXDATA, Access::Word, 10).unwrap();
+        m.write(BASE + TXDATA, Access::Word, 10).unwrap();
+        assert_eq!(echo.0.borrow().0, "[");
+        m.write(BASE + CSMODE, Access::Word, CSMODE_AUTO).unwrap();
+        assert_eq!(echo.0.borrow().0, "[]");
+
+        assert_eq!(m.read(BASE + RXDATA, Access::Word).unwrap(), 11);
+        assert_eq!(m.read(BASE + RXDATA, Access::Word).unwrap(), 12);
+    }
+
+    #[test]
+    fn unconnected_select_and_watermark() {
+        let echo = Echo::default();
+        let irq = IrqLine::default();
+        let mut m = testing::attach(
+            "spi",
+            BASE,
+            SPI_SIZE,
+            SpiController::new()
+                .with_device(1, Box::new(echo.clone()))
+                .with_irq(irq.clone()),
+        );
+
+        m.write(BASE + IE, Access::Word, IP_RXWM).unwrap();
+        m.write(BASE + TXDATA, Access::Word, 1).unwrap();
+        assert!(irq.is_raised());
+        assert_eq!(m.read(BASE + RXDATA, Access::Word).unwrap(), 0xff);
+        assert!(!irq.is_raised());
+        assert_eq!(echo.0.borrow().0, "");
+    }
+}
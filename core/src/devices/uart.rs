@@ -0,0 +1,289 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::mpsc::Receiver,
+};
+
+use super::{stdin_channel, IrqLine};
+use crate::memory::{Access, Device, MemoryError};
+
+const RBR_THR: u32 = 0;
+const IER: u32 = 1;
+const IIR_FCR: u32 = 2;
+const LCR: u32 = 3;
+const MCR: u32 = 4;
+const LSR: u32 = 5;
+const MSR: u32 = 6;
+const SCR: u32 = 7;
+
+const IER_RDA: u8 = 0x01;
+const IER_THRE: u8 = 0x02;
+
+const IIR_NONE: u8 = 0x01;
+const IIR_THRE: u8 = 0x02;
+const IIR_RDA: u8 = 0x04;
+const IIR_FIFO: u8 = 0xc0;
+
+const LCR_DLAB: u8 = 0x80;
+
+const LSR_DR: u8 = 0x01;
+const LSR_THRE: u8 = 0x20;
+const LSR_TEMT: u8 = 0x40;
+
+/// Register window of a 16550 with one-byte register spacing.
+pub const UART_SIZE: u64 = 8;
+
+/// 16550-compatible UART.
+///
+/// Transmission is instantaneous, so THR is always empty. The divisor latch
+/// is accepted and ignored. Received bytes come from an optional channel and
+/// are polled on every tick.
+pub struct Uart16550 {
+    output: Box<dyn Write>,
+    input: Option<Receiver<u8>>,
+    rx: VecDeque<u8>,
+    irq: Option<IrqLine>,
+    ier: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    divisor: u16,
+    /// THR-empty interrupt pending, cleared by reading IIR or writing THR.
+    thre_pending: bool,
+}
+
+impl Uart16550 {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Self {
+            output,
+            input: None,
+            rx: VecDeque::new(),
+            irq: None,
+            ier: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+            divisor: 0,
+            thre_pending: false,
+        }
+    }
+
+    /// Console on the host's stdout and stdin.
+    pub fn stdio() -> Self {
+        Self::new(Box::new(io::stdout())).with_input(stdin_channel())
+    }
+
+    pub fn with_input(mut self, input: Receiver<u8>) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    pub fn with_irq(mut self, irq: IrqLine) -> Self {
+        self.irq = Some(irq);
+        self
+    }
+
+    fn poll(&mut self) {
+        if let Some(input) = &self.input {
+            self.rx.extend(input.try_iter());
+        }
+    }
+
+    fn interrupt(&self) -> u8 {
+        if self.ier & IER_RDA != 0 && !self.rx.is_empty() {
+            IIR_RDA
+        } else if self.ier & IER_THRE != 0 && self.thre_pending {
+            IIR_THRE
+        } else {
+            IIR_NONE
+        }
+    }
+
+    fn update_irq(&self) {
+        if let Some(irq) = &self.irq {
+            irq.set(self.interrupt() != IIR_NONE);
+        }
+    }
+
+    fn read_register(&mut self, offset: u32) -> u8 {
+        self.poll();
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match offset {
+            RBR_THR if dlab => self.divisor as u8,
+            RBR_THR => self.rx.pop_front().unwrap_or(0),
+            IER if dlab => (self.divisor >> 8) as u8,
+            IER => self.ier,
+            IIR_FCR => {
+                let iir = self.interrupt();
+                if iir == IIR_THRE {
+                    self.thre_pending = false;
+                }
+                iir | IIR_FIFO
+            }
+            LCR => self.lcr,
+            MCR => self.mcr,
+            LSR => {
+                let ready = if self.rx.is_empty() { 0 } else { LSR_DR };
+                ready | LSR_THRE | LSR_TEMT
+            }
+            MSR => 0,
+            SCR => self.scr,
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, offset: u32, value: u8) {
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match offset {
+            RBR_THR if dlab => self.divisor = (self.divisor & 0xff00) | value as u16,
+            RBR_THR => {
+                // Console output is best effort: a closed stdout shouldn't stop the guest
+                let _ = self.output.write_all(&[value]);
+                let _ = self.output.flush();
+                self.thre_pending = true;
+            }
+            IER if dlab => self.divisor = (self.divisor & 0x00ff) | (value as u16) << 8,
+            IER => {
+                self.ier = value & 0x0f;
+                self.thre_pending = self.ier & IER_THRE != 0;
+            }
+            LCR => self.lcr = value,
+            MCR => self.mcr = value,
+            SCR => self.scr = value,
+            // FCR and the read-only registers
+            _ => {}
+        }
+    }
+}
+
+impl Device for Uart16550 {
+    fn read(&mut self, offset: u32, _access: Access) -> Result<u32, MemoryError> {
+        let value = self.read_register(offset);
+        self.update_irq();
+        Ok(value as u32)
+    }
+
+    fn write(&mut self, offset: u32, _access: Access, value: u32) -> Result<(), MemoryError> {
+        self.write_register(offset, value as u8);
+        self.update_irq();
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.poll();
+        self.update_irq();
+    }
+
+    /// Input already received stays queued for the next boot.
+    fn reset(&mut self) {
+        self.ier = 0;
+        self.lcr = 0;
+        self.mcr = 0;
+        self.scr = 0;
+        self.divisor = 0;
+        self.thre_pending = false;
+        self.update_irq();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod uart {
+    use super::*;
+    use crate::{
+        testing::{self, Sink},
+        Bus,
+    };
+    use std::sync::mpsc;
+
+    const BASE: u32 = 0x1000_0000;
+
+    #[test]
+    fn transmit_goes_to_output() {
+        let sink = Sink::default();
+        let mut m = testing::attach(
+            "uart",
+            BASE,
+            UART_SIZE,
+            Uart16550::new(Box::new(sink.clone())),
+        );
+
+        for &b in b"hi\n" {
+            assert_ne!(
+                m.read(BASE + LSR, Access::Byte).unwrap() as u8 & LSR_THRE,
+                0
+            );
+            m.write(BASE + RBR_THR, Access::Byte, b as u32).unwrap();
+        }
+        assert_eq!(*sink.0.borrow(), b"hi\n");
+    }
+
+    #[test]
+    fn receive_comes_from_input() {
+        let (tx, rx) = mpsc::channel();
+        let mut m = testing::attach(
+            "uart",
+            BASE,
+            UART_SIZE,
+            Uart16550::new(Box::new(io::sink())).with_input(rx),
+        );
+
+        assert_eq!(m.read(BASE + LSR, Access::Byte).unwrap() as u8 & LSR_DR, 0);
+        tx.send(b'x').unwrap();
+        assert_eq!(
+            m.read(BASE + LSR, Access::Byte).unwrap() as u8 & LSR_DR,
+            LSR_DR
+        );
+        assert_eq!(m.read(BASE + RBR_THR, Access::Byte).unwrap(), b'x' as u32);
+        assert_eq!(m.read(BASE + LSR, Access::Byte).unwrap() as u8 & LSR_DR, 0);
+    }
+
+    #[test]
+    fn interrupt_line_follows_enabled_conditions() {
+        let (tx, rx) = mpsc::channel();
+        let irq = IrqLine::default();
+        let mut m = testing::attach(
+            "uart",
+            BASE,
+            UART_SIZE,
+            Uart16550::new(Box::new(io::sink()))
+                .with_input(rx)
+                .with_irq(irq.clone()),
+        );
+
+        tx.send(b'x').unwrap();
+        m.tick();
+        assert!(!irq.is_raised());
+
+        m.write(BASE + IER, Access::Byte, IER_RDA as u32).unwrap();
+        assert!(irq.is_raised());
+        assert_eq!(
+            m.read(BASE + IIR_FCR, Access::Byte).unwrap() as u8 & 0x0f,
+            IIR_RDA
+        );
+        m.read(BASE + RBR_THR, Access::Byte).unwrap();
+        assert!(!irq.is_raised());
+
+        m.write(BASE + IER, Access::Byte, IER_THRE as u32).unwrap();
+        assert!(irq.is_raised());
+        m.read(BASE + IIR_FCR, Access::Byte).unwrap();
+        assert!(!irq.is_raised());
+    }
+
+    #[test]
+    fn divisor_latch_does_not_transmit() {
+        let sink = Sink::default();
+        let mut m = testing::attach(
+            "uart",
+            BASE,
+            UART_SIZE,
+            Uart16550::new(Box::new(sink.clone())),
+        );
+
+        m.write(BASE + LCR, Access::Byte, LCR_DLAB as u32).unwrap();
+        m.write(BASE + RBR_THR, Access::Byte, 3).unwrap();
+        m.write(BASE + LCR, Access::Byte, 0x03).unwrap();
+        assert!(sink.0.borrow().is_empty());
+        assert_eq!(m.read(BASE + LCR, Access::Byte).unwrap(), 0x03);
+    }
+}
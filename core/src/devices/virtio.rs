@@ -0,0 +1,659 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use super::IrqLine;
+use crate::memory::{Access, Device, FileMode, Memory, MemoryError};
+
+const MAGIC: u32 = 0x7472_6976; // "virt"
+const VERSION: u32 = 2;
+const DEVICE_BLOCK: u32 = 2;
+const VENDOR: u32 = 0x554d_4551; // "QEMU"
+
+const MAGIC_VALUE: u32 = 0x000;
+const VERSION_REG: u32 = 0x004;
+const DEVICE_ID: u32 = 0x008;
+const VENDOR_ID: u32 = 0x00c;
+const DEVICE_FEATURES: u32 = 0x010;
+const DEVICE_FEATURES_SEL: u32 = 0x014;
+const DRIVER_FEATURES: u32 = 0x020;
+const DRIVER_FEATURES_SEL: u32 = 0x024;
+const QUEUE_SEL: u32 = 0x030;
+const QUEUE_NUM_MAX: u32 = 0x034;
+const QUEUE_NUM: u32 = 0x038;
+const QUEUE_READY: u32 = 0x044;
+const QUEUE_NOTIFY: u32 = 0x050;
+const INTERRUPT_STATUS: u32 = 0x060;
+const INTERRUPT_ACK: u32 = 0x064;
+const STATUS: u32 = 0x070;
+const QUEUE_DESC_LOW: u32 = 0x080;
+const QUEUE_DESC_HIGH: u32 = 0x084;
+const QUEUE_DRIVER_LOW: u32 = 0x090;
+const QUEUE_DRIVER_HIGH: u32 = 0x094;
+const QUEUE_DEVICE_LOW: u32 = 0x0a0;
+const QUEUE_DEVICE_HIGH: u32 = 0x0a4;
+const CONFIG_GENERATION: u32 = 0x0fc;
+const CONFIG: u32 = 0x100;
+
+const F_BLK_RO: u64 = 1 << 5;
+const F_BLK_FLUSH: u64 = 1 << 9;
+const F_VERSION_1: u64 = 1 << 32;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+const T_IN: u32 = 0;
+const T_OUT: u32 = 1;
+const T_FLUSH: u32 = 4;
+const T_GET_ID: u32 = 8;
+
+const S_OK: u8 = 0;
+const S_IOERR: u8 = 1;
+const S_UNSUPP: u8 = 2;
+
+const INTERRUPT_USED: u32 = 1;
+
+const SECTOR: u64 = 512;
+const QUEUE_SIZE: u32 = 256;
+const ID: &[u8] = b"brrrt-virtio-blk";
+
+/// Register window of a virtio-mmio device, including config space.
+pub const VIRTIO_SIZE: u64 = 0x200;
+
+#[derive(Debug, Default)]
+struct Queue {
+    num: u32,
+    ready: bool,
+    desc: u64,
+    driver: u64,
+    device: u64,
+    last_avail: u16,
+    used: u16,
+}
+
+/// Disk image, with writes kept in memory for [`FileMode::Private`].
+struct Disk {
+    file: File,
+    mode: FileMode,
+    sectors: u64,
+    overlay: HashMap<u64, Box<[u8; SECTOR as usize]>>,
+}
+
+impl Disk {
+    fn read(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<()> {
+        for (i, chunk) in buf.chunks_mut(SECTOR as usize).enumerate() {
+            let sector = sector + i as u64;
+            match self.overlay.get(&sector) {
+                Some(data) => chunk.copy_from_slice(&data[..chunk.len()]),
+                None => {
+                    self.file.seek(SeekFrom::Start(sector * SECTOR))?;
+                    self.file.read_exact(chunk)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, sector: u64, data: &[u8]) -> io::Result<()> {
+        match self.mode {
+            FileMode::ReadOnly => Err(io::ErrorKind::PermissionDenied.into()),
+            FileMode::Private => {
+                for (i, chunk) in data.chunks(SECTOR as usize).enumerate() {
+                    let mut copy = Box::new([0; SECTOR as usize]);
+                    copy.copy_from_slice(chunk);
+                    self.overlay.insert(sector + i as u64, copy);
+                }
+                Ok(())
+            }
+            FileMode::Sync => {
+                self.file.seek(SeekFrom::Start(sector * SECTOR))?;
+                self.file.write_all(data)
+            }
+        }
+    }
+
+    /// Makes written sectors durable. Only a synced image has any.
+    fn flush(&mut self) -> io::Result<()> {
+        match self.mode {
+            FileMode::Sync => self.file.sync_data(),
+            FileMode::ReadOnly | FileMode::Private => Ok(()),
+        }
+    }
+
+    fn in_range(&self, sector: u64, len: usize) -> bool {
+        (len as u64).is_multiple_of(SECTOR)
+            && sector
+                .checked_add(len as u64 / SECTOR)
+                .is_some_and(|end| end <= self.sectors)
+    }
+}
+
+/// virtio-mmio (version 2) block device with a single split virtqueue.
+///
+/// Requests are processed as soon as the driver notifies the queue.
+pub struct VirtioBlock {
+    disk: Disk,
+    irq: Option<IrqLine>,
+    device_features_sel: u32,
+    driver_features: u64,
+    driver_features_sel: u32,
+    queue_sel: u32,
+    queue: Queue,
+    interrupt_status: u32,
+    status: u32,
+    notified: bool,
+}
+
+impl VirtioBlock {
+    /// Serves the image at `path`. Its size is rounded down to whole sectors.
+    pub fn open(path: impl AsRef<Path>, mode: FileMode) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(mode == FileMode::Sync)
+            .open(path)?;
+        let sectors = file.metadata()?.len() / SECTOR;
+        Ok(Self {
+            disk: Disk {
+                file,
+                mode,
+                sectors,
+                overlay: HashMap::new(),
+            },
+            irq: None,
+            device_features_sel: 0,
+            driver_features: 0,
+            driver_features_sel: 0,
+            queue_sel: 0,
+            queue: Queue::default(),
+            interrupt_status: 0,
+            status: 0,
+            notified: false,
+        })
+    }
+
+    pub fn with_irq(mut self, irq: IrqLine) -> Self {
+        self.irq = Some(irq);
+        self
+    }
+
+    fn features(&self) -> u64 {
+        let ro = if self.disk.mode == FileMode::ReadOnly {
+            F_BLK_RO
+        } else {
+            0
+        };
+        F_VERSION_1 | F_BLK_FLUSH | ro
+    }
+
+    fn config(&self) -> [u8; 24] {
+        let mut config = [0; 24];
+        config[0..8].copy_from_slice(&self.disk.sectors.to_le_bytes());
+        config[20..24].copy_from_slice(&(SECTOR as u32).to_le_bytes());
+        config
+    }
+
+    fn update_irq(&self) {
+        if let Some(irq) = &self.irq {
+            irq.set(self.interrupt_status != 0);
+        }
+    }
+
+    fn set_low(value: &mut u64, low: u32) {
+        *value = (*value & !0xffff_ffff) | low as u64;
+    }
+
+    fn set_high(value: &mut u64, high: u32) {
+        *value = (*value & 0xffff_ffff) | (high as u64) << 32;
+    }
+
+    /// Handles every request made available since the last notification.
+    fn process(&mut self, memory: &mut Memory) -> Result<(), MemoryError> {
+        let q = &self.queue;
+        if !q.ready || q.num == 0 {
+            return Ok(());
+        }
+        let (num, driver, device) = (q.num, guest(q.driver)?, guest(q.device)?);
+        let avail = read_u16(memory, at(driver, 2)?)?;
+        if self.queue.last_avail == avail {
+            return Ok(());
+        }
+        while self.queue.last_avail != avail {
+            let slot = (self.queue.last_avail as u32 % num) * 2;
+            let head = read_u16(memory, at(driver, 4 + slot)?)?;
+            let written = self.request(memory, head)?;
+
+            let used = at(device, 4 + (self.queue.used as u32 % num) * 8)?;
+            memory.write_slice(used, &(head as u32).to_le_bytes())?;
+            memory.write_slice(at(used, 4)?, &written.to_le_bytes())?;
+            self.queue.used = self.queue.used.wrapping_add(1);
+            memory.write_slice(at(device, 2)?, &self.queue.used.to_le_bytes())?;
+            self.queue.last_avail = self.queue.last_avail.wrapping_add(1);
+        }
+        self.interrupt_status |= INTERRUPT_USED;
+        self.update_irq();
+        Ok(())
+    }
+
+    /// Runs the request whose descriptor chain starts at `head`, returning the
+    /// number of bytes written to guest memory.
+    fn request(&mut self, memory: &mut Memory, head: u16) -> Result<u32, MemoryError> {
+        // No request moves more than the whole disk, so neither do its buffers
+        let limit = self.disk.sectors.saturating_mul(SECTOR).saturating_add(16);
+        let mut oversized = false;
+
+        // Gather the driver's bytes and the buffers the device may fill
+        let mut out = Vec::new();
+        let mut buffers = Vec::new();
+        let mut idx = head;
+        for _ in 0..self.queue.num {
+            let desc = at(guest(self.queue.desc)?, idx as u32 * 16)?;
+            let addr = guest(memory.dword_at(desc)?)?;
+            let len = read_u32(memory, at(desc, 8)?)?;
+            let flags = read_u16(memory, at(desc, 12)?)?;
+            if flags & DESC_F_WRITE != 0 {
+                buffers.push((addr, len));
+            } else if out.len() as u64 + len as u64 > limit {
+                oversized = true;
+            } else {
+                let start = out.len();
+                out.resize(start + len as usize, 0);
+                memory.read_slice(addr, &mut out[start..])?;
+            }
+            if flags & DESC_F_NEXT == 0 {
+                break;
+            }
+            idx = read_u16(memory, at(desc, 14)?)?;
+        }
+        let capacity = buffers
+            .iter()
+            .try_fold(0u32, |sum, &(_, len)| sum.checked_add(len));
+        let (data, status) = match capacity {
+            Some(0) => return Ok(0),
+            _ if out.len() < 16 && !oversized => return Ok(0),
+            // Data fills the writable buffers, the status byte goes last
+            Some(capacity) if !oversized && capacity as u64 <= limit => {
+                self.serve(&out, capacity as usize - 1)
+            }
+            _ => (Vec::new(), S_IOERR),
+        };
+
+        let mut done = 0usize;
+        for &(addr, len) in &buffers {
+            if done < data.len() {
+                let end = data.len().min(done.saturating_add(len as usize));
+                memory.write_slice(addr, &data[done..end])?;
+            }
+            done = done.saturating_add(len as usize);
+        }
+        if let Some(&(addr, len)) = buffers.iter().rev().find(|&&(_, len)| len > 0) {
+            memory.write_slice(at(addr, len - 1)?, &[status])?;
+        }
+        Ok(data.len() as u32 + 1)
+    }
+
+    /// Carries out the request in `out`, returning up to `room` bytes of
+    /// data for the guest and the status.
+    fn serve(&mut self, out: &[u8], room: usize) -> (Vec<u8>, u8) {
+        let typ = u32::from_le_bytes(out[0..4].try_into().unwrap());
+        let sector = u64::from_le_bytes(out[8..16].try_into().unwrap());
+        match typ {
+            T_IN => {
+                if !self.disk.in_range(sector, room) {
+                    return (Vec::new(), S_IOERR);
+                }
+                let mut data = vec![0; room];
+                match self.disk.read(sector, &mut data) {
+                    Ok(_) => (data, S_OK),
+                    Err(_) => (Vec::new(), S_IOERR),
+                }
+            }
+            T_OUT => {
+                let payload = &out[16..];
+                if self.disk.in_range(sector, payload.len())
+                    && self.disk.write(sector, payload).is_ok()
+                {
+                    (Vec::new(), S_OK)
+                } else {
+                    (Vec::new(), S_IOERR)
+                }
+            }
+            T_FLUSH => match self.disk.flush() {
+                Ok(()) => (Vec::new(), S_OK),
+                Err(_) => (Vec::new(), S_IOERR),
+            },
+            T_GET_ID => {
+                let mut id = ID.to_vec();
+                id.resize(room.min(20), 0);
+                (id, S_OK)
+            }
+            _ => (Vec::new(), S_UNSUPP),
+        }
+    }
+}
+
+/// Guest address `by` bytes past `base`, which must not wrap.
+fn at(base: u32, by: u32) -> Result<u32, MemoryError> {
+    base.checked_add(by).ok_or(MemoryError::Load(base))
+}
+
+fn guest(address: u64) -> Result<u32, MemoryError> {
+    address
+        .try_into()
+        .map_err(|_| MemoryError::Load(address as u32))
+}
+
+fn read_u16(memory: &Memory, address: u32) -> Result<u16, MemoryError> {
+    let mut data = [0; 2];
+    memory.read_slice(address, &mut data)?;
+    Ok(u16::from_le_bytes(data))
+}
+
+fn read_u32(memory: &Memory, address: u32) -> Result<u32, MemoryError> {
+    let mut data = [0; 4];
+    memory.read_slice(address, &mut data)?;
+    Ok(u32::from_le_bytes(data))
+}
+
+impl Device for VirtioBlock {
+    fn read(&mut self, offset: u32, access: Access) -> Result<u32, MemoryError> {
+        if offset >= CONFIG {
+            let config = self.config();
+            let start = (offset - CONFIG) as usize;
+            let mut value = [0; 4];
+            for (i, b) in value.iter_mut().take(access.size() as usize).enumerate() {
+                *b = config.get(start + i).copied().unwrap_or(0);
+            }
+            return Ok(u32::from_le_bytes(value));
+        }
+        if access != Access::Word {
+            return Err(MemoryError::LoadAddress(access));
+        }
+        let q = &self.queue;
+        Ok(match offset {
+            MAGIC_VALUE => MAGIC,
+            VERSION_REG => VERSION,
+            DEVICE_ID => DEVICE_BLOCK,
+            VENDOR_ID => VENDOR,
+            DEVICE_FEATURES => match self.device_features_sel {
+                0 => self.features() as u32,
+                1 => (self.features() >> 32) as u32,
+                _ => 0,
+            },
+            QUEUE_NUM_MAX if self.queue_sel == 0 => QUEUE_SIZE,
+            QUEUE_READY if self.queue_sel == 0 => q.ready as u32,
+            INTERRUPT_STATUS => self.interrupt_status,
+            STATUS => self.status,
+            CONFIG_GENERATION => 0,
+            _ => 0,
+        })
+    }
+
+    fn write(&mut self, offset: u32, access: Access, value: u32) -> Result<(), MemoryError> {
+        if access != Access::Word {
+            return Err(MemoryError::StoreAddress(access));
+        }
+        let selected = self.queue_sel == 0;
+        let q = &mut self.queue;
+        match offset {
+            DEVICE_FEATURES_SEL => self.device_features_sel = value,
+            DRIVER_FEATURES => match self.driver_features_sel {
+                0 => Self::set_low(&mut self.driver_features, value),
+                1 => Self::set_high(&mut self.driver_features, value),
+                _ => {}
+            },
+            DRIVER_FEATURES_SEL => self.driver_features_sel = value,
+            QUEUE_SEL => self.queue_sel = value,
+            QUEUE_NUM if selected => q.num = value.min(QUEUE_SIZE),
+            QUEUE_READY if selected => q.ready = value & 1 != 0,
+            QUEUE_NOTIFY if value == 0 => self.notified = true,
+            INTERRUPT_ACK => {
+                self.interrupt_status &= !value;
+                self.update_irq();
+            }
+            STATUS if value == 0 => self.reset(),
+            STATUS => self.status = value,
+            QUEUE_DESC_LOW if selected => Self::set_low(&mut q.desc, value),
+            QUEUE_DESC_HIGH if selected => Self::set_high(&mut q.desc, value),
+            QUEUE_DRIVER_LOW if selected => Self::set_low(&mut q.driver, value),
+            QUEUE_DRIVER_HIGH if selected => Self::set_high(&mut q.driver, value),
+            QUEUE_DEVICE_LOW if selected => Self::set_low(&mut q.device, value),
+            QUEUE_DEVICE_HIGH if selected => Self::set_high(&mut q.device, value),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn pending(&self) -> bool {
+        self.notified
+    }
+
+    fn service(&mut self, memory: &mut Memory) {
+        self.notified = false;
+        if let Err(_e) = self.process(memory) {
+            // A broken queue is the driver's problem: flag it and stop processing
+            #[cfg(feature = "trace")]
+            eprintln!("virtio-blk: {:?}", _e);
+            const NEEDS_RESET: u32 = 0x40;
+            self.status |= NEEDS_RESET;
+        }
+    }
+
+    /// Also what the driver gets by writing 0 to STATUS. Private writes
+    /// stay, as they would on a disk.
+    fn reset(&mut self) {
+        self.driver_features = 0;
+        self.queue = Queue::default();
+        self.interrupt_status = 0;
+        self.status = 0;
+        self.notified = false;
+        self.update_irq();
+    }
+}
+
+/// virtio-mmio transport with nothing behind it (device ID 0), like the
+/// unused slots of QEMU's `virt` board.
+#[derive(Debug, Default)]
+pub struct VirtioEmpty;
+
+impl Device for VirtioEmpty {
+    fn read(&mut self, offset: u32, access: Access) -> Result<u32, MemoryError> {
+        if access != Access::Word {
+            return Err(MemoryError::LoadAddress(access));
+        }
+        Ok(match offset {
+            MAGIC_VALUE => MAGIC,
+            VERSION_REG => VERSION,
+            VENDOR_ID => VENDOR,
+            _ => 0,
+        })
+    }
+
+    fn write(&mut self, _offset: u32, _access: Access, _value: u32) -> Result<(), MemoryError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod virtio {
+    use super::*;
+    use crate::{Bus, MemoryMap};
+    use std::path::PathBuf;
+
+    const BASE: u32 = 0x1000_1000;
+    const DESC: u32 = 0x1000;
+    const AVAIL: u32 = 0x2000;
+    const USED: u32 = 0x3000;
+    const HEADER: u32 = 0x4000;
+    const DATA: u32 = 0x5000;
+    const STATUS_BYTE: u32 = 0x6000;
+
+    fn image(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("brrrt-{}-virtio-{}", std::process::id(), name));
+        let mut data = vec![0; 4 * SECTOR as usize];
+        data[SECTOR as usize..SECTOR as usize + 4].copy_from_slice(b"disk");
+        std::fs::write(&path, data).expect("temp image");
+        path
+    }
+
+    fn machine(path: &Path, mode: FileMode, irq: &IrqLine) -> Memory {
+        let mut m = Memory::with_map(MemoryMap::default().ram(0, 0x8000)).expect("valid map");
+        let device = VirtioBlock::open(path, mode)
+            .expect("image opens")
+            .with_irq(irq.clone());
+        m.attach("virtio0", BASE, VIRTIO_SIZE, Box::new(device))
+            .expect("should attach");
+        for (reg, value) in [
+            (STATUS, 1 | 2),
+            (DRIVER_FEATURES_SEL, 1),
+            (DRIVER_FEATURES, 1),
+            (STATUS, 1 | 2 | 8),
+            (QUEUE_SEL, 0),
+            (QUEUE_NUM, 8),
+            (QUEUE_DESC_LOW, DESC),
+            (QUEUE_DRIVER_LOW, AVAIL),
+            (QUEUE_DEVICE_LOW, USED),
+            (QUEUE_READY, 1),
+            (STATUS, 1 | 2 | 8 | 4),
+        ] {
+            m.write(BASE + reg, Access::Word, value).unwrap();
+        }
+        m
+    }
+
+    fn descriptor(m: &mut Memory, idx: u32, addr: u32, len: u32, flags: u16, next: u16) {
+        let at = DESC + idx * 16;
+        m.set_dword_at(at, addr as u64).unwrap();
+        m.set_word_at(at + 8, len).unwrap();
+        m.set_hw_at(at + 12, flags).unwrap();
+        m.set_hw_at(at + 14, next).unwrap();
+    }
+
+    /// Queues a three-descriptor request and notifies the device.
+    fn submit(m: &mut Memory, typ: u32, sector: u64, len: u32, device_writes: bool) {
+        m.set_word_at(HEADER, typ).unwrap();
+        m.set_dword_at(HEADER + 8, sector).unwrap();
+        let data_flags = if device_writes { DESC_F_WRITE } else { 0 };
+        descriptor(m, 0, HEADER, 16, DESC_F_NEXT, 1);
+        descriptor(m, 1, DATA, len, DESC_F_NEXT | data_flags, 2);
+        descriptor(m, 2, STATUS_BYTE, 1, DESC_F_WRITE, 0);
+
+        let avail = m.hw_at(AVAIL + 2).unwrap();
+        m.set_hw_at(AVAIL + 4 + (avail as u32 % 8) * 2, 0).unwrap();
+        m.set_hw_at(AVAIL + 2, avail + 1).unwrap();
+        m.write(BASE + QUEUE_NOTIFY, Access::Word, 0).unwrap();
+    }
+
+    #[test]
+    fn identifies_as_block_device() {
+        let path = image("id");
+        let mut m = machine(&path, FileMode::ReadOnly, &IrqLine::default());
+
+        assert_eq!(m.read(BASE + MAGIC_VALUE, Access::Word).unwrap(), MAGIC);
+        assert_eq!(m.read(BASE + VERSION_REG, Access::Word).unwrap(), 2);
+        assert_eq!(m.read(BASE + DEVICE_ID, Access::Word).unwrap(), 2);
+        assert_eq!(m.read(BASE + CONFIG, Access::Word).unwrap(), 4);
+        m.write(BASE + DEVICE_FEATURES_SEL, Access::Word, 0)
+            .unwrap();
+        let features = m.read(BASE + DEVICE_FEATURES, Access::Word).unwrap() as u64;
+        assert_ne!(features & F_BLK_RO, 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_request_completes_with_interrupt() {
+        let path = image("read");
+        let irq = IrqLine::default();
+        let mut m = machine(&path, FileMode::ReadOnly, &irq);
+
+        submit(&mut m, T_IN, 1, SECTOR as u32, true);
+
+        assert_eq!(m.read_cstr(DATA, 8).unwrap(), b"disk");
+        assert_eq!(m.byte_at(STATUS_BYTE).unwrap(), S_OK);
+        assert_eq!(m.hw_at(USED + 2).unwrap(), 1);
+        assert_eq!(m.word_at(USED + 8).unwrap(), SECTOR as u32 + 1);
+        assert!(irq.is_raised());
+        m.write(BASE + INTERRUPT_ACK, Access::Word, INTERRUPT_USED)
+            .unwrap();
+        assert!(!irq.is_raised());
+        // Nothing new to use, so nothing to signal
+        m.write(BASE + QUEUE_NOTIFY, Access::Word, 0).unwrap();
+        assert!(!irq.is_raised());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn writes_respect_mode() {
+        let path = image("write");
+        let irq = IrqLine::default();
+
+        let mut m = machine(&path, FileMode::ReadOnly, &irq);
+        m.write_slice(DATA, b"data").unwrap();
+        submit(&mut m, T_OUT, 2, SECTOR as u32, false);
+        assert_eq!(m.byte_at(STATUS_BYTE).unwrap(), S_IOERR);
+
+        // Overlay writes are visible to the guest but leave the image alone
+        let mut m = machine(&path, FileMode::Private, &irq);
+        m.write_slice(DATA, b"data").unwrap();
+        submit(&mut m, T_OUT, 2, SECTOR as u32, false);
+        assert_eq!(m.byte_at(STATUS_BYTE).unwrap(), S_OK);
+        m.fill(DATA, SECTOR as u32, 0).unwrap();
+        submit(&mut m, T_IN, 2, SECTOR as u32, true);
+        assert_eq!(m.read_cstr(DATA, 8).unwrap(), b"data");
+        assert_eq!(std::fs::read(&path).unwrap()[2 * SECTOR as usize], 0);
+
+        let mut m = machine(&path, FileMode::Sync, &irq);
+        m.write_slice(DATA, b"data").unwrap();
+        submit(&mut m, T_OUT, 3, SECTOR as u32, false);
+        submit(&mut m, T_FLUSH, 0, 0, false);
+        assert_eq!(m.byte_at(STATUS_BYTE).unwrap(), S_OK);
+        assert_eq!(
+            &std::fs::read(&path).unwrap()[3 * SECTOR as usize..][..4],
+            b"data"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn out_of_range_request_fails() {
+        let path = image("range");
+        let mut m = machine(&path, FileMode::ReadOnly, &IrqLine::default());
+
+        submit(&mut m, T_IN, 4, SECTOR as u32, true);
+        assert_eq!(m.byte_at(STATUS_BYTE).unwrap(), S_IOERR);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn hostile_lengths_and_rings_are_refused() {
+        let path = image("hostile");
+        let mut m = machine(&path, FileMode::Private, &IrqLine::default());
+
+        // Buffers adding up past 4 GiB
+        m.set_word_at(HEADER, T_IN).unwrap();
+        descriptor(&mut m, 0, HEADER, 16, DESC_F_NEXT, 1);
+        descriptor(&mut m, 1, DATA, 0x8000_0000, DESC_F_NEXT | DESC_F_WRITE, 2);
+        descriptor(&mut m, 2, DATA, 0x8000_0000, DESC_F_NEXT | DESC_F_WRITE, 3);
+        descriptor(&mut m, 3, STATUS_BYTE, 1, DESC_F_WRITE, 0);
+        m.set_hw_at(AVAIL + 2, 1).unwrap();
+        m.write(BASE + QUEUE_NOTIFY, Access::Word, 0).unwrap();
+        assert_eq!(m.byte_at(STATUS_BYTE).unwrap(), S_IOERR);
+
+        // Far more data than the disk holds
+        submit(&mut m, T_OUT, 0, u32::MAX, false);
+        assert_eq!(m.byte_at(STATUS_BYTE).unwrap(), S_IOERR);
+        submit(&mut m, T_IN, 0, u32::MAX, true);
+        assert_eq!(m.byte_at(STATUS_BYTE).unwrap(), S_IOERR);
+
+        // A ring at the end of the address space breaks the device, not the host
+        m.write(BASE + QUEUE_DRIVER_LOW, Access::Word, 0xffff_fffe)
+            .unwrap();
+        m.write(BASE + QUEUE_NOTIFY, Access::Word, 0).unwrap();
+        assert_ne!(m.read(BASE + STATUS, Access::Word).unwrap() & 0x40, 0);
+        std::fs::remove_file(path).unwrap();
+    }
+}
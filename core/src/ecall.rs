@@ -0,0 +1,100 @@
+use crate::{
+    rv32i::instr::{instruction::InstructionError, operation::Operation, part::Part},
+    Instruction, VM,
+};
+
+const ECALL: u32 = 0;
+
+/// Services ECALLs in place of a trap handler.
+///
+/// The core has no privilege modes or traps, so the handler sees the VM as
+/// the caller left it and answers through its registers. PC moves past the
+/// ECALL afterwards unless the handler fails.
+pub trait Ecall {
+    fn ecall(&mut self, vm: &mut VM) -> Result<(), InstructionError>;
+    /// Forgets what earlier calls set up, as on a reboot.
+    fn reset(&mut self) {}
+}
+
+pub(crate) struct Handler(Box<dyn Ecall>);
+
+impl Handler {
+    pub(crate) fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+impl std::fmt::Debug for Handler {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Handler")
+    }
+}
+
+impl VM {
+    pub fn set_ecall(&mut self, handler: Box<dyn Ecall>) {
+        self.ecall = Some(Handler(handler));
+    }
+
+    /// ECALL goes to the handler; EBREAK and ECALLs without a handler
+    /// aren't supported.
+    pub(crate) fn call(&mut self, i: Instruction) -> Result<(), InstructionError> {
+        let function = i
+            .value(Part::Imm110)
+            .or(Err(InstructionError::InvalidArgument(Part::Imm110)))?;
+        if function != ECALL {
+            return Err(InstructionError::InvalidOperation(Operation::Call));
+        }
+        let Some(mut handler) = self.ecall.take() else {
+            return Err(InstructionError::InvalidOperation(Operation::Call));
+        };
+        let result = handler.0.ecall(self);
+        self.ecall = Some(handler);
+        result
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod ecall {
+    use super::*;
+    use crate::{Program, Register};
+
+    struct Add;
+
+    impl Ecall for Add {
+        fn ecall(&mut self, vm: &mut VM) -> Result<(), InstructionError> {
+            let sum = vm.cpu.register.get(Register::X10) + vm.cpu.register.get(Register::X11);
+            vm.cpu.register.set(Register::X10, sum);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn handler_answers_through_registers() {
+        let mut vm: VM = Default::default();
+        let program = Program::from_asm(&[0x0000_0073]);
+        program.load(&mut vm).expect("should load");
+        vm.set_ecall(Box::new(Add));
+        vm.cpu.register.set(Register::X10, 2);
+        vm.cpu.register.set(Register::X11, 3);
+
+        program.run(&mut vm).expect("should run");
+
+        assert_eq!(vm.cpu.register.get(Register::X10), 5);
+        assert_eq!(vm.cpu.register.get(Register::PC), 4);
+    }
+
+    #[test]
+    fn unhandled_calls_fail() {
+        let mut vm: VM = Default::default();
+        let program = Program::from_asm(&[0x0000_0073, 0x0010_0073]);
+        program.load(&mut vm).expect("should load");
+        assert!(program.run(&mut vm).is_err());
+
+        vm.set_ecall(Box::new(Add));
+        program.step(&mut vm, 0).expect("ECALL is handled");
+        // EBREAK
+        assert!(program.step(&mut vm, 1).is_err());
+        assert_eq!(vm.cpu.register.get(Register::PC), 4);
+    }
+}
@@ -1,10 +1,10 @@
 #[derive(Default, Debug)]
 pub(crate) struct ELFHeader {
-    entry: u32,
+    pub(crate) entry: u32,
 
-    phoff: u32,
-    phentsize: u16,
-    phnum: u16,
+    pub(crate) phoff: u32,
+    pub(crate) phentsize: u16,
+    pub(crate) phnum: u16,
 
     pub(crate) shoff: u32,
     pub(crate) shentsize: u16,
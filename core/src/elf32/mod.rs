@@ -1,14 +1,20 @@
 mod header;
 mod section;
+mod segment;
+mod symbol;
 
 use header::{ELFHeader, ELFHeaderError};
 pub use section::SectionName;
 use section::{Section, SectionHeader, SectionHeaderError, SectionNameError};
+pub use segment::Segment;
+use segment::SegmentError;
+pub use symbol::{Symbol, Symbols};
 
 #[derive(Debug)]
 pub enum Error {
     HeaderParseError,
     SectionParseError,
+    SegmentParseError,
 }
 
 impl From<ELFHeaderError> for Error {
@@ -41,17 +47,67 @@ impl From<SectionNameError> for Error {
     }
 }
 
+impl From<SegmentError> for Error {
+    fn from(_e: SegmentError) -> Self {
+        #[cfg(feature = "trace")]
+        {
+            eprintln!("Segment error: {:?}", _e);
+        }
+        Self::SegmentParseError
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ELF {
     header: ELFHeader,
     sections: Vec<Section>,
+    segments: Vec<Segment>,
 }
 
 impl ELF {
+    /// Entry point address.
+    pub fn entry(&self) -> u32 {
+        self.header.entry
+    }
+
     pub fn get(&self, s: SectionName) -> Option<&Section> {
         self.sections.iter().find(|&x| x.name == s)
     }
 
+    /// Program headers, present in linked executables.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Address the program headers are loaded at, if a load segment
+    /// covers them.
+    pub fn program_headers(&self) -> Option<u32> {
+        self.segments
+            .iter()
+            .filter(|s| s.is_load())
+            .find_map(|s| s.address_of(self.header.phoff))
+    }
+
+    /// Size of a program header entry.
+    pub fn phent(&self) -> u16 {
+        self.header.phentsize
+    }
+
+    /// Number of program headers.
+    pub fn phnum(&self) -> u16 {
+        self.header.phnum
+    }
+
+    /// Function and object symbols, empty for stripped binaries.
+    pub fn symbols(&self, executable: &[u8]) -> Symbols {
+        match (self.get(SectionName::Symtab), self.get(SectionName::Strtab)) {
+            (Some(symtab), Some(strtab)) => {
+                Symbols::parse(symtab.get(executable), strtab.get(executable))
+            }
+            _ => Symbols::default(),
+        }
+    }
+
     pub fn parse(executable: &[u8]) -> Result<Self, Error> {
         ELFHeader::is_valid(executable)?;
         let mut e: ELF = Self {
@@ -62,15 +118,15 @@ impl ELF {
         if e.header.shnum > 0 {
             let names_offset = {
                 let field_off = 4 * 4; // "offset" is fifth 4-byte field
-                let start = (e.header.shstrndx * e.header.shentsize + e.header.shoff as u16)
-                    as usize
+                let start = e.header.shstrndx as usize * e.header.shentsize as usize
+                    + e.header.shoff as usize
                     + field_off;
                 let entry = &executable[start..start + 4];
                 u32::from_le_bytes(entry.try_into().or(Err(Error::SectionParseError))?)
             } as usize;
             e.sections = Vec::with_capacity(e.header.shnum as usize);
             for x in 1..e.header.shnum {
-                let start = ((x * e.header.shentsize) + e.header.shoff as u16) as usize;
+                let start = x as usize * e.header.shentsize as usize + e.header.shoff as usize;
 
                 let content = &executable[start + 4..start + e.header.shentsize as usize];
                 let hdr = SectionHeader::parse(content)?;
@@ -89,6 +145,15 @@ impl ELF {
             }
         }
 
+        e.segments = Vec::with_capacity(e.header.phnum as usize);
+        for x in 0..e.header.phnum as usize {
+            let start = e.header.phoff as usize + x * e.header.phentsize as usize;
+            let content = executable
+                .get(start..start + e.header.phentsize as usize)
+                .ok_or(Error::SegmentParseError)?;
+            e.segments.push(Segment::parse(content)?);
+        }
+
         Ok(e)
     }
 }
@@ -1,9 +1,16 @@
+use crate::memory::Permissions;
+
 const MAX_NAME_LENGTH: usize = 32;
 
+const SHF_WRITE: u32 = 0x1;
+const SHF_EXECINSTR: u32 = 0x4;
+
 #[derive(Debug, PartialEq)]
 pub enum SectionName {
     Text,
     Rodata,
+    Symtab,
+    Strtab,
 }
 
 impl TryFrom<String> for SectionName {
@@ -13,6 +20,8 @@ impl TryFrom<String> for SectionName {
         match s.as_str() {
             ".text" => Ok(Self::Text),
             ".rodata" => Ok(Self::Rodata),
+            ".symtab" => Ok(Self::Symtab),
+            ".strtab" => Ok(Self::Strtab),
             _ => Err(SectionNameError::Unknown),
         }
     }
@@ -50,28 +59,33 @@ impl Section {
         Ok(Self { name, header: hdr })
     }
 
-    pub fn get(&self, executable: &[u8]) -> Vec<u32> {
+    /// Raw section contents.
+    pub fn get<'a>(&self, executable: &'a [u8]) -> &'a [u8] {
         let hdr = &self.header;
-        let mut data = Vec::with_capacity(hdr.size as usize);
-        match &self.name {
-            SectionName::Rodata => {
-                for x in (hdr.offset..hdr.offset + hdr.size).step_by(hdr.align as usize) {
-                    let x = x as usize;
-                    if let &[val] = &executable[x..x + 1] {
-                        data.push(val as u32)
-                    }
-                }
-            }
-            SectionName::Text => {
-                for x in hdr.offset..hdr.offset + hdr.size {
-                    let x = x as usize;
-                    if let &[val] = &executable[x..x + 1] {
-                        data.push(val as u32)
-                    }
-                }
-            }
-        };
-        data
+        let start = hdr.offset as usize;
+        let end = hdr.offset.saturating_add(hdr.size) as usize;
+        &executable[start.min(executable.len())..end.min(executable.len())]
+    }
+
+    /// Virtual address the section wants to be loaded at.
+    pub fn address(&self) -> u32 {
+        self.header.addr
+    }
+
+    pub fn size(&self) -> u32 {
+        self.header.size
+    }
+
+    pub fn align(&self) -> u32 {
+        self.header.align
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions::new(
+            true,
+            self.header.flags & SHF_WRITE != 0,
+            self.header.flags & SHF_EXECINSTR != 0,
+        )
     }
 }
 
@@ -171,6 +185,7 @@ pub enum SectionNameError {
 }
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod section {
     use super::*;
 
@@ -178,21 +193,21 @@ mod section {
     fn parse_empty_should_fail() {
         Section::parse(Default::default(), &Vec::new()).map_or_else(
             |e| match e {
-                SectionNameError::Missing => assert!(true),
-                _ => assert!(false, "unexpected error: {:?}", e),
+                SectionNameError::Missing => {}
+                _ => panic!("unexpected error: {:?}", e),
             },
-            |_| assert!(false, "expected failure"),
+            |_| panic!("expected failure"),
         );
     }
 
     #[test]
     fn parse_zero_byte_should_fail() {
-        Section::parse(Default::default(), &vec![0]).map_or_else(
+        Section::parse(Default::default(), &[0]).map_or_else(
             |e| match e {
-                SectionNameError::Missing => assert!(true),
-                _ => assert!(false, "unexpected error: {:?}", e),
+                SectionNameError::Missing => {}
+                _ => panic!("unexpected error: {:?}", e),
             },
-            |_| assert!(false, "expected failure"),
+            |_| panic!("expected failure"),
         );
     }
 
@@ -200,22 +215,16 @@ mod section {
     fn parse_garbage_should_fail() {
         Section::parse(Default::default(), String::from("wat").as_bytes()).map_or_else(
             |e| match e {
-                SectionNameError::Invalid => assert!(true),
-                _ => assert!(false, "unexpected error: {:?}", e),
+                SectionNameError::Invalid => {}
+                _ => panic!("unexpected error: {:?}", e),
             },
-            |_| assert!(false, "expected failure"),
+            |_| panic!("expected failure"),
         );
     }
 
     #[test]
     fn parse_happy_path() {
-        Section::parse(
-            Default::default(),
-            &vec!['.' as u8, 't' as u8, 'e' as u8, 'x' as u8, 't' as u8, 0],
-        )
-        .map_or_else(
-            |e| assert!(false, "expected success: {:?}", e),
-            |_| assert!(true, "expected success"),
-        );
+        Section::parse(Default::default(), b".text\0")
+            .map_or_else(|e| panic!("expected success: {:?}", e), |_| {});
     }
 }
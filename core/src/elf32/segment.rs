@@ -0,0 +1,144 @@
+use crate::memory::Permissions;
+
+const PT_LOAD: u32 = 1;
+
+const PF_X: u32 = 0x1;
+const PF_W: u32 = 0x2;
+const PF_R: u32 = 0x4;
+
+/// Program header entry.
+#[derive(Default, Debug)]
+pub struct Segment {
+    typ: u32,
+    offset: u32,
+    vaddr: u32,
+    paddr: u32,
+    filesz: u32,
+    memsz: u32,
+    flags: u32,
+    align: u32,
+}
+
+impl Segment {
+    pub(crate) fn parse(executable: &[u8]) -> Result<Self, SegmentError> {
+        let field = |idx: usize, e: SegmentError| -> Result<u32, SegmentError> {
+            let entry = executable.get(idx * 4..idx * 4 + 4).ok_or(e)?;
+            Ok(u32::from_le_bytes(entry.try_into().or(Err(e))?))
+        };
+        Ok(Self {
+            typ: field(0, SegmentError::Type)?,
+            offset: field(1, SegmentError::Offset)?,
+            vaddr: field(2, SegmentError::VirtualAddress)?,
+            paddr: field(3, SegmentError::PhysicalAddress)?,
+            filesz: field(4, SegmentError::FileSize)?,
+            memsz: field(5, SegmentError::MemorySize)?,
+            flags: field(6, SegmentError::Flags)?,
+            align: field(7, SegmentError::Align)?,
+        })
+    }
+
+    /// Whether the segment gets loaded into memory.
+    pub fn is_load(&self) -> bool {
+        self.typ == PT_LOAD
+    }
+
+    /// Segment contents present in the file.
+    pub fn get<'a>(&self, executable: &'a [u8]) -> &'a [u8] {
+        let start = self.offset as usize;
+        let end = self.offset.saturating_add(self.filesz) as usize;
+        &executable[start.min(executable.len())..end.min(executable.len())]
+    }
+
+    /// Address a file offset ends up at, if the segment loads it.
+    pub(crate) fn address_of(&self, offset: u32) -> Option<u32> {
+        let within = offset.checked_sub(self.offset)?;
+        if within < self.filesz {
+            self.vaddr.checked_add(within)
+        } else {
+            None
+        }
+    }
+
+    pub fn address(&self) -> u32 {
+        self.vaddr
+    }
+
+    pub fn physical_address(&self) -> u32 {
+        self.paddr
+    }
+
+    /// Size in memory, including the zero-filled part past file contents.
+    pub fn size(&self) -> u32 {
+        self.memsz
+    }
+
+    pub fn align(&self) -> u32 {
+        self.align
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions::new(
+            self.flags & PF_R != 0,
+            self.flags & PF_W != 0,
+            self.flags & PF_X != 0,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SegmentError {
+    Type,
+    Offset,
+    VirtualAddress,
+    PhysicalAddress,
+    FileSize,
+    MemorySize,
+    Flags,
+    Align,
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod segment {
+    use super::*;
+
+    #[test]
+    fn parse_truncated_should_fail() {
+        assert!(Segment::parse(&[1, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn parse_happy_path() {
+        let raw: Vec<u8> = [
+            PT_LOAD,
+            0x1000,
+            0x8000_0000,
+            0x8000_0000,
+            16,
+            32,
+            PF_R | PF_X,
+            4,
+        ]
+        .iter()
+        .flat_map(|x: &u32| x.to_le_bytes())
+        .collect();
+        let segment = Segment::parse(&raw).expect("should parse");
+
+        assert!(segment.is_load());
+        assert_eq!(segment.address(), 0x8000_0000);
+        assert_eq!(segment.size(), 32);
+        assert_eq!(segment.permissions(), Permissions::RX);
+        assert_eq!(segment.get(&raw).len(), 0);
+    }
+
+    #[test]
+    fn get_clamps_bogus_sizes() {
+        let raw: Vec<u8> = [PT_LOAD, 4, 0, 0, u32::MAX, u32::MAX, PF_R, 4]
+            .iter()
+            .flat_map(|x: &u32| x.to_le_bytes())
+            .collect();
+        let segment = Segment::parse(&raw).expect("should parse");
+
+        assert_eq!(segment.get(&raw), &raw[4..]);
+    }
+}
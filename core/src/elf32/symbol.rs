@@ -0,0 +1,103 @@
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+const ENTRY_SIZE: usize = 16;
+
+/// Function or data object from the `.symtab` section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u32,
+    pub size: u32,
+    pub function: bool,
+}
+
+impl Symbol {
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.address && (address - self.address) < self.size.max(1)
+    }
+}
+
+/// Symbols sorted by address, for turning addresses into names.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols(Vec<Symbol>);
+
+impl Symbols {
+    pub(crate) fn parse(symtab: &[u8], strtab: &[u8]) -> Self {
+        let mut symbols: Vec<Symbol> = symtab
+            .chunks_exact(ENTRY_SIZE)
+            .filter_map(|entry| {
+                let field = |idx: usize| {
+                    u32::from_le_bytes(entry[idx * 4..idx * 4 + 4].try_into().unwrap())
+                };
+                let kind = entry[12] & 0xf;
+                let section = u16::from_le_bytes([entry[14], entry[15]]);
+                if section == 0 || (kind != STT_FUNC && kind != STT_OBJECT) {
+                    return None;
+                }
+                let name = strtab.get(field(0) as usize..)?;
+                let end = name.iter().position(|&c| c == 0)?;
+                Some(Symbol {
+                    name: String::from_utf8_lossy(&name[..end]).into_owned(),
+                    address: field(1),
+                    size: field(2),
+                    function: kind == STT_FUNC,
+                })
+            })
+            .collect();
+        symbols.sort_by_key(|s| s.address);
+        Self(symbols)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.0.iter()
+    }
+
+    /// Symbol covering `address`.
+    pub fn lookup(&self, address: u32) -> Option<&Symbol> {
+        let idx = self.0.partition_point(|s| s.address <= address);
+        self.0[..idx].iter().rev().find(|s| s.contains(address))
+    }
+
+    /// `name+0xoffset` for `address`, or the bare address if nothing covers it.
+    pub fn describe(&self, address: u32) -> String {
+        match self.lookup(address) {
+            Some(s) if s.address == address => s.name.clone(),
+            Some(s) => format!("{}+{:#x}", s.name, address - s.address),
+            None => format!("{:#010x}", address),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod symbol {
+    use super::*;
+
+    fn entry(name: u32, address: u32, size: u32, kind: u8) -> Vec<u8> {
+        let mut raw: Vec<u8> = [name, address, size]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        raw.extend_from_slice(&[kind, 0, 1, 0]);
+        raw
+    }
+
+    #[test]
+    fn lookup_by_address() {
+        let strtab = b"\0main\0buffer\0";
+        let symtab = [
+            entry(0, 0, 0, 0),
+            entry(6, 0x200, 16, STT_OBJECT),
+            entry(1, 0x100, 32, STT_FUNC),
+        ]
+        .concat();
+        let symbols = Symbols::parse(&symtab, strtab);
+
+        assert_eq!(symbols.iter().count(), 2);
+        assert_eq!(symbols.describe(0x100), "main");
+        assert_eq!(symbols.describe(0x114), "main+0x14");
+        assert_eq!(symbols.describe(0x120), "0x00000120");
+        assert!(!symbols.lookup(0x204).expect("should find").function);
+    }
+}
@@ -1,10 +1,19 @@
 pub mod bitops;
 pub mod cpu;
 pub mod debug;
+pub mod devices;
+pub mod ecall;
 pub mod elf32;
+pub mod linux;
+pub mod machine;
 pub mod memory;
 pub mod program;
 pub mod rv32i;
+pub mod sbi;
+pub mod shadow;
+pub mod snapshot;
+pub mod stack;
+pub mod vfs;
 
 // tests
 #[cfg(test)]
@@ -21,20 +30,60 @@ mod load;
 mod math;
 #[cfg(test)]
 mod store;
+#[cfg(test)]
+mod testing;
 
 pub use cpu::{Register, Registers, CPU, REGISTER_INCREMENT};
-pub use memory::Memory;
+use memory::Access;
+pub use memory::{Bus, Memory, MemoryMap};
 pub use program::Program;
 use rv32i::{
     instr::instruction::{Instruction, InstructionError},
     instr::operation::{Operation, OperationError},
     instr::part::Part,
 };
+use shadow::Shadow;
+pub use snapshot::Snapshot;
+use stack::{Link, StackMonitor, StackOverflow};
+
+/// Reason execution stopped before running off the end of the program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Halt {
+    /// A watchpoint callback asked to stop, on access to the address.
+    Watchpoint(u32),
+    /// The stack pointer left the configured stack region.
+    StackOverflow(StackOverflow),
+    /// The guest asked to be switched off.
+    PowerOff,
+    /// The guest asked to be restarted.
+    Reboot,
+    /// The guest reported a failure with this code.
+    Fail(u16),
+    /// The guest program exited with this status.
+    Exit(i32),
+}
+
+impl std::fmt::Display for Halt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Watchpoint(address) => write!(f, "watchpoint hit at {:#010x}", address),
+            Self::StackOverflow(overflow) => write!(f, "{}", overflow),
+            Self::PowerOff => write!(f, "powered off"),
+            Self::Reboot => write!(f, "reboot requested"),
+            Self::Fail(code) => write!(f, "failed with code {}", code),
+            Self::Exit(status) => write!(f, "exited with status {}", status),
+        }
+    }
+}
 
 #[derive(Default, Debug)]
 pub struct VM {
     pub cpu: CPU,
-    pub ram: Memory,
+    pub memory: Memory,
+    halt: Option<Halt>,
+    shadow: Option<Shadow>,
+    stack: Option<StackMonitor>,
+    ecall: Option<ecall::Handler>,
     #[cfg(feature = "debug")]
     debug: Vec<String>,
     #[cfg(feature = "debug")]
@@ -42,6 +91,50 @@ pub struct VM {
 }
 
 impl VM {
+    pub fn new(map: MemoryMap) -> Result<Self, memory::MemoryError> {
+        Ok(Self {
+            memory: Memory::with_map(map)?,
+            ..Default::default()
+        })
+    }
+
+    /// Point stack pointer at the top of the stack, or of RAM if none is set.
+    pub fn initialize(&mut self) {
+        if let Some(stack) = self.stack.as_ref() {
+            self.cpu.initialize(stack.stack().top());
+        } else if let Some(top) = self.memory.map().stack_top() {
+            self.cpu.initialize(top);
+        }
+    }
+
+    /// Why execution stopped, if it was stopped.
+    pub fn halted(&self) -> Option<&Halt> {
+        self.halt.as_ref()
+    }
+
+    pub fn halt(&mut self, reason: Halt) {
+        self.halt = Some(reason);
+    }
+
+    /// Picks up stop requests raised while accessing memory.
+    pub(crate) fn poll_halt(&mut self) {
+        if let Some(reason) = self.memory.take_halt() {
+            self.halt = Some(reason);
+        }
+    }
+
+    /// Starts reporting uses of uninitialized memory.
+    ///
+    /// Enable before loading the program, or its image counts as undefined.
+    pub fn enable_shadow(&mut self) {
+        self.memory.enable_shadow();
+        self.shadow.get_or_insert_with(Shadow::default);
+    }
+
+    pub fn shadow(&self) -> Option<&Shadow> {
+        self.shadow.as_ref()
+    }
+
     #[cfg(feature = "debug")]
     pub fn debug(&self) -> Vec<String> {
         self.debug.clone()
@@ -52,8 +145,12 @@ impl VM {
     }
 
     pub fn execute(&mut self, i: Instruction) -> Result<(), InstructionError> {
+        let pc = self.cpu.register.get(Register::PC);
+        self.memory.set_pc(pc);
         self.debug.clear();
         self.last = Some(i.clone());
+        let pending = self.shadow_before(&i);
+        let link = Link::of(&i);
         let result = match i.opcode {
             Operation::LUI => self.load_upper_immediate(i),
             Operation::AUIPC => self.add_upper_immediate(i),
@@ -64,11 +161,17 @@ impl VM {
             Operation::Branch => self.branch(i),
             Operation::Load => self.load(i),
             Operation::Store => self.store(i),
+            Operation::Call => self.call(i),
             _ => Err(OperationError::UnknownOpcode(i.raw).into()),
         };
         if result.is_ok() {
+            if let Some(pending) = pending {
+                self.shadow_after(pending);
+            }
+            self.track_stack(link, pc);
             self.cpu.increment_pc();
         }
+        self.poll_halt();
         result
     }
 
@@ -91,7 +194,8 @@ impl VM {
             .value(Part::Imm115)
             .or(Err(InstructionError::InvalidArgument(Part::Imm115)))?;
         let immediate = (im115 << 5) | im40; // https://stackoverflow.com/a/60239441
-        let address = self.cpu.register.get(rs1) as i32 + bitops::sign_extend(immediate, 12);
+        let address =
+            (self.cpu.register.get(rs1) as i32).wrapping_add(bitops::sign_extend(immediate, 12));
 
         #[cfg(any(feature = "trace", feature = "debug"))]
         {
@@ -117,20 +221,20 @@ impl VM {
         match f3 {
             0b000 => {
                 // SB
-                self.ram
-                    .set_byte_at(address as u32, self.cpu.register.get(rs2) as u8)?;
+                self.memory
+                    .write(address as u32, Access::Byte, self.cpu.register.get(rs2))?;
                 Ok(())
             }
             0b001 => {
                 // SH
-                self.ram
-                    .set_hw_at(address as u32, self.cpu.register.get(rs2) as u16)?;
+                self.memory
+                    .write(address as u32, Access::HalfWord, self.cpu.register.get(rs2))?;
                 Ok(())
             }
             0b010 => {
                 // SW
-                self.ram
-                    .set_word_at(address as u32, self.cpu.register.get(rs2))?;
+                self.memory
+                    .write(address as u32, Access::Word, self.cpu.register.get(rs2))?;
                 Ok(())
             }
             _ => Err(InstructionError::InvalidOperation(Operation::Store)),
@@ -152,7 +256,8 @@ impl VM {
         let immediate = i
             .value(Part::Imm110)
             .or(Err(InstructionError::InvalidArgument(Part::Imm110)))?;
-        let address = self.cpu.register.get(rs1) as i32 + bitops::sign_extend(immediate, 12);
+        let address =
+            (self.cpu.register.get(rs1) as i32).wrapping_add(bitops::sign_extend(immediate, 12));
 
         #[cfg(any(feature = "trace", feature = "debug"))]
         {
@@ -169,61 +274,41 @@ impl VM {
             self.debug.extend_from_slice(&debug);
         }
 
+        let address = address as u32;
         match f3 {
             0b000 => {
                 // LB
-                let value = self.ram.byte_at(
-                    address
-                        .try_into()
-                        .or(Err(InstructionError::InvalidMemory))?,
-                )?;
+                let value = self.memory.read(address, Access::Byte)?;
                 self.cpu
                     .register
-                    .set(rsd, bitops::sign_extend(value as u32, 8) as u32);
+                    .set(rsd, bitops::sign_extend(value, 8) as u32);
                 Ok(())
             }
             0b001 => {
                 // LH
-                let value = self.ram.hw_at(
-                    address
-                        .try_into()
-                        .or(Err(InstructionError::InvalidMemory))?,
-                )?;
+                let value = self.memory.read(address, Access::HalfWord)?;
                 self.cpu
                     .register
-                    .set(rsd, bitops::sign_extend(value as u32, 16) as u32);
+                    .set(rsd, bitops::sign_extend(value, 16) as u32);
                 Ok(())
             }
             0b010 => {
                 // LW
-                self.cpu.register.set(
-                    rsd,
-                    self.ram.word_at(
-                        address
-                            .try_into()
-                            .or(Err(InstructionError::InvalidMemory))?,
-                    )?,
-                );
+                self.cpu
+                    .register
+                    .set(rsd, self.memory.read(address, Access::Word)?);
                 Ok(())
             }
             0b100 => {
                 // LBU
-                let value = self.ram.byte_at(
-                    address
-                        .try_into()
-                        .or(Err(InstructionError::InvalidMemory))?,
-                )?;
-                self.cpu.register.set(rsd, value as u32);
+                let value = self.memory.read(address, Access::Byte)?;
+                self.cpu.register.set(rsd, value);
                 Ok(())
             }
             0b101 => {
                 // LHU
-                let value = self.ram.hw_at(
-                    address
-                        .try_into()
-                        .or(Err(InstructionError::InvalidMemory))?,
-                )?;
-                self.cpu.register.set(rsd, value as u32);
+                let value = self.memory.read(address, Access::HalfWord)?;
+                self.cpu.register.set(rsd, value);
                 Ok(())
             }
             _ => Err(InstructionError::InvalidOperation(Operation::Load)),
@@ -0,0 +1,805 @@
+use std::{
+    io::{self, Read, SeekFrom, Write},
+    ops::Range,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    ecall::Ecall,
+    memory::{MemoryError, PAGE_SIZE},
+    rv32i::instr::instruction::InstructionError,
+    vfs::{Handle, OpenFlags, Vfs},
+    Halt, Register, VM,
+};
+
+const OPENAT: u32 = 56;
+const CLOSE: u32 = 57;
+const LSEEK: u32 = 62;
+const READ: u32 = 63;
+const WRITE: u32 = 64;
+const WRITEV: u32 = 66;
+const FSTAT: u32 = 80;
+const EXIT: u32 = 93;
+const EXIT_GROUP: u32 = 94;
+const CLOCK_GETTIME: u32 = 113;
+const UNAME: u32 = 160;
+const BRK: u32 = 214;
+const MUNMAP: u32 = 215;
+const MMAP: u32 = 222;
+const CLOCK_GETTIME64: u32 = 403;
+
+const ENOENT: i32 = 2;
+const EIO: i32 = 5;
+const EBADF: i32 = 9;
+const ENOMEM: i32 = 12;
+const EACCES: i32 = 13;
+const EFAULT: i32 = 14;
+const EEXIST: i32 = 17;
+const ENODEV: i32 = 19;
+const ENOTDIR: i32 = 20;
+const EISDIR: i32 = 21;
+const EINVAL: i32 = 22;
+const EFBIG: i32 = 27;
+const ENOSPC: i32 = 28;
+const ESPIPE: i32 = 29;
+const EROFS: i32 = 30;
+const ENOSYS: i32 = 38;
+
+const AT_FDCWD: u32 = -100i32 as u32;
+const O_ACCMODE: u32 = 3;
+const O_WRONLY: u32 = 1;
+const O_RDWR: u32 = 2;
+const O_CREAT: u32 = 0o100;
+const O_EXCL: u32 = 0o200;
+const O_TRUNC: u32 = 0o1000;
+const O_APPEND: u32 = 0o2000;
+
+const MAP_FIXED: u32 = 0x10;
+const MAP_ANONYMOUS: u32 = 0x20;
+
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// `struct stat64` of the generic 32-bit ABI.
+const STAT_SIZE: usize = 104;
+/// Fields of `struct utsname`.
+const UTSNAME_FIELD: usize = 65;
+const PATH_MAX: u32 = 4096;
+/// Most bytes moved by one read or write; the rest is a short count.
+const MAX_IO: u32 = 1 << 20;
+/// Most iovecs one `writev` takes.
+const IOV_MAX: u32 = 1024;
+
+const AT_NULL: u32 = 0;
+const AT_PHDR: u32 = 3;
+const AT_PHENT: u32 = 4;
+const AT_PHNUM: u32 = 5;
+const AT_PAGESZ: u32 = 6;
+const AT_ENTRY: u32 = 9;
+const AT_RANDOM: u32 = 25;
+/// Fixed, so runs repeat exactly.
+const RANDOM: [u8; 16] = *b"brrrt-at-random!";
+
+/// RAM given to Linux programs when no memory map is configured.
+pub const LINUX_RAM_SIZE: u64 = 64 << 20;
+
+/// Room kept free for the stack below its top, as by the default rlimit.
+pub const LINUX_STACK_RESERVE: u32 = 8 << 20;
+
+enum Fd {
+    Input(Box<dyn Read>),
+    Output(Box<dyn Write>),
+    File(Handle),
+}
+
+/// Linux user-mode system calls for static rv32 programs.
+///
+/// The number is in a7 and arguments in a0..a5, as in the RISC-V Linux ABI;
+/// a0 gets the result, or a negative errno. Covers console and file I/O,
+/// `brk` and anonymous `mmap` inside a fixed heap, the clocks, `uname` and
+/// exiting, which halts the VM. Other calls fail with `ENOSYS`.
+///
+/// Files are opened in a [`Vfs`], empty unless one is given, and descriptors
+/// map to its handles.
+pub struct Linux {
+    fds: Vec<Option<Fd>>,
+    vfs: Vfs,
+    heap: Range<u32>,
+    brk: u32,
+    /// Lowest mapping so far; mappings grow down from the end of the heap.
+    mmap_top: u32,
+    start: Instant,
+}
+
+impl Linux {
+    /// Program break starts at `heap.start`, mappings are taken from the end.
+    /// Console input is empty and output is discarded.
+    pub fn new(heap: Range<u32>) -> Self {
+        Self {
+            fds: vec![
+                Some(Fd::Input(Box::new(io::empty()))),
+                Some(Fd::Output(Box::new(io::sink()))),
+                Some(Fd::Output(Box::new(io::sink()))),
+            ],
+            vfs: Vfs::new(),
+            brk: heap.start,
+            mmap_top: heap.end,
+            heap,
+            start: Instant::now(),
+        }
+    }
+
+    /// Console on the host's stdin, stdout and stderr.
+    pub fn stdio(heap: Range<u32>) -> Self {
+        Self::new(heap)
+            .with_stdin(Box::new(io::stdin()))
+            .with_stdout(Box::new(io::stdout()))
+            .with_stderr(Box::new(io::stderr()))
+    }
+
+    pub fn with_stdin(mut self, input: Box<dyn Read>) -> Self {
+        self.fds[0] = Some(Fd::Input(input));
+        self
+    }
+
+    pub fn with_stdout(mut self, output: Box<dyn Write>) -> Self {
+        self.fds[1] = Some(Fd::Output(output));
+        self
+    }
+
+    pub fn with_stderr(mut self, output: Box<dyn Write>) -> Self {
+        self.fds[2] = Some(Fd::Output(output));
+        self
+    }
+
+    pub fn with_vfs(mut self, vfs: Vfs) -> Self {
+        self.vfs = vfs;
+        self
+    }
+
+    fn fd(&mut self, fd: u32) -> Result<&mut Fd, i32> {
+        self.fds
+            .get_mut(fd as usize)
+            .and_then(|f| f.as_mut())
+            .ok_or(EBADF)
+    }
+
+    fn call(&mut self, vm: &mut VM, nr: u32, a: [u32; 6]) -> Result<u32, i32> {
+        match nr {
+            OPENAT => self.openat(vm, a[0], a[1], a[2]),
+            CLOSE => match self.fds.get_mut(a[0] as usize).and_then(|f| f.take()) {
+                Some(Fd::File(handle)) => {
+                    self.vfs.close(handle);
+                    Ok(0)
+                }
+                Some(_) => Ok(0),
+                None => Err(EBADF),
+            },
+            LSEEK => self.lseek(vm, a[0], (a[1] as u64) << 32 | a[2] as u64, a[3], a[4]),
+            READ => self.read(vm, a[0], a[1], a[2]),
+            WRITE => self.write(vm, a[0], a[1], a[2]),
+            WRITEV => self.writev(vm, a[0], a[1], a[2]),
+            FSTAT => self.fstat(vm, a[0], a[1]),
+            EXIT | EXIT_GROUP => {
+                vm.halt(Halt::Exit(a[0] as i32));
+                Ok(0)
+            }
+            CLOCK_GETTIME => clock_gettime(vm, self.clock(a[0])?, a[1], false),
+            CLOCK_GETTIME64 => clock_gettime(vm, self.clock(a[0])?, a[1], true),
+            UNAME => {
+                let mut buf = [0; 6 * UTSNAME_FIELD];
+                let fields: [&[u8]; 6] =
+                    [b"Linux", b"brrrt", b"6.1.0", b"#1", b"riscv32", b"(none)"];
+                for (i, field) in fields.iter().enumerate() {
+                    let at = i * UTSNAME_FIELD;
+                    buf[at..at + field.len()].copy_from_slice(field);
+                }
+                vm.memory.write_slice(a[0], &buf).or(Err(EFAULT))?;
+                Ok(0)
+            }
+            BRK => Ok(self.brk(vm, a[0])),
+            MUNMAP => {
+                // Only the lowest mapping can be given back
+                if a[0] == self.mmap_top {
+                    let size = a[1].next_multiple_of(PAGE_SIZE);
+                    self.mmap_top = self.mmap_top.saturating_add(size).min(self.heap.end);
+                }
+                Ok(0)
+            }
+            MMAP => self.mmap(vm, a[1], a[3]),
+            _ => Err(ENOSYS),
+        }
+    }
+
+    fn openat(&mut self, vm: &mut VM, dirfd: u32, path: u32, flags: u32) -> Result<u32, i32> {
+        let path = vm.memory.read_cstr(path, PATH_MAX).or(Err(EFAULT))?;
+        let path = String::from_utf8(path).or(Err(ENOENT))?;
+        // There are no directory descriptors to resolve against
+        if dirfd != AT_FDCWD && !path.starts_with('/') {
+            return Err(EBADF);
+        }
+        let access = flags & O_ACCMODE;
+        let flags = OpenFlags {
+            read: access != O_WRONLY,
+            write: access == O_WRONLY || access == O_RDWR,
+            append: flags & O_APPEND != 0,
+            truncate: flags & O_TRUNC != 0,
+            create: flags & O_CREAT != 0,
+            exclusive: flags & O_EXCL != 0,
+        };
+        let file = self.vfs.open(&path, flags).map_err(|e| errno(&e))?;
+        let fd = match self.fds.iter().position(|f| f.is_none()) {
+            Some(fd) => {
+                self.fds[fd] = Some(Fd::File(file));
+                fd
+            }
+            None => {
+                self.fds.push(Some(Fd::File(file)));
+                self.fds.len() - 1
+            }
+        };
+        Ok(fd as u32)
+    }
+
+    /// `_llseek`, the 32-bit ABI's only seek: the new position goes to
+    /// `result`.
+    fn lseek(
+        &mut self,
+        vm: &mut VM,
+        fd: u32,
+        offset: u64,
+        result: u32,
+        whence: u32,
+    ) -> Result<u32, i32> {
+        let from = match whence {
+            0 => SeekFrom::Start(offset),
+            1 => SeekFrom::Current(offset as i64),
+            2 => SeekFrom::End(offset as i64),
+            _ => return Err(EINVAL),
+        };
+        let &mut Fd::File(handle) = self.fd(fd)? else {
+            return Err(ESPIPE);
+        };
+        let position = self.vfs.seek(handle, from).map_err(|e| errno(&e))?;
+        vm.memory.set_dword_at(result, position).or(Err(EFAULT))?;
+        Ok(0)
+    }
+
+    fn read(&mut self, vm: &mut VM, fd: u32, buf: u32, count: u32) -> Result<u32, i32> {
+        let mut data = vec![0; count.min(MAX_IO) as usize];
+        let n = match self.fd(fd)? {
+            Fd::Input(input) => input.read(&mut data),
+            &mut Fd::File(handle) => self.vfs.read(handle, &mut data),
+            Fd::Output(_) => return Err(EBADF),
+        }
+        .map_err(|e| errno(&e))?;
+        vm.memory.write_slice(buf, &data[..n]).or(Err(EFAULT))?;
+        Ok(n as u32)
+    }
+
+    fn write(&mut self, vm: &mut VM, fd: u32, buf: u32, count: u32) -> Result<u32, i32> {
+        let mut data = vec![0; count.min(MAX_IO) as usize];
+        vm.memory.read_slice(buf, &mut data).or(Err(EFAULT))?;
+        let n = match self.fd(fd)? {
+            Fd::Output(output) => output
+                .write_all(&data)
+                .and_then(|_| output.flush())
+                .map(|_| data.len()),
+            &mut Fd::File(handle) => self.vfs.write(handle, &data),
+            Fd::Input(_) => return Err(EBADF),
+        }
+        .map_err(|e| errno(&e))?;
+        Ok(n as u32)
+    }
+
+    fn writev(&mut self, vm: &mut VM, fd: u32, iov: u32, iovcnt: u32) -> Result<u32, i32> {
+        if iovcnt > IOV_MAX {
+            return Err(EINVAL);
+        }
+        let mut vecs = Vec::with_capacity(iovcnt as usize);
+        for i in 0..iovcnt {
+            let entry = iov.wrapping_add(i.wrapping_mul(8));
+            let base = vm.memory.word_at(entry).or(Err(EFAULT))?;
+            let len = vm.memory.word_at(entry.wrapping_add(4)).or(Err(EFAULT))?;
+            vecs.push((base, len));
+        }
+        // Like Linux, stop at the first short write or error and report
+        // what went out before it
+        let mut total = 0u32;
+        for (base, len) in vecs {
+            let room = MAX_IO - total;
+            if room == 0 {
+                break;
+            }
+            match self.write(vm, fd, base, len.min(room)) {
+                Ok(n) => {
+                    total += n;
+                    if n < len {
+                        break;
+                    }
+                }
+                Err(_) if total > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(total)
+    }
+
+    fn fstat(&mut self, vm: &mut VM, fd: u32, statbuf: u32) -> Result<u32, i32> {
+        let (mode, size, blksize, mtime) = match self.fd(fd)? {
+            &mut Fd::File(handle) => {
+                let stat = self.vfs.stat(handle).map_err(|e| errno(&e))?;
+                let mode = if stat.is_dir {
+                    S_IFDIR | 0o755
+                } else {
+                    S_IFREG | 0o644
+                };
+                (mode, stat.size, 4096, stat.modified)
+            }
+            _ => (S_IFCHR | 0o620, 0, 1024, Default::default()),
+        };
+        let mut buf = [0; STAT_SIZE];
+        let mut put = |at: usize, bytes: &[u8]| buf[at..at + bytes.len()].copy_from_slice(bytes);
+        put(16, &mode.to_le_bytes());
+        // nlink
+        put(20, &1u32.to_le_bytes());
+        put(48, &size.to_le_bytes());
+        put(56, &(blksize as u32).to_le_bytes());
+        put(64, &size.div_ceil(512).to_le_bytes());
+        // atime, mtime and ctime
+        for at in [72, 80, 88] {
+            put(at, &(mtime.as_secs() as u32).to_le_bytes());
+            put(at + 4, &mtime.subsec_nanos().to_le_bytes());
+        }
+        vm.memory.write_slice(statbuf, &buf).or(Err(EFAULT))?;
+        Ok(0)
+    }
+
+    /// Moves the break within the heap and returns where it ends up; a
+    /// failed move leaves it in place.
+    fn brk(&mut self, vm: &mut VM, addr: u32) -> u32 {
+        if (self.heap.start..=self.mmap_top).contains(&addr) {
+            // Memory handed out again must read as zero
+            let grown = addr.saturating_sub(self.brk);
+            if vm.memory.fill(self.brk, grown, 0).is_ok() {
+                self.brk = addr;
+            }
+        }
+        self.brk
+    }
+
+    /// Anonymous mappings only, placed below the previous one; the address
+    /// hint is ignored.
+    fn mmap(&mut self, vm: &mut VM, len: u32, flags: u32) -> Result<u32, i32> {
+        if flags & MAP_ANONYMOUS == 0 {
+            return Err(ENODEV);
+        }
+        if len == 0 || flags & MAP_FIXED != 0 {
+            return Err(EINVAL);
+        }
+        let size = len.checked_next_multiple_of(PAGE_SIZE).ok_or(ENOMEM)?;
+        let base = self
+            .mmap_top
+            .checked_sub(size)
+            .filter(|&base| base >= self.brk)
+            .ok_or(ENOMEM)?;
+        vm.memory.fill(base, size, 0).or(Err(ENOMEM))?;
+        self.mmap_top = base;
+        Ok(base)
+    }
+
+    /// Time on a clock, as seconds and nanoseconds.
+    fn clock(&self, id: u32) -> Result<(u64, u32), i32> {
+        let time = match id {
+            // REALTIME and REALTIME_COARSE
+            0 | 5 => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            // MONOTONIC, the CPU time clocks, MONOTONIC_RAW, MONOTONIC_COARSE
+            // and BOOTTIME all count from startup
+            1..=4 | 6 | 7 => self.start.elapsed(),
+            _ => return Err(EINVAL),
+        };
+        Ok((time.as_secs(), time.subsec_nanos()))
+    }
+}
+
+/// Writes a `timespec`, with 64-bit seconds for the time64 calls.
+fn clock_gettime(
+    vm: &mut VM,
+    (secs, nanos): (u64, u32),
+    tp: u32,
+    time64: bool,
+) -> Result<u32, i32> {
+    let written = if time64 {
+        let nanos_at = tp.checked_add(8).ok_or(EFAULT)?;
+        vm.memory
+            .set_dword_at(tp, secs)
+            .and_then(|_| vm.memory.set_dword_at(nanos_at, nanos as u64))
+    } else {
+        let nanos_at = tp.checked_add(4).ok_or(EFAULT)?;
+        vm.memory
+            .set_word_at(tp, secs as u32)
+            .and_then(|_| vm.memory.set_word_at(nanos_at, nanos))
+    };
+    written.or(Err(EFAULT))?;
+    Ok(0)
+}
+
+/// Host error codes differ between platforms, so only the kind is mapped.
+fn errno(err: &io::Error) -> i32 {
+    match err.kind() {
+        io::ErrorKind::NotFound => ENOENT,
+        io::ErrorKind::PermissionDenied => EACCES,
+        io::ErrorKind::AlreadyExists => EEXIST,
+        io::ErrorKind::InvalidInput => EINVAL,
+        io::ErrorKind::NotADirectory => ENOTDIR,
+        io::ErrorKind::IsADirectory => EISDIR,
+        io::ErrorKind::ReadOnlyFilesystem => EROFS,
+        io::ErrorKind::NotSeekable => ESPIPE,
+        io::ErrorKind::FileTooLarge => EFBIG,
+        io::ErrorKind::StorageFull => ENOSPC,
+        _ => EIO,
+    }
+}
+
+#[cfg(feature = "trace")]
+fn syscall_name(nr: u32) -> &'static str {
+    match nr {
+        OPENAT => "openat",
+        CLOSE => "close",
+        LSEEK => "lseek",
+        READ => "read",
+        WRITE => "write",
+        WRITEV => "writev",
+        FSTAT => "fstat",
+        EXIT => "exit",
+        EXIT_GROUP => "exit_group",
+        CLOCK_GETTIME => "clock_gettime",
+        UNAME => "uname",
+        BRK => "brk",
+        MUNMAP => "munmap",
+        MMAP => "mmap",
+        CLOCK_GETTIME64 => "clock_gettime64",
+        _ => "unknown",
+    }
+}
+
+/// Loaded executable, as described to it by the auxiliary vector.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Image {
+    pub entry: u32,
+    /// Address of the program headers, if they are loaded at all; without
+    /// it the vector says nothing about them.
+    pub phdr: Option<u32>,
+    pub phent: u32,
+    pub phnum: u32,
+}
+
+impl VM {
+    /// Lays out argc, argv, envp and auxv below SP as Linux does at exec,
+    /// strings and AT_RANDOM bytes above them, and leaves SP at argc.
+    ///
+    /// argc, argv and envp also go to a0..a2, for programs entered straight
+    /// at `main`.
+    pub fn push_initial_stack(
+        &mut self,
+        args: &[String],
+        env: &[String],
+        image: &Image,
+    ) -> Result<(), MemoryError> {
+        let mut sp = self.cpu.register.get(Register::X2);
+        sp = sp.wrapping_sub(RANDOM.len() as u32);
+        self.memory.write_slice(sp, &RANDOM)?;
+        let random = sp;
+        let mut strings = Vec::with_capacity(args.len() + env.len());
+        for s in args.iter().chain(env) {
+            sp = sp.wrapping_sub(s.len() as u32 + 1);
+            self.memory.write_cstr(sp, s.as_bytes())?;
+            strings.push(sp);
+        }
+        let (argv, envp) = strings.split_at(args.len());
+
+        let mut words = vec![args.len() as u32];
+        words.extend_from_slice(argv);
+        words.push(0);
+        words.extend_from_slice(envp);
+        words.push(0);
+        let headers = image.phdr.map(|phdr| {
+            [
+                (AT_PHDR, phdr),
+                (AT_PHENT, image.phent),
+                (AT_PHNUM, image.phnum),
+            ]
+        });
+        for (key, value) in headers.into_iter().flatten().chain([
+            (AT_PAGESZ, PAGE_SIZE),
+            (AT_ENTRY, image.entry),
+            (AT_RANDOM, random),
+            (AT_NULL, 0),
+        ]) {
+            words.extend_from_slice(&[key, value]);
+        }
+        // The ABI wants SP 16-byte aligned
+        sp = sp.wrapping_sub(4 * words.len() as u32) & !15;
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        self.memory.write_slice(sp, &bytes)?;
+
+        self.cpu.register.set(Register::X2, sp);
+        self.cpu.register.set(Register::X10, args.len() as u32);
+        self.cpu.register.set(Register::X11, sp + 4);
+        self.cpu
+            .register
+            .set(Register::X12, sp + 4 * (args.len() as u32 + 2));
+        Ok(())
+    }
+}
+
+impl Ecall for Linux {
+    fn ecall(&mut self, vm: &mut VM) -> Result<(), InstructionError> {
+        let reg = |r| vm.cpu.register.get(r);
+        let nr = reg(Register::X17);
+        let args = [
+            reg(Register::X10),
+            reg(Register::X11),
+            reg(Register::X12),
+            reg(Register::X13),
+            reg(Register::X14),
+            reg(Register::X15),
+        ];
+
+        let result = match self.call(vm, nr, args) {
+            Ok(value) => value,
+            Err(errno) => -errno as u32,
+        };
+        #[cfg(feature = "trace")]
+        eprintln!(
+            "linux: {} {} ({:#x}, {:#x}, {:#x}, {:#x}) -> {}",
+            syscall_name(nr),
+            nr,
+            args[0],
+            args[1],
+            args[2],
+            args[3],
+            result as i32
+        );
+        vm.cpu.register.set(Register::X10, result);
+        Ok(())
+    }
+
+    /// Back to the console and an empty heap. Files the guest wrote keep
+    /// their contents.
+    fn reset(&mut self) {
+        for fd in self.fds.drain(3..).flatten() {
+            if let Fd::File(handle) = fd {
+                self.vfs.close(handle);
+            }
+        }
+        self.brk = self.heap.start;
+        self.mmap_top = self.heap.end;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod linux {
+    use super::*;
+    use crate::{memory::FileMode, testing::Sink, MemoryMap, VM};
+
+    const HEAP: Range<u32> = 0x10000..0x20000;
+    const BUF: u32 = 0x1000;
+
+    fn vm() -> VM {
+        VM::new(MemoryMap::default().ram(0, 0x20000)).expect("valid map")
+    }
+
+    fn call(linux: &mut Linux, vm: &mut VM, nr: u32, args: &[u32]) -> i32 {
+        vm.cpu.register.set(Register::X17, nr);
+        for (i, &arg) in args.iter().enumerate() {
+            vm.cpu
+                .register
+                .set(Register::try_from(10 + i as u32).unwrap(), arg);
+        }
+        linux.ecall(vm).expect("syscall");
+        vm.cpu.register.get(Register::X10) as i32
+    }
+
+    #[test]
+    fn console_io() {
+        let out = Sink::default();
+        let mut vm = vm();
+        let mut linux = Linux::new(HEAP)
+            .with_stdin(Box::new(&b"in"[..]))
+            .with_stdout(Box::new(out.clone()));
+
+        vm.memory.write_slice(BUF, b"hello, world").unwrap();
+        assert_eq!(call(&mut linux, &mut vm, WRITE, &[1, BUF, 5]), 5);
+        // iovecs: ", " and "world"
+        for (i, word) in [BUF + 5, 2, BUF + 7, 5].iter().enumerate() {
+            vm.memory.set_word_at(0x100 + 4 * i as u32, *word).unwrap();
+        }
+        assert_eq!(call(&mut linux, &mut vm, WRITEV, &[1, 0x100, 2]), 7);
+        assert_eq!(*out.0.borrow(), b"hello, world");
+        // A faulting second iovec still reports the first
+        vm.memory.set_word_at(0x108, u32::MAX).unwrap();
+        assert_eq!(call(&mut linux, &mut vm, WRITEV, &[1, 0x100, 2]), 2);
+        assert_eq!(
+            call(&mut linux, &mut vm, WRITEV, &[1, 0x100, 1025]),
+            -EINVAL
+        );
+        assert_eq!(
+            call(&mut linux, &mut vm, WRITEV, &[1, u32::MAX, 1]),
+            -EFAULT
+        );
+        assert_eq!(*out.0.borrow(), b"hello, world, ");
+
+        assert_eq!(call(&mut linux, &mut vm, READ, &[0, BUF, 16]), 2);
+        let mut data = [0; 2];
+        vm.memory.read_slice(BUF, &mut data).unwrap();
+        assert_eq!(&data, b"in");
+        assert_eq!(call(&mut linux, &mut vm, READ, &[0, BUF, 16]), 0);
+        assert_eq!(call(&mut linux, &mut vm, WRITE, &[7, BUF, 1]), -EBADF);
+        assert_eq!(call(&mut linux, &mut vm, 0x7ff, &[]), -ENOSYS);
+    }
+
+    #[test]
+    fn files_go_through_the_vfs() {
+        let dir = std::env::temp_dir().join(format!("brrrt-{}-linux-root", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("temp root");
+        let mut vm = vm();
+        let vfs = Vfs::rooted(&dir, FileMode::Sync).expect("root exists");
+        let mut linux = Linux::new(HEAP).with_vfs(vfs);
+        vm.memory.write_cstr(0x200, b"/file").unwrap();
+
+        let flags = O_WRONLY | O_CREAT | O_TRUNC;
+        let fd = call(
+            &mut linux,
+            &mut vm,
+            OPENAT,
+            &[AT_FDCWD, 0x200, flags, 0o644],
+        );
+        assert_eq!(fd, 3);
+        vm.memory.write_slice(BUF, b"0123456789").unwrap();
+        assert_eq!(call(&mut linux, &mut vm, WRITE, &[3, BUF, 10]), 10);
+        assert_eq!(call(&mut linux, &mut vm, CLOSE, &[3]), 0);
+        assert_eq!(call(&mut linux, &mut vm, CLOSE, &[3]), -EBADF);
+
+        let fd = call(&mut linux, &mut vm, OPENAT, &[AT_FDCWD, 0x200, 0, 0]) as u32;
+        assert_eq!(call(&mut linux, &mut vm, FSTAT, &[fd, 0x400]), 0);
+        assert_eq!(vm.memory.word_at(0x400 + 16).unwrap(), S_IFREG | 0o644);
+        assert_eq!(vm.memory.dword_at(0x400 + 48).unwrap(), 10);
+        assert_eq!(call(&mut linux, &mut vm, LSEEK, &[fd, 0, 6, 0x300, 0]), 0);
+        assert_eq!(vm.memory.dword_at(0x300).unwrap(), 6);
+        assert_eq!(call(&mut linux, &mut vm, READ, &[fd, BUF, 16]), 4);
+        let mut data = [0; 4];
+        vm.memory.read_slice(BUF, &mut data).unwrap();
+        assert_eq!(&data, b"6789");
+        assert_eq!(
+            call(&mut linux, &mut vm, LSEEK, &[1, 0, 0, 0x300, 0]),
+            -ESPIPE
+        );
+
+        assert_eq!(std::fs::read(dir.join("file")).unwrap(), b"0123456789");
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(
+            call(&mut linux, &mut vm, OPENAT, &[AT_FDCWD, 0x200, 0, 0]),
+            -ENOENT
+        );
+        vm.memory.write_cstr(0x200, b"/../etc/passwd").unwrap();
+        assert_eq!(
+            call(&mut linux, &mut vm, OPENAT, &[AT_FDCWD, 0x200, 0, 0]),
+            -EACCES
+        );
+    }
+
+    #[test]
+    fn heap_and_mappings_share_the_range() {
+        let mut vm = vm();
+        let mut linux = Linux::new(HEAP);
+
+        assert_eq!(call(&mut linux, &mut vm, BRK, &[0]) as u32, HEAP.start);
+        assert_eq!(call(&mut linux, &mut vm, BRK, &[0x12000]) as u32, 0x12000);
+        let anon = MAP_ANONYMOUS | 0x02;
+        let map = call(
+            &mut linux,
+            &mut vm,
+            MMAP,
+            &[0, 0x3000, 3, anon, -1i32 as u32, 0],
+        );
+        assert_eq!(map as u32, 0x1d000);
+        // Break can't run into the mapping, nor mappings into the break
+        assert_eq!(call(&mut linux, &mut vm, BRK, &[0x1e000]) as u32, 0x12000);
+        let huge = call(
+            &mut linux,
+            &mut vm,
+            MMAP,
+            &[0, 0xc000, 3, anon, -1i32 as u32, 0],
+        );
+        assert_eq!(huge, -ENOMEM);
+        assert_eq!(
+            call(&mut linux, &mut vm, MMAP, &[0, 0x1000, 3, 0x02, 3, 0]),
+            -ENODEV
+        );
+
+        assert_eq!(call(&mut linux, &mut vm, MUNMAP, &[0x1d000, 0x3000]), 0);
+        assert_eq!(call(&mut linux, &mut vm, BRK, &[0x1e000]) as u32, 0x1e000);
+    }
+
+    #[test]
+    fn initial_stack() {
+        let mut vm = vm();
+        vm.initialize();
+        let image = Image {
+            entry: 0x1_0074,
+            phdr: Some(0x1_0034),
+            phent: 32,
+            phnum: 2,
+        };
+        let args = ["prg".to_owned(), "-v".to_owned()];
+        vm.push_initial_stack(&args, &["HOME=/".to_owned()], &image)
+            .expect("room for the stack");
+
+        let sp = vm.cpu.register.get(Register::X2);
+        assert_eq!(sp % 16, 0);
+        let word = |at: u32| vm.memory.word_at(at).unwrap();
+        let string = |at: u32| vm.memory.read_cstr(at, 16).unwrap();
+        assert_eq!(word(sp), 2);
+        assert_eq!(string(word(sp + 4)), b"prg");
+        assert_eq!(string(word(sp + 8)), b"-v");
+        assert_eq!(word(sp + 12), 0);
+        assert_eq!(string(word(sp + 16)), b"HOME=/");
+        assert_eq!(word(sp + 20), 0);
+
+        let auxv: Vec<(u32, u32)> = (0..7)
+            .map(|i| (word(sp + 24 + 8 * i), word(sp + 28 + 8 * i)))
+            .collect();
+        assert!(auxv.contains(&(AT_PAGESZ, 4096)));
+        assert!(auxv.contains(&(AT_ENTRY, 0x1_0074)));
+        assert!(auxv.contains(&(AT_PHDR, 0x1_0034)));
+        assert_eq!(auxv[6], (AT_NULL, 0));
+        let random = auxv.iter().find(|(key, _)| *key == AT_RANDOM).unwrap().1;
+        let mut bytes = [0; 16];
+        vm.memory.read_slice(random, &mut bytes).unwrap();
+        assert_eq!(bytes, RANDOM);
+
+        assert_eq!(vm.cpu.register.get(Register::X10), 2);
+        assert_eq!(vm.cpu.register.get(Register::X11), sp + 4);
+        assert_eq!(vm.cpu.register.get(Register::X12), sp + 16);
+
+        // Headers that aren't loaded are left out rather than pointing at 0
+        let image = Image {
+            phdr: None,
+            ..image
+        };
+        vm.push_initial_stack(&args, &[], &image)
+            .expect("room for the stack");
+        let sp = vm.cpu.register.get(Register::X2);
+        let keys: Vec<u32> = (0..4)
+            .map(|i| vm.memory.word_at(sp + 20 + 8 * i).unwrap())
+            .collect();
+        assert_eq!(keys, [AT_PAGESZ, AT_ENTRY, AT_RANDOM, AT_NULL]);
+    }
+
+    #[test]
+    fn exit_clock_and_uname() {
+        let mut vm = vm();
+        let mut linux = Linux::new(HEAP);
+
+        assert_eq!(call(&mut linux, &mut vm, CLOCK_GETTIME64, &[0, 0x100]), 0);
+        assert!(vm.memory.dword_at(0x100).unwrap() > 1_600_000_000);
+        assert_eq!(call(&mut linux, &mut vm, CLOCK_GETTIME, &[1, 0x100]), 0);
+        assert!(vm.memory.word_at(0x104).unwrap() < 1_000_000_000);
+        assert_eq!(
+            call(&mut linux, &mut vm, CLOCK_GETTIME, &[99, 0x100]),
+            -EINVAL
+        );
+
+        assert_eq!(call(&mut linux, &mut vm, UNAME, &[0x200]), 0);
+        assert_eq!(vm.memory.read_cstr(0x200, 65).unwrap(), b"Linux");
+        assert_eq!(vm.memory.read_cstr(0x200 + 4 * 65, 65).unwrap(), b"riscv32");
+
+        call(&mut linux, &mut vm, EXIT_GROUP, &[3]);
+        assert_eq!(vm.halted(), Some(&Halt::Exit(3)));
+    }
+}
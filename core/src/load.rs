@@ -20,7 +20,7 @@ mod byte {
         let mut vm: VM = Default::default();
         vm.cpu.register.set(Register::X12, 1);
         vm.cpu.register.set(Register::X13, 1);
-        vm.ram
+        vm.memory
             .set_byte_at(162, neg as u8)
             .expect("memory value set");
 
@@ -30,7 +30,7 @@ mod byte {
         assert_eq!(vm.cpu.register.get(Register::X13), 1);
         assert_eq!(vm.cpu.register.get(Register::X12), neg as u32);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.byte_at(162).expect("memory access"), neg as u8);
+        assert_eq!(vm.memory.byte_at(162).expect("memory access"), neg as u8);
     }
 
     #[test]
@@ -51,7 +51,7 @@ mod byte {
         let mut vm: VM = Default::default();
         vm.cpu.register.set(Register::X12, 1);
         vm.cpu.register.set(Register::X13, 161 + 32);
-        vm.ram
+        vm.memory
             .set_byte_at(161, negval as u8)
             .expect("memory value set");
 
@@ -61,7 +61,7 @@ mod byte {
         assert_eq!(vm.cpu.register.get(Register::X13), 161 + 32);
         assert_eq!(vm.cpu.register.get(Register::X12), negval as u32);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.byte_at(161).expect("memory access"), negval as u8);
+        assert_eq!(vm.memory.byte_at(161).expect("memory access"), negval as u8);
     }
 }
 
@@ -87,7 +87,7 @@ mod half_word {
         let mut vm: VM = Default::default();
         vm.cpu.register.set(Register::X12, 1);
         vm.cpu.register.set(Register::X13, 1);
-        vm.ram
+        vm.memory
             .set_hw_at(162, negval as u16)
             .expect("memory value set");
 
@@ -97,7 +97,7 @@ mod half_word {
         assert_eq!(vm.cpu.register.get(Register::X13), 1);
         assert_eq!(vm.cpu.register.get(Register::X12), negval as u32);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.hw_at(162).expect("memory access"), negval as u16);
+        assert_eq!(vm.memory.hw_at(162).expect("memory access"), negval as u16);
     }
 
     #[test]
@@ -118,7 +118,7 @@ mod half_word {
         let mut vm: VM = Default::default();
         vm.cpu.register.set(Register::X12, 1);
         vm.cpu.register.set(Register::X13, 161 + 32);
-        vm.ram
+        vm.memory
             .set_hw_at(161, negval as u16)
             .expect("memory value set");
 
@@ -128,7 +128,7 @@ mod half_word {
         assert_eq!(vm.cpu.register.get(Register::X13), 161 + 32);
         assert_eq!(vm.cpu.register.get(Register::X12), negval as u32);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.hw_at(161).expect("memory access"), negval as u16);
+        assert_eq!(vm.memory.hw_at(161).expect("memory access"), negval as u16);
     }
 }
 
@@ -152,7 +152,9 @@ mod word {
         let mut vm: VM = Default::default();
         vm.cpu.register.set(Register::X12, 1);
         vm.cpu.register.set(Register::X13, 1);
-        vm.ram.set_word_at(162, 1611312).expect("memory value set");
+        vm.memory
+            .set_word_at(162, 1611312)
+            .expect("memory value set");
 
         assert_eq!(vm.cpu.register.get(Register::PC), 0);
         vm.execute(i).expect("should execute");
@@ -160,7 +162,7 @@ mod word {
         assert_eq!(vm.cpu.register.get(Register::X13), 1);
         assert_eq!(vm.cpu.register.get(Register::X12), 1611312);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.word_at(162).expect("memory access"), 1611312);
+        assert_eq!(vm.memory.word_at(162).expect("memory access"), 1611312);
     }
 
     #[test]
@@ -179,7 +181,9 @@ mod word {
         let mut vm: VM = Default::default();
         vm.cpu.register.set(Register::X12, 1);
         vm.cpu.register.set(Register::X13, 161 + 32);
-        vm.ram.set_word_at(161, 1611312).expect("memory value set");
+        vm.memory
+            .set_word_at(161, 1611312)
+            .expect("memory value set");
 
         assert_eq!(vm.cpu.register.get(Register::PC), 0);
         vm.execute(i).expect("should execute");
@@ -187,7 +191,30 @@ mod word {
         assert_eq!(vm.cpu.register.get(Register::X13), 161 + 32);
         assert_eq!(vm.cpu.register.get(Register::X12), 1611312);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.word_at(161).expect("memory access"), 1611312);
+        assert_eq!(vm.memory.word_at(161).expect("memory access"), 1611312);
+    }
+
+    #[test]
+    fn load_high_address() {
+        let i = Instruction::parse(
+            Builder::opcode(Operation::Load)
+                .pack(Part::Dest, Register::X12 as u32)
+                .pack(Part::Funct3, 0b010)
+                .pack(Part::Reg1, Register::X13 as u32)
+                .pack(Part::Imm110, 4)
+                .build(),
+        )
+        .expect("should parse");
+
+        let mut vm = VM::new(MemoryMap::default().ram(0x8000_0000, 64)).expect("valid map");
+        vm.cpu.register.set(Register::X13, 0x8000_0000);
+        vm.memory
+            .set_word_at(0x8000_0004, 1611312)
+            .expect("memory value set");
+
+        vm.execute(i).expect("should execute");
+
+        assert_eq!(vm.cpu.register.get(Register::X12), 1611312);
     }
 }
 
@@ -211,7 +238,7 @@ mod byte_unsigned {
         let mut vm: VM = Default::default();
         vm.cpu.register.set(Register::X12, 1);
         vm.cpu.register.set(Register::X13, 1);
-        vm.ram.set_byte_at(162, 6).expect("memory value set");
+        vm.memory.set_byte_at(162, 6).expect("memory value set");
 
         assert_eq!(vm.cpu.register.get(Register::PC), 0);
         vm.execute(i).expect("should execute");
@@ -219,7 +246,7 @@ mod byte_unsigned {
         assert_eq!(vm.cpu.register.get(Register::X13), 1);
         assert_eq!(vm.cpu.register.get(Register::X12), 6);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.byte_at(162).expect("memory access"), 6);
+        assert_eq!(vm.memory.byte_at(162).expect("memory access"), 6);
     }
 
     #[test]
@@ -238,7 +265,7 @@ mod byte_unsigned {
         let mut vm: VM = Default::default();
         vm.cpu.register.set(Register::X12, 1);
         vm.cpu.register.set(Register::X13, 161 + 32);
-        vm.ram.set_byte_at(161, 6).expect("memory value set");
+        vm.memory.set_byte_at(161, 6).expect("memory value set");
 
         assert_eq!(vm.cpu.register.get(Register::PC), 0);
         vm.execute(i).expect("should execute");
@@ -246,7 +273,7 @@ mod byte_unsigned {
         assert_eq!(vm.cpu.register.get(Register::X13), 161 + 32);
         assert_eq!(vm.cpu.register.get(Register::X12), 6);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.byte_at(161).expect("memory access"), 6);
+        assert_eq!(vm.memory.byte_at(161).expect("memory access"), 6);
     }
 }
 
@@ -270,7 +297,7 @@ mod half_word_unsigned {
         let mut vm: VM = Default::default();
         vm.cpu.register.set(Register::X12, 1);
         vm.cpu.register.set(Register::X13, 1);
-        vm.ram.set_hw_at(162, 1312).expect("memory value set");
+        vm.memory.set_hw_at(162, 1312).expect("memory value set");
 
         assert_eq!(vm.cpu.register.get(Register::PC), 0);
         vm.execute(i).expect("should execute");
@@ -278,7 +305,7 @@ mod half_word_unsigned {
         assert_eq!(vm.cpu.register.get(Register::X13), 1);
         assert_eq!(vm.cpu.register.get(Register::X12), 1312);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.hw_at(162).expect("memory access"), 1312);
+        assert_eq!(vm.memory.hw_at(162).expect("memory access"), 1312);
     }
 
     #[test]
@@ -297,7 +324,7 @@ mod half_word_unsigned {
         let mut vm: VM = Default::default();
         vm.cpu.register.set(Register::X12, 1);
         vm.cpu.register.set(Register::X13, 161 + 32);
-        vm.ram.set_hw_at(161, 1312).expect("memory value set");
+        vm.memory.set_hw_at(161, 1312).expect("memory value set");
 
         assert_eq!(vm.cpu.register.get(Register::PC), 0);
         vm.execute(i).expect("should execute");
@@ -305,6 +332,6 @@ mod half_word_unsigned {
         assert_eq!(vm.cpu.register.get(Register::X13), 161 + 32);
         assert_eq!(vm.cpu.register.get(Register::X12), 1312);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.hw_at(161).expect("memory access"), 1312);
+        assert_eq!(vm.memory.hw_at(161).expect("memory access"), 1312);
     }
 }
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+const MAGIC: u32 = 0xd00d_feed;
+const VERSION: u32 = 17;
+const LAST_COMPATIBLE: u32 = 16;
+const HEADER_SIZE: usize = 40;
+/// A single, terminating reservation entry.
+const RESERVATIONS_SIZE: usize = 16;
+
+const BEGIN_NODE: u32 = 1;
+const END_NODE: u32 = 2;
+const PROP: u32 = 3;
+const END: u32 = 9;
+
+/// Writes a flattened device tree (DTB, version 17).
+///
+/// Nodes are opened and closed in order; properties belong to the innermost
+/// open node. Values are big-endian as the format requires.
+#[derive(Debug, Default)]
+pub struct Fdt {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+    offsets: HashMap<String, u32>,
+    depth: usize,
+}
+
+impl Fdt {
+    pub fn new() -> Self {
+        let mut fdt = Self::default();
+        fdt.begin_node("");
+        fdt
+    }
+
+    pub fn begin_node(&mut self, name: &str) {
+        self.token(BEGIN_NODE);
+        self.structure.extend_from_slice(name.as_bytes());
+        self.structure.push(0);
+        self.align();
+        self.depth += 1;
+    }
+
+    pub fn end_node(&mut self) {
+        self.token(END_NODE);
+        self.depth -= 1;
+    }
+
+    pub fn property(&mut self, name: &str, value: &[u8]) {
+        let offset = self.name_offset(name);
+        self.token(PROP);
+        self.structure
+            .extend_from_slice(&(value.len() as u32).to_be_bytes());
+        self.structure.extend_from_slice(&offset.to_be_bytes());
+        self.structure.extend_from_slice(value);
+        self.align();
+    }
+
+    /// Property without a value, e.g. `interrupt-controller`.
+    pub fn flag(&mut self, name: &str) {
+        self.property(name, &[]);
+    }
+
+    pub fn u32(&mut self, name: &str, value: u32) {
+        self.cells(name, &[value]);
+    }
+
+    pub fn cells(&mut self, name: &str, cells: &[u32]) {
+        let value: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes()).collect();
+        self.property(name, &value);
+    }
+
+    /// String list property; a single string is a list of one.
+    pub fn strings(&mut self, name: &str, values: &[&str]) {
+        let mut value = Vec::new();
+        for s in values {
+            value.extend_from_slice(s.as_bytes());
+            value.push(0);
+        }
+        self.property(name, &value);
+    }
+
+    /// Closes the root node and lays out the blob.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.end_node();
+        assert_eq!(self.depth, 0, "unbalanced device tree nodes");
+        self.token(END);
+
+        let off_reservations = HEADER_SIZE;
+        let off_structure = off_reservations + RESERVATIONS_SIZE;
+        let off_strings = off_structure + self.structure.len();
+        let total = off_strings + self.strings.len();
+        let header = [
+            MAGIC,
+            total as u32,
+            off_structure as u32,
+            off_strings as u32,
+            off_reservations as u32,
+            VERSION,
+            LAST_COMPATIBLE,
+            0,
+            self.strings.len() as u32,
+            self.structure.len() as u32,
+        ];
+
+        let mut blob: Vec<u8> = header.iter().flat_map(|w| w.to_be_bytes()).collect();
+        blob.resize(off_structure, 0);
+        blob.extend_from_slice(&self.structure);
+        blob.extend_from_slice(&self.strings);
+        blob
+    }
+
+    fn token(&mut self, token: u32) {
+        self.structure.extend_from_slice(&token.to_be_bytes());
+    }
+
+    fn align(&mut self) {
+        let len = self.structure.len().next_multiple_of(4);
+        self.structure.resize(len, 0);
+    }
+
+    fn name_offset(&mut self, name: &str) -> u32 {
+        if let Some(&offset) = self.offsets.get(name) {
+            return offset;
+        }
+        let offset = self.strings.len() as u32;
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        self.offsets.insert(name.to_owned(), offset);
+        offset
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod fdt {
+    use super::*;
+
+    fn word(blob: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes(blob[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn header_describes_layout() {
+        let mut fdt = Fdt::new();
+        fdt.u32("#address-cells", 2);
+        fdt.begin_node("chosen");
+        fdt.strings("bootargs", &["console=ttyS0"]);
+        fdt.end_node();
+        let blob = fdt.finish();
+
+        assert_eq!(word(&blob, 0), MAGIC);
+        assert_eq!(word(&blob, 4) as usize, blob.len());
+        assert_eq!(word(&blob, 20), 17);
+        let structure = word(&blob, 8) as usize;
+        let strings = word(&blob, 12) as usize;
+        assert_eq!(structure + word(&blob, 36) as usize, strings);
+        assert_eq!(&blob[strings..], b"#address-cells\0bootargs\0");
+        assert_eq!(word(&blob, strings - 4), END);
+    }
+
+    #[test]
+    fn properties_are_padded_and_names_shared() {
+        let mut fdt = Fdt::new();
+        fdt.strings("compatible", &["a", "bc"]);
+        fdt.strings("compatible", &["d"]);
+        let blob = fdt.finish();
+
+        let structure = word(&blob, 8) as usize;
+        // Root node: token and an empty, padded name
+        assert_eq!(word(&blob, structure), BEGIN_NODE);
+        let prop = structure + 8;
+        assert_eq!(word(&blob, prop), PROP);
+        assert_eq!(word(&blob, prop + 4), 5);
+        assert_eq!(word(&blob, prop + 8), 0);
+        assert_eq!(&blob[prop + 12..prop + 17], b"a\0bc\0");
+        let next = prop + 12 + 8;
+        assert_eq!(word(&blob, next), PROP);
+        assert_eq!(word(&blob, next + 8), 0);
+        assert_eq!(word(&blob, word(&blob, 12) as usize - 8), END_NODE);
+    }
+}
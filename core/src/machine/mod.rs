@@ -0,0 +1,389 @@
+mod fdt;
+
+pub use fdt::Fdt;
+
+use crate::{
+    devices::{
+        Clint, Clock, GoldfishRtc, IrqLine, Plic, TestFinisher, Uart16550, VirtioBlock,
+        VirtioEmpty, CLINT_SIZE, FINISHER_SIZE, PLIC_SIZE, PLIC_SOURCES, RTC_SIZE, UART_SIZE,
+        VIRTIO_SIZE,
+    },
+    memory::{MemoryError, MemoryMap, RegionKind},
+    Register, VM,
+};
+
+pub const VIRT_TEST: u32 = 0x0010_0000;
+pub const VIRT_RTC: u32 = 0x0010_1000;
+pub const VIRT_CLINT: u32 = 0x0200_0000;
+pub const VIRT_PLIC: u32 = 0x0c00_0000;
+pub const VIRT_UART: u32 = 0x1000_0000;
+/// First of [`VIRT_VIRTIO_SLOTS`] virtio-mmio transports.
+pub const VIRT_VIRTIO: u32 = 0x1000_1000;
+pub const VIRT_VIRTIO_STRIDE: u32 = 0x1000;
+pub const VIRT_VIRTIO_SLOTS: usize = 8;
+pub const VIRT_DRAM: u32 = 0x8000_0000;
+/// RAM size when none is given, the same as QEMU's.
+pub const VIRT_RAM_SIZE: u64 = 128 << 20;
+
+const VIRTIO_IRQ: usize = 1;
+const UART_IRQ: usize = 10;
+const RTC_IRQ: usize = 11;
+
+/// `mtime` ticks per second: one per instruction, matching the default
+/// virtual clock.
+const TIMEBASE: u32 = 100_000_000;
+const UART_CLOCK: u32 = 3_686_400;
+
+/// The device tree is placed below the top of RAM, aligned like QEMU does.
+const FDT_ALIGN: u64 = 2 << 20;
+const FDT_MAX: u64 = 64 << 10;
+
+const CPU_INTC_PHANDLE: u32 = 1;
+const PLIC_PHANDLE: u32 = 2;
+const TEST_PHANDLE: u32 = 3;
+
+/// Machine profile compatible with QEMU's `virt` board, with a single
+/// RV32I hart.
+///
+/// [`Virt::build`] attaches the devices at the `virt` addresses, wires their
+/// interrupts to the PLIC and hands the guest a device tree describing them.
+/// Interrupts are modelled up to the PLIC and CLINT outputs only: the core
+/// has no trap support to deliver them.
+pub struct Virt {
+    console: Uart16550,
+    clock: Clock,
+    disks: Vec<VirtioBlock>,
+    bootargs: Option<String>,
+}
+
+impl Virt {
+    pub fn new(console: Uart16550) -> Self {
+        Self {
+            console,
+            clock: Clock::default(),
+            disks: Vec::new(),
+            bootargs: None,
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Puts `disk` in the next free virtio slot. [`Virt::build`] fails if
+    /// there are more disks than [`VIRT_VIRTIO_SLOTS`].
+    pub fn with_disk(mut self, disk: VirtioBlock) -> Self {
+        self.disks.push(disk);
+        self
+    }
+
+    pub fn with_bootargs(mut self, bootargs: &str) -> Self {
+        self.bootargs = Some(bootargs.to_owned());
+        self
+    }
+
+    /// RAM of `size` bytes at the `virt` DRAM base.
+    pub fn memory_map(size: u64) -> MemoryMap {
+        MemoryMap::default().sparse_ram(VIRT_DRAM, size)
+    }
+
+    /// Where the device tree goes for RAM ending at `ram_top`.
+    ///
+    /// A top of 0 stands for the end of the address space.
+    pub fn fdt_address(ram_top: u32) -> u32 {
+        let top = if ram_top == 0 {
+            1 << 32
+        } else {
+            ram_top as u64
+        };
+        (top.saturating_sub(FDT_MAX) & !(FDT_ALIGN - 1)) as u32
+    }
+
+    /// Attaches the devices to `vm`, loads the device tree and sets up the
+    /// boot registers: the hart ID in a0 and the device tree address in a1.
+    ///
+    /// Returns the device tree address. The image goes below it, so RAM
+    /// has to leave it at least 2 MiB.
+    pub fn build(self, vm: &mut VM) -> Result<u32, MemoryError> {
+        if self.disks.len() > VIRT_VIRTIO_SLOTS {
+            return Err(MemoryError::TooManyDisks(self.disks.len()));
+        }
+        let dtb = self.device_tree(vm.memory.map());
+        let address = Self::fdt_address(vm.memory.map().stack_top().unwrap_or(0));
+        let ram = vm
+            .memory
+            .map()
+            .regions()
+            .iter()
+            .filter(|r| r.kind == RegionKind::Ram);
+        if let Some(region) = ram.max_by_key(|r| r.base) {
+            if (address as u64) < region.base as u64 + FDT_ALIGN {
+                return Err(MemoryError::NoRoomForFdt(region.clone()));
+            }
+        }
+
+        let mut plic = Plic::new();
+        let mut line = |id: usize| {
+            let irq = IrqLine::default();
+            plic = std::mem::take(&mut plic).connect(id, irq.clone());
+            irq
+        };
+        let uart_irq = line(UART_IRQ);
+        let rtc_irq = line(RTC_IRQ);
+        let virtio_irqs: Vec<_> = (0..VIRT_VIRTIO_SLOTS)
+            .map(|slot| line(VIRTIO_IRQ + slot))
+            .collect();
+
+        let memory = &mut vm.memory;
+        memory.attach(
+            "test",
+            VIRT_TEST,
+            FINISHER_SIZE,
+            Box::new(TestFinisher::new()),
+        )?;
+        memory.attach(
+            "rtc",
+            VIRT_RTC,
+            RTC_SIZE,
+            Box::new(GoldfishRtc::new(self.clock).with_irq(rtc_irq)),
+        )?;
+        memory.attach("clint", VIRT_CLINT, CLINT_SIZE, Box::new(Clint::new()))?;
+        memory.attach(
+            "uart",
+            VIRT_UART,
+            UART_SIZE,
+            Box::new(self.console.with_irq(uart_irq)),
+        )?;
+        let mut disks = self.disks.into_iter();
+        for (slot, irq) in virtio_irqs.into_iter().enumerate() {
+            let base = VIRT_VIRTIO + slot as u32 * VIRT_VIRTIO_STRIDE;
+            let name = format!("virtio{}", slot);
+            match disks.next() {
+                Some(disk) => {
+                    memory.attach(&name, base, VIRTIO_SIZE, Box::new(disk.with_irq(irq)))?
+                }
+                None => memory.attach(&name, base, VIRTIO_SIZE, Box::new(VirtioEmpty))?,
+            }
+        }
+        // Last, so sources are sampled after their devices have ticked
+        memory.attach("plic", VIRT_PLIC, PLIC_SIZE, Box::new(plic))?;
+
+        memory.load(address, &dtb)?;
+        vm.cpu.register.set(Register::X10, 0);
+        vm.cpu.register.set(Register::X11, address);
+        Ok(address)
+    }
+
+    /// Device tree describing `map` and the `virt` devices, laid out like
+    /// QEMU's.
+    pub fn device_tree(&self, map: &MemoryMap) -> Vec<u8> {
+        let mut fdt = Fdt::new();
+        fdt.u32("#address-cells", 2);
+        fdt.u32("#size-cells", 2);
+        fdt.strings("compatible", &["riscv-virtio"]);
+        fdt.strings("model", &["riscv-virtio,qemu"]);
+
+        fdt.begin_node("chosen");
+        if let Some(bootargs) = &self.bootargs {
+            fdt.strings("bootargs", &[bootargs]);
+        }
+        fdt.strings("stdout-path", &[&format!("/soc/serial@{:x}", VIRT_UART)]);
+        fdt.end_node();
+
+        for region in map.regions().iter().filter(|r| r.kind == RegionKind::Ram) {
+            fdt.begin_node(&format!("memory@{:x}", region.base));
+            fdt.strings("device_type", &["memory"]);
+            fdt.cells("reg", &reg(region.base, region.size));
+            fdt.end_node();
+        }
+
+        fdt.begin_node("cpus");
+        fdt.u32("#address-cells", 1);
+        fdt.u32("#size-cells", 0);
+        fdt.u32("timebase-frequency", TIMEBASE);
+        fdt.begin_node("cpu@0");
+        fdt.strings("device_type", &["cpu"]);
+        fdt.u32("reg", 0);
+        fdt.strings("status", &["okay"]);
+        fdt.strings("compatible", &["riscv"]);
+        fdt.strings("riscv,isa", &["rv32i"]);
+        fdt.begin_node("interrupt-controller");
+        fdt.u32("#interrupt-cells", 1);
+        fdt.flag("interrupt-controller");
+        fdt.strings("compatible", &["riscv,cpu-intc"]);
+        fdt.u32("phandle", CPU_INTC_PHANDLE);
+        fdt.end_node();
+        fdt.end_node();
+        fdt.end_node();
+
+        for (node, value) in [("poweroff", 0x5555), ("reboot", 0x7777)] {
+            fdt.begin_node(node);
+            fdt.strings("compatible", &[&format!("syscon-{}", node)]);
+            fdt.u32("regmap", TEST_PHANDLE);
+            fdt.u32("offset", 0);
+            fdt.u32("value", value);
+            fdt.end_node();
+        }
+
+        fdt.begin_node("soc");
+        fdt.u32("#address-cells", 2);
+        fdt.u32("#size-cells", 2);
+        fdt.strings("compatible", &["simple-bus"]);
+        fdt.flag("ranges");
+
+        fdt.begin_node(&format!("test@{:x}", VIRT_TEST));
+        fdt.strings("compatible", &["sifive,test1", "sifive,test0", "syscon"]);
+        fdt.cells("reg", &reg(VIRT_TEST, FINISHER_SIZE));
+        fdt.u32("phandle", TEST_PHANDLE);
+        fdt.end_node();
+
+        fdt.begin_node(&format!("rtc@{:x}", VIRT_RTC));
+        fdt.strings("compatible", &["google,goldfish-rtc"]);
+        fdt.cells("reg", &reg(VIRT_RTC, RTC_SIZE));
+        fdt.u32("interrupt-parent", PLIC_PHANDLE);
+        fdt.u32("interrupts", RTC_IRQ as u32);
+        fdt.end_node();
+
+        fdt.begin_node(&format!("serial@{:x}", VIRT_UART));
+        fdt.strings("compatible", &["ns16550a"]);
+        fdt.cells("reg", &reg(VIRT_UART, UART_SIZE));
+        fdt.u32("clock-frequency", UART_CLOCK);
+        fdt.u32("interrupt-parent", PLIC_PHANDLE);
+        fdt.u32("interrupts", UART_IRQ as u32);
+        fdt.end_node();
+
+        for slot in 0..VIRT_VIRTIO_SLOTS {
+            let base = VIRT_VIRTIO + slot as u32 * VIRT_VIRTIO_STRIDE;
+            fdt.begin_node(&format!("virtio_mmio@{:x}", base));
+            fdt.strings("compatible", &["virtio,mmio"]);
+            fdt.cells("reg", &reg(base, VIRT_VIRTIO_STRIDE as u64));
+            fdt.u32("interrupt-parent", PLIC_PHANDLE);
+            fdt.u32("interrupts", (VIRTIO_IRQ + slot) as u32);
+            fdt.end_node();
+        }
+
+        // Machine and supervisor external interrupts of hart 0
+        fdt.begin_node(&format!("plic@{:x}", VIRT_PLIC));
+        fdt.strings("compatible", &["sifive,plic-1.0.0", "riscv,plic0"]);
+        fdt.cells("reg", &reg(VIRT_PLIC, PLIC_SIZE));
+        fdt.u32("#address-cells", 0);
+        fdt.u32("#interrupt-cells", 1);
+        fdt.flag("interrupt-controller");
+        fdt.u32("riscv,ndev", PLIC_SOURCES as u32);
+        fdt.cells(
+            "interrupts-extended",
+            &[CPU_INTC_PHANDLE, 11, CPU_INTC_PHANDLE, 9],
+        );
+        fdt.u32("phandle", PLIC_PHANDLE);
+        fdt.end_node();
+
+        // Machine software and timer interrupts of hart 0
+        fdt.begin_node(&format!("clint@{:x}", VIRT_CLINT));
+        fdt.strings("compatible", &["sifive,clint0", "riscv,clint0"]);
+        fdt.cells("reg", &reg(VIRT_CLINT, CLINT_SIZE));
+        fdt.cells(
+            "interrupts-extended",
+            &[CPU_INTC_PHANDLE, 3, CPU_INTC_PHANDLE, 7],
+        );
+        fdt.end_node();
+
+        fdt.end_node();
+        fdt.finish()
+    }
+}
+
+/// `reg` cells with two-cell addresses and sizes.
+fn reg(base: u32, size: u64) -> [u32; 4] {
+    [0, base, (size >> 32) as u32, size as u32]
+}
+
+#[cfg(test)]
+mod virt {
+    use super::*;
+    use crate::{
+        memory::{Access, FileMode},
+        Bus, Halt,
+    };
+    use std::io;
+
+    fn machine() -> VM {
+        let mut vm = VM::new(Virt::memory_map(VIRT_RAM_SIZE)).expect("valid map");
+        let console = Uart16550::new(Box::new(io::sink()));
+        Virt::new(console)
+            .with_bootargs("console=ttyS0")
+            .build(&mut vm)
+            .expect("should build");
+        vm
+    }
+
+    #[test]
+    fn boot_registers_point_at_device_tree() {
+        let vm = machine();
+        let dtb = vm.cpu.register.get(Register::X11);
+
+        assert_eq!(vm.cpu.register.get(Register::X10), 0);
+        assert_eq!(dtb, 0x87e0_0000);
+        assert_eq!(vm.memory.word_at(dtb).unwrap(), 0xedfe_0dd0);
+        let size = u32::from_be(vm.memory.word_at(dtb + 4).unwrap());
+        let mut blob = vec![0; size as usize];
+        vm.memory.read_slice(dtb, &mut blob).unwrap();
+        for needle in [
+            &b"ns16550a"[..],
+            b"riscv,plic0",
+            b"console=ttyS0",
+            b"memory@80000000",
+        ] {
+            assert!(blob.windows(needle.len()).any(|w| w == needle));
+        }
+    }
+
+    #[test]
+    fn devices_sit_at_virt_addresses() {
+        let mut vm = machine();
+        let m = &mut vm.memory;
+
+        assert_eq!(m.read(VIRT_VIRTIO, Access::Word).unwrap(), 0x7472_6976);
+        // Empty slot
+        assert_eq!(m.read(VIRT_VIRTIO + 8, Access::Word).unwrap(), 0);
+        assert_ne!(m.read(VIRT_UART + 5, Access::Byte).unwrap(), 0);
+        assert_ne!(m.read(VIRT_RTC, Access::Word).unwrap(), u32::MAX);
+        m.write(VIRT_TEST, Access::Word, 0x5555).unwrap();
+        assert_eq!(m.take_halt(), Some(Halt::PowerOff));
+    }
+
+    #[test]
+    fn disks_must_fit_the_virtio_slots() {
+        let path = std::env::temp_dir().join(format!("brrrt-{}-virt-disks", std::process::id()));
+        std::fs::write(&path, [0; 512]).expect("temp image");
+        let mut virt = Virt::new(Uart16550::new(Box::new(io::sink())));
+        for _ in 0..=VIRT_VIRTIO_SLOTS {
+            virt = virt.with_disk(VirtioBlock::open(&path, FileMode::ReadOnly).expect("opens"));
+        }
+
+        let mut vm = VM::new(Virt::memory_map(VIRT_RAM_SIZE)).expect("valid map");
+        assert!(matches!(
+            virt.build(&mut vm),
+            Err(MemoryError::TooManyDisks(n)) if n == VIRT_VIRTIO_SLOTS + 1
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn ram_must_fit_image_and_fdt() {
+        for size in [32 << 10, 2 << 20] {
+            let mut vm = VM::new(Virt::memory_map(size)).expect("valid map");
+            let virt = Virt::new(Uart16550::new(Box::new(io::sink())));
+            assert!(matches!(
+                virt.build(&mut vm),
+                Err(MemoryError::NoRoomForFdt(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn fdt_stays_below_ram_top() {
+        assert_eq!(Virt::fdt_address(0x8800_0000), 0x87e0_0000);
+        assert_eq!(Virt::fdt_address(0), 0xffe0_0000);
+    }
+}
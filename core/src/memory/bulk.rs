@@ -0,0 +1,260 @@
+use super::{Memory, MemoryError, RegionKind, PAGE_SIZE};
+
+/// Host access to ranges of RAM and ROM.
+///
+/// Like [`Memory::byte_at`] and friends these bypass permissions, watchpoints
+/// and devices. Ranges may span adjacent regions; errors carry the first
+/// address that could not be accessed.
+impl Memory {
+    /// Calls `f` with each region-contiguous chunk of `[address, address + len)`.
+    fn chunks(
+        &self,
+        address: u32,
+        len: usize,
+        mut f: impl FnMut(usize, u32, usize) -> Result<(), u32>,
+    ) -> Result<(), u32> {
+        let mut done = 0;
+        while done < len {
+            let at = address.wrapping_add(done as u32);
+            if address as u64 + done as u64 >= 1 << 32 {
+                return Err(at);
+            }
+            let (bank, offset) = self.locate(at, 1).ok_or(at)?;
+            let region = &self.map.regions[bank];
+            let n = (len - done).min((region.size - offset as u64) as usize);
+            f(bank, offset, n).map_err(|_| at)?;
+            done += n;
+        }
+        Ok(())
+    }
+
+    pub fn read_slice(&self, address: u32, buf: &mut [u8]) -> Result<(), MemoryError> {
+        let mut done = 0;
+        self.chunks(address, buf.len(), |bank, offset, n| {
+            self.banks[bank].read(offset, &mut buf[done..done + n]);
+            done += n;
+            Ok(())
+        })
+        .map_err(MemoryError::Load)
+    }
+
+    /// Writes `data`, failing on the first byte outside RAM.
+    ///
+    /// Nothing is written unless the whole range is writable.
+    pub fn write_slice(&mut self, address: u32, data: &[u8]) -> Result<(), MemoryError> {
+        self.store(address, data, false)
+    }
+
+    /// Writes `data` to RAM, or ROM too if `rom` is set.
+    pub(super) fn store(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        rom: bool,
+    ) -> Result<(), MemoryError> {
+        let mut done = 0;
+        for (bank, offset, n) in self.writable(address, data.len(), rom)? {
+            self.banks[bank].write(offset, &data[done..done + n]);
+            done += n;
+        }
+        self.mark(address, data.len() as u32, true);
+        Ok(())
+    }
+
+    /// Chunks of `[address, address + len)`, if all of it is RAM, or ROM
+    /// too if `rom` is set.
+    fn writable(
+        &self,
+        address: u32,
+        len: usize,
+        rom: bool,
+    ) -> Result<Vec<(usize, u32, usize)>, MemoryError> {
+        let mut chunks = Vec::new();
+        self.chunks(address, len, |bank, offset, n| {
+            if !rom && self.map.regions[bank].kind != RegionKind::Ram {
+                return Err(offset);
+            }
+            chunks.push((bank, offset, n));
+            Ok(())
+        })
+        .map_err(MemoryError::Store)?;
+        Ok(chunks)
+    }
+
+    pub fn fill(&mut self, address: u32, len: u32, value: u8) -> Result<(), MemoryError> {
+        let page = [value; PAGE_SIZE as usize];
+        for (bank, offset, n) in self.writable(address, len as usize, false)? {
+            for at in (0..n).step_by(page.len()) {
+                let step = (n - at).min(page.len());
+                self.banks[bank].write(offset + at as u32, &page[..step]);
+            }
+        }
+        self.mark(address, len, true);
+        Ok(())
+    }
+
+    /// Copies `len` bytes from `src` to `dst`; the ranges may overlap.
+    pub fn copy(&mut self, dst: u32, src: u32, len: u32) -> Result<(), MemoryError> {
+        self.chunks(src, len as usize, |_, _, _| Ok(()))
+            .map_err(MemoryError::Load)?;
+        self.writable(dst, len as usize, false)?;
+
+        // A page at a time, from the end when that would overwrite source
+        // bytes not copied yet
+        let mut buf = [0; PAGE_SIZE as usize];
+        let pages = len.div_ceil(PAGE_SIZE);
+        let backwards = dst > src && dst - src < len;
+        for i in 0..pages {
+            let at = if backwards { pages - 1 - i } else { i } * PAGE_SIZE;
+            let n = (len - at).min(PAGE_SIZE) as usize;
+            self.read_slice(src + at, &mut buf[..n])?;
+            self.write_slice(dst + at, &buf[..n])?;
+        }
+        Ok(())
+    }
+
+    /// Reads a NUL-terminated string of at most `max` bytes, without the NUL.
+    pub fn read_cstr(&self, address: u32, max: u32) -> Result<Vec<u8>, MemoryError> {
+        let mut data = Vec::new();
+        for i in 0..max {
+            let at = address.wrapping_add(i);
+            let mut b = [0];
+            self.read_slice(at, &mut b)?;
+            if b[0] == 0 {
+                return Ok(data);
+            }
+            data.push(b[0]);
+        }
+        Err(MemoryError::Unterminated(address))
+    }
+
+    /// Writes `s` followed by a NUL.
+    pub fn write_cstr(&mut self, address: u32, s: &[u8]) -> Result<(), MemoryError> {
+        let mut data = Vec::with_capacity(s.len() + 1);
+        data.extend_from_slice(s);
+        data.push(0);
+        self.write_slice(address, &data)
+    }
+
+    /// Little-endian double word.
+    pub fn dword_at(&self, address: u32) -> Result<u64, MemoryError> {
+        let mut data = [0; 8];
+        self.read_slice(address, &mut data)?;
+        Ok(u64::from_le_bytes(data))
+    }
+
+    pub fn set_dword_at(&mut self, address: u32, dword: u64) -> Result<(), MemoryError> {
+        self.write_slice(address, &dword.to_le_bytes())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod bulk {
+    use super::*;
+    use crate::MemoryMap;
+
+    fn memory() -> Memory {
+        Memory::with_map(
+            MemoryMap::default()
+                .rom(0, 16)
+                .ram(16, 16)
+                .ram(32, 16)
+                .ram(0x100, 16),
+        )
+        .expect("valid map")
+    }
+
+    #[test]
+    fn slices_span_adjacent_regions() {
+        let mut m = memory();
+        m.write_slice(24, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12])
+            .expect("all RAM");
+        let mut buf = [0; 12];
+        m.read_slice(24, &mut buf).expect("all mapped");
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(m.byte_at(32).unwrap(), 9);
+    }
+
+    #[test]
+    fn errors_report_failing_address() {
+        let mut m = memory();
+        let mut buf = [0; 8];
+        assert!(matches!(
+            m.read_slice(44, &mut buf),
+            Err(MemoryError::Load(48))
+        ));
+        assert!(matches!(
+            m.write_slice(12, &[1; 8]),
+            Err(MemoryError::Store(12))
+        ));
+        assert!(matches!(
+            m.fill(0x108, 16, 0xff),
+            Err(MemoryError::Store(0x110))
+        ));
+        // Nothing is written when part of the range fails
+        assert_eq!(m.byte_at(0x108).unwrap(), 0);
+        assert!(matches!(
+            m.read_slice(u32::MAX, &mut buf),
+            Err(MemoryError::Load(u32::MAX))
+        ));
+    }
+
+    #[test]
+    fn fill_and_copy() {
+        let mut m = memory();
+        m.fill(16, 8, 0xaa).unwrap();
+        m.set_dword_at(20, 0x0102_0304_0506_0708).unwrap();
+        m.copy(18, 20, 8).unwrap();
+
+        assert_eq!(m.dword_at(18).unwrap(), 0x0102_0304_0506_0708);
+        assert_eq!(m.hw_at(16).unwrap(), 0xaaaa);
+    }
+
+    #[test]
+    fn bulk_ranges_are_checked_before_writing() {
+        let mut m = memory();
+        // Would be 4 GiB if allocated up front
+        assert!(matches!(
+            m.fill(16, u32::MAX, 0xff),
+            Err(MemoryError::Store(48))
+        ));
+        assert!(matches!(
+            m.copy(16, 0x100, u32::MAX),
+            Err(MemoryError::Load(0x110))
+        ));
+        assert_eq!(m.byte_at(16).unwrap(), 0);
+    }
+
+    #[test]
+    fn overlapping_copy_spans_pages() {
+        let mut m = Memory::new(4 * PAGE_SIZE);
+        let data: Vec<u8> = (0..2 * PAGE_SIZE).map(|i| (i % 251) as u8).collect();
+        m.write_slice(0, &data).unwrap();
+
+        m.copy(100, 0, 2 * PAGE_SIZE).unwrap();
+        let mut moved = vec![0; data.len()];
+        m.read_slice(100, &mut moved).unwrap();
+        assert_eq!(moved, data);
+
+        m.copy(0, 100, 2 * PAGE_SIZE).unwrap();
+        m.read_slice(0, &mut moved).unwrap();
+        assert_eq!(moved, data);
+    }
+
+    #[test]
+    fn c_strings() {
+        let mut m = memory();
+        m.write_cstr(0x100, b"brrrt").unwrap();
+        assert_eq!(m.read_cstr(0x100, 16).unwrap(), b"brrrt");
+        assert!(matches!(
+            m.read_cstr(0x100, 3),
+            Err(MemoryError::Unterminated(0x100))
+        ));
+        m.fill(0x100, 16, b'x').unwrap();
+        assert!(matches!(
+            m.read_cstr(0x100, 64),
+            Err(MemoryError::Load(0x110))
+        ));
+    }
+}
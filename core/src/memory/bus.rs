@@ -0,0 +1,407 @@
+use super::{Access, AccessKind, Memory, MemoryError};
+
+/// Address-decoded access path used by the VM for fetches, loads and stores.
+pub trait Bus {
+    fn fetch(&mut self, address: u32) -> Result<u32, MemoryError>;
+    fn read(&mut self, address: u32, access: Access) -> Result<u32, MemoryError>;
+    fn write(&mut self, address: u32, access: Access, value: u32) -> Result<(), MemoryError>;
+    /// Called once per retired instruction.
+    fn tick(&mut self);
+}
+
+/// Memory-mapped peripheral, attached to a region of the address space.
+///
+/// Offsets are relative to the region base.
+pub trait Device {
+    fn read(&mut self, offset: u32, access: Access) -> Result<u32, MemoryError>;
+    fn write(&mut self, offset: u32, access: Access, value: u32) -> Result<(), MemoryError>;
+    fn tick(&mut self) {}
+    /// Whether the device wants [`Device::service`] called.
+    fn pending(&self) -> bool {
+        false
+    }
+    /// Gives the device access to guest memory, e.g. to process DMA requests.
+    ///
+    /// Called after accesses and ticks while [`Device::pending`] is true. The
+    /// device's own registers read as unmapped in the meantime.
+    fn service(&mut self, _memory: &mut Memory) {}
+    /// Returns to the power-on state, as on a reboot. What the device is
+    /// connected to on the host, like files and IRQ lines, stays.
+    fn reset(&mut self) {}
+    /// Last failure the bus had no way to report, e.g. writing back to a
+    /// host file.
+    fn last_error(&self) -> Option<MemoryError> {
+        None
+    }
+}
+
+/// Stand-in for a device while it is being serviced.
+struct Detached;
+
+impl Device for Detached {
+    fn read(&mut self, _offset: u32, access: Access) -> Result<u32, MemoryError> {
+        Err(MemoryError::LoadAddress(access))
+    }
+
+    fn write(&mut self, _offset: u32, access: Access, _value: u32) -> Result<(), MemoryError> {
+        Err(MemoryError::StoreAddress(access))
+    }
+}
+
+impl Bus for Memory {
+    fn fetch(&mut self, address: u32) -> Result<u32, MemoryError> {
+        self.check(address, Access::Word, AccessKind::Execute)?;
+        self.count(address, AccessKind::Execute);
+        if let Some(cache) = self.icache.as_mut() {
+            cache.access(self.pc, address, Access::Word.size(), AccessKind::Read);
+        }
+        self.load_from(address, Access::Word)
+    }
+
+    fn read(&mut self, address: u32, access: Access) -> Result<u32, MemoryError> {
+        self.check(address, access, AccessKind::Read)?;
+        self.count(address, AccessKind::Read);
+        let value = self.load_from(address, access)?;
+        self.cache_data(address, access, AccessKind::Read);
+        if self.is_watched(address, access, AccessKind::Read) {
+            self.notify(address, access, AccessKind::Read, value, value);
+        }
+        Ok(value)
+    }
+
+    fn write(&mut self, address: u32, access: Access, value: u32) -> Result<(), MemoryError> {
+        self.check(address, access, AccessKind::Write)?;
+        self.count(address, AccessKind::Write);
+        let watched = self.is_watched(address, access, AccessKind::Write);
+        let old = if watched {
+            self.peek(address, access)
+        } else {
+            0
+        };
+        let device = self.device_at(address, access.size());
+        if let Some((idx, offset)) = device {
+            self.devices[idx].device.write(offset, access, value)?;
+            self.service(idx);
+        } else {
+            match access {
+                Access::Byte => self.set_byte_at(address, value as u8),
+                Access::HalfWord => self.set_hw_at(address, value as u16),
+                Access::Word => self.set_word_at(address, value),
+            }?;
+            self.cache_data(address, access, AccessKind::Write);
+        }
+        if watched {
+            // Device registers can't be peeked, so report what was stored
+            let new = match device {
+                Some(_) => value & (u32::MAX >> (32 - 8 * access.size())),
+                None => self.peek(address, access),
+            };
+            self.notify(address, access, AccessKind::Write, old, new);
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        for idx in 0..self.devices.len() {
+            self.devices[idx].device.tick();
+            self.service(idx);
+        }
+    }
+}
+
+impl Memory {
+    /// Reads RAM/ROM without side effects. Devices read as 0.
+    fn peek(&self, address: u32, access: Access) -> u32 {
+        match access {
+            Access::Byte => self.byte_at(address).map(|x| x as u32),
+            Access::HalfWord => self.hw_at(address).map(|x| x as u32),
+            Access::Word => self.word_at(address),
+        }
+        .unwrap_or(0)
+    }
+
+    fn count(&mut self, address: u32, kind: AccessKind) {
+        if let Some(stats) = self.stats.as_mut() {
+            stats.record(address, kind);
+        }
+    }
+
+    fn cache_data(&mut self, address: u32, access: Access, kind: AccessKind) {
+        if self.dcache.is_none() || self.device_at(address, access.size()).is_some() {
+            return;
+        }
+        if let Some(cache) = self.dcache.as_mut() {
+            cache.access(self.pc, address, access.size(), kind);
+        }
+    }
+
+    fn service(&mut self, idx: usize) {
+        if !self.devices[idx].device.pending() {
+            return;
+        }
+        let mut device = std::mem::replace(&mut self.devices[idx].device, Box::new(Detached));
+        device.service(self);
+        self.devices[idx].device = device;
+    }
+
+    fn load_from(&mut self, address: u32, access: Access) -> Result<u32, MemoryError> {
+        if let Some((idx, offset)) = self.device_at(address, access.size()) {
+            let value = self.devices[idx].device.read(offset, access)?;
+            self.service(idx);
+            return Ok(value);
+        }
+        match access {
+            Access::Byte => self.byte_at(address).map(|x| x as u32),
+            Access::HalfWord => self.hw_at(address).map(|x| x as u32),
+            Access::Word => self.word_at(address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::MemoryMap;
+
+    #[derive(Default)]
+    pub(super) struct Latch {
+        value: u32,
+        ticks: u32,
+    }
+
+    impl Device for Latch {
+        fn read(&mut self, offset: u32, _access: Access) -> Result<u32, MemoryError> {
+            Ok(if offset == 0 { self.value } else { self.ticks })
+        }
+
+        fn write(&mut self, _offset: u32, access: Access, value: u32) -> Result<(), MemoryError> {
+            if access != Access::Word {
+                return Err(MemoryError::StoreAddress(access));
+            }
+            self.value = value;
+            Ok(())
+        }
+
+        fn tick(&mut self) {
+            self.ticks += 1;
+        }
+    }
+
+    #[test]
+    fn routes_to_device() {
+        let mut m = Memory::new(16);
+        m.attach("latch", 0x1000, 8, Box::new(Latch::default()))
+            .expect("should attach");
+
+        m.write(0x1000, Access::Word, 1312).unwrap();
+        m.tick();
+        m.tick();
+
+        assert_eq!(m.read(0x1000, Access::Word).unwrap(), 1312);
+        assert_eq!(m.read(0x1004, Access::Word).unwrap(), 2);
+        assert!(m.write(0x1000, Access::Byte, 1).is_err());
+        assert!(m.byte_at(0x1000).is_err());
+    }
+
+    #[test]
+    fn routes_to_memory() {
+        let mut m = Memory::new(16);
+        m.write(8, Access::HalfWord, 0xffff_1312).unwrap();
+
+        assert_eq!(m.read(8, Access::Word).unwrap(), 0x1312);
+        assert_eq!(m.hw_at(8).unwrap(), 0x1312);
+    }
+
+    #[test]
+    fn overlapping_device_is_rejected() {
+        let mut m = Memory::with_map(MemoryMap::default().ram(0, 16)).expect("valid map");
+        assert!(m.attach("latch", 8, 8, Box::new(Latch::default())).is_err());
+        assert!(m.attach("latch", 16, 8, Box::new(Latch::default())).is_ok());
+        assert!(m
+            .attach("latch", 20, 8, Box::new(Latch::default()))
+            .is_err());
+    }
+}
+
+#[cfg(test)]
+mod cache {
+    use super::*;
+    use crate::memory::CacheConfig;
+
+    #[test]
+    fn caches_observe_accesses() {
+        let mut m = Memory::new(1024);
+        m.set_icache(CacheConfig::default()).expect("valid cache");
+        m.set_dcache(CacheConfig::default()).expect("valid cache");
+        m.set_pc(4);
+
+        m.fetch(4).unwrap();
+        m.fetch(8).unwrap();
+        m.write(512, Access::Word, 1312).unwrap();
+        assert_eq!(m.read(512, Access::Word).unwrap(), 1312);
+
+        let icache = m.icache().expect("icache set");
+        assert_eq!((icache.stats().hits, icache.stats().misses), (1, 1));
+        let dcache = m.dcache().expect("dcache set");
+        assert_eq!((dcache.stats().hits, dcache.stats().misses), (1, 1));
+        assert_eq!(dcache.stats_by_pc()[&4].accesses(), 2);
+    }
+
+    #[test]
+    fn invalid_geometry_is_rejected() {
+        let mut m = Memory::new(16);
+        assert!(m
+            .set_dcache(CacheConfig {
+                ways: 3,
+                ..Default::default()
+            })
+            .is_err());
+    }
+}
+
+#[cfg(test)]
+mod stats {
+    use super::*;
+
+    #[test]
+    fn stats_count_bus_accesses() {
+        let mut m = Memory::new(1024);
+        m.enable_stats(64).expect("power of two");
+        m.fetch(0).unwrap();
+        m.write(512, Access::Byte, 1).unwrap();
+        m.read(520, Access::Word).unwrap();
+        m.set_word_at(600, 1).unwrap();
+
+        let stats = m.stats().expect("stats enabled");
+        assert_eq!(stats.get(0).fetches, 1);
+        assert_eq!(stats.get(512).total(), 2);
+        assert_eq!(stats.get(600).total(), 0);
+        assert!(m.enable_stats(48).is_err());
+    }
+}
+
+#[cfg(test)]
+mod permissions {
+    use super::*;
+    use crate::memory::{MemoryMap, Permissions};
+
+    #[test]
+    fn rom_store_faults() {
+        let mut m = Memory::with_map(MemoryMap::default().rom(0, 16)).expect("valid map");
+        m.set_pc(8);
+
+        match m.write(4, Access::Word, 1) {
+            Err(MemoryError::AccessFault(fault)) => {
+                assert_eq!(fault.kind, AccessKind::Write);
+                assert_eq!(fault.address, 4);
+                assert_eq!(fault.pc, 8);
+                assert_eq!(fault.region, "rom0");
+            }
+            x => panic!("expected access fault, got {:?}", x),
+        }
+        assert!(m.fetch(4).is_ok());
+    }
+
+    #[test]
+    fn protection_overrides_region() {
+        let mut m = Memory::new(32);
+        m.protect(".text", 0, 16, Permissions::RX);
+        m.protect(".rodata", 16, 8, Permissions::R);
+
+        assert!(m.fetch(12).is_ok());
+        assert!(m.write(12, Access::Byte, 1).is_err());
+        assert!(m.read(16, Access::Word).is_ok());
+        match m.fetch(16) {
+            Err(MemoryError::AccessFault(fault)) => assert_eq!(fault.region, ".rodata"),
+            x => panic!("expected access fault, got {:?}", x),
+        }
+        assert!(m.write(24, Access::Word, 1).is_ok());
+        assert!(m.fetch(24).is_ok());
+    }
+
+    #[test]
+    fn access_straddling_protection_faults() {
+        let mut m = Memory::new(32);
+        m.protect(".rodata", 16, 8, Permissions::R);
+
+        assert!(m.write(14, Access::Word, 1).is_err());
+    }
+}
+
+#[cfg(test)]
+mod watch {
+    use super::test::Latch;
+    use super::*;
+    use crate::memory::{Watch, WatchAction, WatchEvent};
+    use crate::Halt;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn write_reports_old_and_new_values() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let seen = events.clone();
+        let mut m = Memory::new(32);
+        m.set_word_at(8, 0x0102_0304).unwrap();
+        m.watch(8, 4, Watch::Write, move |e| {
+            seen.borrow_mut().push(e.clone());
+            WatchAction::Continue
+        });
+        m.set_pc(64);
+
+        m.write(9, Access::Byte, 0xff).unwrap();
+        m.read(8, Access::Word).unwrap();
+        m.write(12, Access::Word, 1).unwrap();
+
+        assert_eq!(
+            *events.borrow(),
+            vec![WatchEvent {
+                pc: 64,
+                address: 9,
+                access: Access::Byte,
+                kind: AccessKind::Write,
+                old: 0x03,
+                new: 0xff,
+            }]
+        );
+        assert!(m.take_halt().is_none());
+    }
+
+    #[test]
+    fn callback_can_stop_execution() {
+        let mut m = Memory::new(32);
+        let id = m.watch(16, 4, Watch::ReadWrite, |e| {
+            if e.new == 1312 {
+                WatchAction::Stop
+            } else {
+                WatchAction::Continue
+            }
+        });
+
+        m.write(16, Access::Word, 161).unwrap();
+        assert!(m.take_halt().is_none());
+        m.write(16, Access::Word, 1312).unwrap();
+        assert_eq!(m.take_halt(), Some(Halt::Watchpoint(16)));
+
+        assert!(m.unwatch(id));
+        assert!(!m.unwatch(id));
+    }
+
+    #[test]
+    fn device_write_reports_stored_value() {
+        let mut m = Memory::new(16);
+        m.attach("latch", 0x1000, 8, Box::new(Latch::default()))
+            .expect("should attach");
+        m.watch(0x1000, 4, Watch::Write, |e| {
+            if e.new == 0x12 {
+                WatchAction::Stop
+            } else {
+                WatchAction::Continue
+            }
+        });
+
+        m.write(0x1000, Access::Word, 0x1312).unwrap();
+        assert!(m.take_halt().is_none());
+        m.write(0x1000, Access::Word, 0x12).unwrap();
+        assert_eq!(m.take_halt(), Some(Halt::Watchpoint(0x1000)));
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+
+use super::AccessKind;
+
+/// Which line of a full set gets evicted.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Replacement {
+    /// Least recently used.
+    #[default]
+    Lru,
+    /// Oldest fill.
+    Fifo,
+    /// Pseudo-random, from a fixed seed so runs are reproducible.
+    Random,
+}
+
+/// How stores reach the next level.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WritePolicy {
+    /// Stores allocate and dirty the line; dirty lines are written back on eviction.
+    #[default]
+    WriteBack,
+    /// Stores go straight through and don't allocate on a miss.
+    WriteThrough,
+}
+
+/// Geometry and timing of a cache. Sizes are in bytes and must be powers of two.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheConfig {
+    pub size: u32,
+    pub ways: u32,
+    pub line: u32,
+    pub replacement: Replacement,
+    pub write: WritePolicy,
+    /// Cycles added for each miss and each write-back.
+    pub miss_penalty: u64,
+}
+
+impl CacheConfig {
+    pub fn is_valid(&self) -> bool {
+        self.size.is_power_of_two()
+            && self.ways.is_power_of_two()
+            && self.line.is_power_of_two()
+            && self.line >= 4
+            && self
+                .ways
+                .checked_mul(self.line)
+                .is_some_and(|way| self.size >= way)
+    }
+
+    fn sets(&self) -> u32 {
+        self.size / (self.ways * self.line)
+    }
+}
+
+impl Default for CacheConfig {
+    /// 16 KiB, 4-way, 64-byte lines.
+    fn default() -> Self {
+        Self {
+            size: 16 * 1024,
+            ways: 4,
+            line: 64,
+            replacement: Replacement::default(),
+            write: WritePolicy::default(),
+            miss_penalty: 10,
+        }
+    }
+}
+
+/// Hit and miss counters.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub writebacks: u64,
+    /// Penalty cycles spent on misses and write-backs.
+    pub cycles: u64,
+}
+
+impl CacheStats {
+    pub fn accesses(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        match self.accesses() {
+            0 => 0.0,
+            n => self.hits as f64 / n as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Line {
+    tag: u32,
+    valid: bool,
+    dirty: bool,
+    /// Last use for LRU, fill time for FIFO.
+    stamp: u64,
+}
+
+/// Timing model of a set-associative cache.
+///
+/// Only tags are tracked: data always comes from memory, so the cache never
+/// changes what the program computes.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    config: CacheConfig,
+    lines: Vec<Line>,
+    clock: u64,
+    seed: u32,
+    stats: CacheStats,
+    by_pc: HashMap<u32, CacheStats>,
+}
+
+impl Cache {
+    pub(crate) fn new(config: CacheConfig) -> Self {
+        Self {
+            lines: vec![Line::default(); (config.sets() * config.ways) as usize],
+            config,
+            clock: 0,
+            seed: 0x2545_f491,
+            stats: CacheStats::default(),
+            by_pc: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &CacheStats {
+        &self.stats
+    }
+
+    /// Counters per PC of the instruction making the access.
+    pub fn stats_by_pc(&self) -> &HashMap<u32, CacheStats> {
+        &self.by_pc
+    }
+
+    /// Records an access of `len` bytes, touching every line it spans.
+    pub(crate) fn access(&mut self, pc: u32, address: u32, len: u32, kind: AccessKind) {
+        let first = address / self.config.line;
+        let last = address.saturating_add(len.max(1) - 1) / self.config.line;
+        for line in first..=last {
+            let before = self.stats;
+            self.access_line(line, kind);
+            let counts = self.by_pc.entry(pc).or_default();
+            counts.hits += self.stats.hits - before.hits;
+            counts.misses += self.stats.misses - before.misses;
+            counts.writebacks += self.stats.writebacks - before.writebacks;
+            counts.cycles += self.stats.cycles - before.cycles;
+        }
+    }
+
+    fn access_line(&mut self, line: u32, kind: AccessKind) {
+        self.clock += 1;
+        let sets = self.config.sets();
+        let (set, tag) = (line % sets, line / sets);
+        let ways = self.config.ways as usize;
+        let start = set as usize * ways;
+        let write = kind == AccessKind::Write;
+        let through = self.config.write == WritePolicy::WriteThrough;
+
+        if let Some(hit) = self.lines[start..start + ways]
+            .iter_mut()
+            .find(|l| l.valid && l.tag == tag)
+        {
+            self.stats.hits += 1;
+            if self.config.replacement == Replacement::Lru {
+                hit.stamp = self.clock;
+            }
+            hit.dirty |= write && !through;
+            return;
+        }
+
+        self.stats.misses += 1;
+        self.stats.cycles += self.config.miss_penalty;
+        if write && through {
+            return;
+        }
+        let victim = start + self.victim(start, ways);
+        let evicted = self.lines[victim];
+        if evicted.valid && evicted.dirty {
+            self.stats.writebacks += 1;
+            self.stats.cycles += self.config.miss_penalty;
+        }
+        self.lines[victim] = Line {
+            tag,
+            valid: true,
+            dirty: write,
+            stamp: self.clock,
+        };
+    }
+
+    fn victim(&mut self, start: usize, ways: usize) -> usize {
+        let set = &self.lines[start..start + ways];
+        if let Some(free) = set.iter().position(|l| !l.valid) {
+            return free;
+        }
+        match self.config.replacement {
+            Replacement::Lru | Replacement::Fifo => set
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, l)| l.stamp)
+                .map_or(0, |(idx, _)| idx),
+            Replacement::Random => {
+                // xorshift32
+                self.seed ^= self.seed << 13;
+                self.seed ^= self.seed >> 17;
+                self.seed ^= self.seed << 5;
+                self.seed as usize % ways
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod cache {
+    use super::*;
+
+    fn config(replacement: Replacement, write: WritePolicy) -> CacheConfig {
+        CacheConfig {
+            size: 64,
+            ways: 2,
+            line: 16,
+            replacement,
+            write,
+            miss_penalty: 10,
+        }
+    }
+
+    #[test]
+    fn repeated_access_hits() {
+        let mut c = Cache::new(config(Replacement::Lru, WritePolicy::WriteBack));
+        c.access(0, 0, 4, AccessKind::Read);
+        c.access(4, 12, 4, AccessKind::Read);
+        c.access(4, 14, 4, AccessKind::Read);
+
+        assert_eq!(c.stats().hits, 2);
+        assert_eq!(c.stats().misses, 2);
+        assert_eq!(c.stats().cycles, 20);
+        assert_eq!(c.stats_by_pc()[&4].hits, 2);
+    }
+
+    #[test]
+    fn lru_keeps_recently_used_line() {
+        // Lines 0, 2 and 4 all map to set 0
+        let mut c = Cache::new(config(Replacement::Lru, WritePolicy::WriteBack));
+        c.access(0, 0, 4, AccessKind::Read);
+        c.access(0, 32, 4, AccessKind::Read);
+        c.access(0, 0, 4, AccessKind::Read);
+        c.access(0, 64, 4, AccessKind::Read);
+        c.access(0, 0, 4, AccessKind::Read);
+        assert_eq!(c.stats().hits, 2);
+
+        let mut c = Cache::new(config(Replacement::Fifo, WritePolicy::WriteBack));
+        c.access(0, 0, 4, AccessKind::Read);
+        c.access(0, 32, 4, AccessKind::Read);
+        c.access(0, 0, 4, AccessKind::Read);
+        c.access(0, 64, 4, AccessKind::Read);
+        c.access(0, 0, 4, AccessKind::Read);
+        assert_eq!(c.stats().hits, 1);
+    }
+
+    #[test]
+    fn dirty_lines_are_written_back() {
+        let mut c = Cache::new(config(Replacement::Lru, WritePolicy::WriteBack));
+        c.access(0, 0, 4, AccessKind::Write);
+        c.access(0, 32, 4, AccessKind::Read);
+        c.access(0, 64, 4, AccessKind::Read);
+        assert_eq!(c.stats().writebacks, 1);
+        assert_eq!(c.stats().cycles, 40);
+    }
+
+    #[test]
+    fn write_through_does_not_allocate() {
+        let mut c = Cache::new(config(Replacement::Lru, WritePolicy::WriteThrough));
+        c.access(0, 0, 4, AccessKind::Write);
+        c.access(0, 0, 4, AccessKind::Read);
+        c.access(0, 0, 4, AccessKind::Write);
+        assert_eq!(c.stats().misses, 2);
+        assert_eq!(c.stats().hits, 1);
+        assert_eq!(c.stats().writebacks, 0);
+    }
+
+    #[test]
+    fn config_must_be_powers_of_two() {
+        assert!(CacheConfig::default().is_valid());
+        assert!(!CacheConfig {
+            size: 48,
+            ..Default::default()
+        }
+        .is_valid());
+        assert!(!CacheConfig {
+            size: 4096,
+            ways: 65536,
+            line: 65536,
+            ..Default::default()
+        }
+        .is_valid());
+    }
+}
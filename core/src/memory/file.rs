@@ -0,0 +1,150 @@
+use std::{fs, path::PathBuf};
+
+use super::{Backend, Memory, MemoryError, Permissions, Region, RegionKind, Storage};
+
+/// How a host file mapped into guest memory can be modified.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FileMode {
+    /// Mapped as ROM.
+    #[default]
+    ReadOnly,
+    /// Writable copy; the file is left untouched.
+    Private,
+    /// Writable copy, written back to the file by [`Memory::sync_files`].
+    Sync,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct MappedFile {
+    path: PathBuf,
+    region: usize,
+    mode: FileMode,
+}
+
+impl Memory {
+    /// Adds a region at `base` holding the contents of the file at `path`.
+    ///
+    /// The region is as large as the file and must not overlap anything else.
+    pub fn map_file(
+        &mut self,
+        path: impl Into<PathBuf>,
+        base: u32,
+        mode: FileMode,
+    ) -> Result<(), MemoryError> {
+        let path = path.into();
+        let data = fs::read(&path).map_err(|e| MemoryError::Io(e.kind()))?;
+        let (kind, permissions) = match mode {
+            FileMode::ReadOnly => (RegionKind::Rom, Permissions::RX),
+            FileMode::Private | FileMode::Sync => (RegionKind::Ram, Permissions::RWX),
+        };
+        let region = Region {
+            name: path.display().to_string(),
+            base,
+            size: data.len() as u64,
+            kind,
+            backend: Backend::Flat,
+            permissions,
+        };
+        if base as u64 + region.size > 1 << 32 {
+            return Err(MemoryError::OutOfRange(region));
+        }
+        let taken = self
+            .map
+            .regions
+            .iter()
+            .chain(self.devices.iter().map(|d| &d.region))
+            .find(|r| r.overlaps(&region));
+        if let Some(other) = taken {
+            return Err(MemoryError::Overlap(region, other.clone()));
+        }
+
+        self.banks.push(Storage::new(Backend::Flat, region.size));
+        self.map.regions.push(region);
+        self.load(base, &data)?;
+        self.files.push(MappedFile {
+            path,
+            region: self.map.regions.len() - 1,
+            mode,
+        });
+        Ok(())
+    }
+
+    /// Writes the contents of regions mapped with [`FileMode::Sync`] back to
+    /// their files, then reports any write-back a device failed earlier.
+    pub fn sync_files(&self) -> Result<(), MemoryError> {
+        for file in self.files.iter().filter(|f| f.mode == FileMode::Sync) {
+            let region = &self.map.regions[file.region];
+            let mut data = vec![0; region.size as usize];
+            self.banks[file.region].read(0, &mut data);
+            fs::write(&file.path, data).map_err(|e| MemoryError::Io(e.kind()))?;
+        }
+        match self.devices.iter().find_map(|d| d.device.last_error()) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod file {
+    use super::*;
+    use crate::memory::{Access, Bus};
+
+    fn host_file(name: &str, data: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("brrrt-{}-{}", std::process::id(), name));
+        fs::write(&path, data).expect("temp file");
+        path
+    }
+
+    #[test]
+    fn read_only_mapping() {
+        let path = host_file("ro", &[1, 2, 3, 4, 5]);
+        let mut m = Memory::new(64);
+        m.map_file(&path, 0x1000, FileMode::ReadOnly)
+            .expect("should map");
+
+        assert_eq!(m.read(0x1000, Access::Word).unwrap(), 0x0403_0201);
+        assert_eq!(m.byte_at(0x1004).unwrap(), 5);
+        assert!(m.byte_at(0x1005).is_err());
+        assert!(m.write(0x1000, Access::Byte, 0).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn private_mapping_leaves_file_alone() {
+        let path = host_file("private", &[1, 2, 3, 4]);
+        let mut m = Memory::new(64);
+        m.map_file(&path, 0x1000, FileMode::Private)
+            .expect("should map");
+
+        m.write(0x1000, Access::Byte, 9).unwrap();
+        m.sync_files().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), [1, 2, 3, 4]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sync_mapping_writes_back() {
+        let path = host_file("sync", &[1, 2, 3, 4]);
+        let mut m = Memory::new(64);
+        m.map_file(&path, 0x1000, FileMode::Sync)
+            .expect("should map");
+
+        m.write(0x1002, Access::HalfWord, 0x0807).unwrap();
+        m.sync_files().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), [1, 2, 7, 8]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn overlapping_mapping_is_rejected() {
+        let path = host_file("overlap", &[0; 8]);
+        let mut m = Memory::new(64);
+        assert!(m.map_file(&path, 60, FileMode::ReadOnly).is_err());
+        assert!(m
+            .map_file("/nonexistent/brrrt", 0x1000, FileMode::ReadOnly)
+            .is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,902 @@
+mod bulk;
+mod bus;
+mod cache;
+mod file;
+mod protection;
+mod shadow;
+mod stats;
+mod storage;
+mod watch;
+
+use std::cell::Cell;
+
+use crate::Halt;
+pub use bus::{Bus, Device};
+pub use cache::{Cache, CacheConfig, CacheStats, Replacement, WritePolicy};
+pub use file::FileMode;
+use file::MappedFile;
+use protection::Protection;
+pub use protection::{AccessKind, Fault, Permissions};
+use shadow::ShadowMap;
+pub use stats::{AccessCounts, AccessStats};
+use storage::Storage;
+pub use storage::{Backend, PAGE_SIZE};
+pub use watch::{Watch, WatchAction, WatchEvent, WatchId};
+use watch::{WatchCallback, Watchpoint};
+
+/// Guest physical address space, made up of RAM and ROM regions and
+/// memory-mapped devices.
+#[derive(Debug)]
+pub struct Memory {
+    map: MemoryMap,
+    banks: Vec<Storage>,
+    devices: Vec<Mapped>,
+    protections: Vec<Protection>,
+    watches: Vec<Watchpoint>,
+    next_watch: usize,
+    halt: Option<Halt>,
+    shadow: Option<ShadowMap>,
+    icache: Option<Cache>,
+    dcache: Option<Cache>,
+    files: Vec<MappedFile>,
+    stats: Option<AccessStats>,
+    pc: u32,
+    /// Region of the last located access, checked first.
+    hint: Cell<usize>,
+}
+
+struct Mapped {
+    region: Region,
+    device: Box<dyn Device>,
+}
+
+impl std::fmt::Debug for Mapped {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Device({})", self.region)
+    }
+}
+
+#[derive(Debug)]
+pub enum MemoryError {
+    LoadAddress(Access),
+    StoreAddress(Access),
+    /// First address of a range that can't be read.
+    Load(u32),
+    /// First address of a range that can't be written.
+    Store(u32),
+    /// No NUL within the allowed length of the string starting here.
+    Unterminated(u32),
+    Overlap(Region, Region),
+    OutOfRange(Region),
+    Unattached(Region),
+    AccessFault(Fault),
+    InvalidCache(CacheConfig),
+    SnapshotMismatch,
+    Io(std::io::ErrorKind),
+    InvalidGranularity(u32),
+    /// More disks than a board has virtio slots.
+    TooManyDisks(usize),
+    /// RAM too small for both the image and a device tree above it.
+    NoRoomForFdt(Region),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Byte,
+    HalfWord,
+    Word,
+}
+
+impl Access {
+    pub fn size(&self) -> u32 {
+        match self {
+            Self::Byte => 1,
+            Self::HalfWord => 2,
+            Self::Word => 4,
+        }
+    }
+}
+
+impl From<MemoryError> for String {
+    fn from(e: MemoryError) -> Self {
+        match e {
+            MemoryError::LoadAddress(access) => format!("Invalid {:?} load", access),
+            MemoryError::StoreAddress(access) => format!("Invalid {:?} store", access),
+            MemoryError::Load(address) => format!("Invalid load at {:#010x}", address),
+            MemoryError::Store(address) => format!("Invalid store at {:#010x}", address),
+            MemoryError::Unterminated(address) => {
+                format!("Unterminated string at {:#010x}", address)
+            }
+            MemoryError::Overlap(a, b) => format!("Region {} overlaps {}", a, b),
+            MemoryError::OutOfRange(r) => format!("Region {} exceeds address space", r),
+            MemoryError::Unattached(r) => format!("Region {} has no device attached", r),
+            MemoryError::AccessFault(fault) => fault.to_string(),
+            MemoryError::InvalidCache(c) => format!(
+                "Invalid cache geometry: {} bytes, {} ways, {} byte lines",
+                c.size, c.ways, c.line
+            ),
+            MemoryError::InvalidGranularity(g) => {
+                format!("Statistics granularity {} is not a power of two", g)
+            }
+            MemoryError::Io(kind) => format!("Mapped file error: {}", kind),
+            MemoryError::TooManyDisks(n) => format!("{} disks don't fit the virtio slots", n),
+            MemoryError::NoRoomForFdt(r) => {
+                format!("Region {} has no room for a device tree above the image", r)
+            }
+            MemoryError::SnapshotMismatch => {
+                "Snapshot taken with a different memory map".to_owned()
+            }
+        }
+    }
+}
+
+/// RAM and ROM contents captured by [`Memory::snapshot`].
+#[derive(Debug, Clone)]
+pub struct MemorySnapshot {
+    map: MemoryMap,
+    banks: Vec<Storage>,
+    shadow: Option<ShadowMap>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegionKind {
+    Ram,
+    Rom,
+    Device,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub name: String,
+    pub base: u32,
+    pub size: u64,
+    pub kind: RegionKind,
+    pub backend: Backend,
+    pub permissions: Permissions,
+}
+
+impl Region {
+    pub fn contains(&self, address: u32, len: u32) -> bool {
+        address >= self.base && (address - self.base) as u64 + len as u64 <= self.size
+    }
+
+    fn overlaps(&self, other: &Region) -> bool {
+        let end = self.base as u64 + self.size;
+        let other_end = other.base as u64 + other.size;
+        (self.base as u64) < other_end && (other.base as u64) < end
+    }
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} [{:#010x}..{:#010x}) {}",
+            self.name,
+            self.base,
+            self.base as u64 + self.size,
+            self.permissions
+        )
+    }
+}
+
+/// Layout of the guest address space.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMap {
+    regions: Vec<Region>,
+}
+
+impl MemoryMap {
+    pub fn ram(self, base: u32, size: u64) -> Self {
+        self.region(RegionKind::Ram, base, size, Backend::Flat)
+    }
+
+    pub fn rom(self, base: u32, size: u64) -> Self {
+        self.region(RegionKind::Rom, base, size, Backend::Flat)
+    }
+
+    /// RAM backed by lazily allocated pages, for large or far-apart regions.
+    pub fn sparse_ram(self, base: u32, size: u64) -> Self {
+        self.region(RegionKind::Ram, base, size, Backend::Sparse)
+    }
+
+    pub fn region(mut self, kind: RegionKind, base: u32, size: u64, backend: Backend) -> Self {
+        let count = self.regions.iter().filter(|r| r.kind == kind).count();
+        let (name, permissions) = match kind {
+            RegionKind::Ram => (format!("ram{}", count), Permissions::RWX),
+            RegionKind::Rom => (format!("rom{}", count), Permissions::RX),
+            RegionKind::Device => (format!("device{}", count), Permissions::RW),
+        };
+        self.regions.push(Region {
+            name,
+            base,
+            size,
+            kind,
+            backend,
+            permissions,
+        });
+        self
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Initial stack pointer: the top of the highest RAM region.
+    pub fn stack_top(&self) -> Option<u32> {
+        self.regions
+            .iter()
+            .filter(|r| r.kind == RegionKind::Ram)
+            .max_by_key(|r| r.base)
+            .map(|r| (r.base as u64 + r.size) as u32)
+    }
+}
+
+impl Memory {
+    /// Single RAM region of `pool` bytes, starting at address 0.
+    pub fn new(pool: u32) -> Self {
+        Self::with_map(MemoryMap::default().ram(0, pool as u64)).expect("single region")
+    }
+
+    /// Sparse RAM spanning the whole 32-bit address space.
+    pub fn sparse() -> Self {
+        Self::with_map(MemoryMap::default().sparse_ram(0, 1 << 32)).expect("single region")
+    }
+
+    pub fn with_map(map: MemoryMap) -> Result<Self, MemoryError> {
+        for (i, a) in map.regions.iter().enumerate() {
+            if a.base as u64 + a.size > 1 << 32 {
+                return Err(MemoryError::OutOfRange(a.clone()));
+            }
+            if a.kind == RegionKind::Device {
+                return Err(MemoryError::Unattached(a.clone()));
+            }
+            for b in &map.regions[i + 1..] {
+                if a.overlaps(b) {
+                    return Err(MemoryError::Overlap(a.clone(), b.clone()));
+                }
+            }
+        }
+        let banks = map
+            .regions
+            .iter()
+            .map(|r| Storage::new(r.backend, r.size))
+            .collect();
+        Ok(Self {
+            map,
+            banks,
+            devices: Vec::new(),
+            protections: Vec::new(),
+            watches: Vec::new(),
+            next_watch: 0,
+            halt: None,
+            shadow: None,
+            icache: None,
+            dcache: None,
+            files: Vec::new(),
+            stats: None,
+            pc: 0,
+            hint: Cell::new(0),
+        })
+    }
+
+    pub fn map(&self) -> &MemoryMap {
+        &self.map
+    }
+
+    /// Maps a device into the address space.
+    pub fn attach(
+        &mut self,
+        name: &str,
+        base: u32,
+        size: u64,
+        device: Box<dyn Device>,
+    ) -> Result<(), MemoryError> {
+        let region = Region {
+            name: name.to_owned(),
+            base,
+            size,
+            kind: RegionKind::Device,
+            backend: Backend::Flat,
+            permissions: Permissions::RW,
+        };
+        if base as u64 + size > 1 << 32 {
+            return Err(MemoryError::OutOfRange(region));
+        }
+        let taken = self
+            .map
+            .regions
+            .iter()
+            .chain(self.devices.iter().map(|d| &d.region))
+            .find(|r| r.overlaps(&region));
+        if let Some(other) = taken {
+            return Err(MemoryError::Overlap(region, other.clone()));
+        }
+        self.devices.push(Mapped { region, device });
+        Ok(())
+    }
+
+    /// Attached device regions.
+    pub fn devices(&self) -> impl Iterator<Item = &Region> {
+        self.devices.iter().map(|d| &d.region)
+    }
+
+    /// Overrides permissions for a range, e.g. for a loaded ELF section.
+    ///
+    /// Later protections take precedence over earlier ones.
+    pub fn protect(&mut self, name: &str, base: u32, size: u64, permissions: Permissions) {
+        self.protections.push(Protection {
+            name: name.to_owned(),
+            base,
+            size,
+            permissions,
+        });
+    }
+
+    /// Calls `callback` on bus accesses overlapping the range.
+    pub fn watch(
+        &mut self,
+        base: u32,
+        size: u64,
+        on: Watch,
+        callback: impl FnMut(&WatchEvent) -> WatchAction + 'static,
+    ) -> WatchId {
+        let id = WatchId(self.next_watch);
+        self.next_watch += 1;
+        self.watches.push(Watchpoint {
+            id,
+            base,
+            size,
+            on,
+            callback: Box::new(callback) as WatchCallback,
+        });
+        id
+    }
+
+    pub fn unwatch(&mut self, id: WatchId) -> bool {
+        let count = self.watches.len();
+        self.watches.retain(|w| w.id != id);
+        count != self.watches.len()
+    }
+
+    fn is_watched(&self, address: u32, access: Access, kind: AccessKind) -> bool {
+        self.watches
+            .iter()
+            .any(|w| w.triggers(address, access.size(), kind))
+    }
+
+    fn notify(&mut self, address: u32, access: Access, kind: AccessKind, old: u32, new: u32) {
+        let event = WatchEvent {
+            pc: self.pc,
+            address,
+            access,
+            kind,
+            old,
+            new,
+        };
+        for w in self.watches.iter_mut() {
+            if w.triggers(address, access.size(), kind) && (w.callback)(&event) == WatchAction::Stop
+            {
+                self.halt = Some(Halt::Watchpoint(address));
+            }
+        }
+    }
+
+    /// Asks the VM to stop after the current instruction.
+    pub fn request_halt(&mut self, reason: Halt) {
+        self.halt = Some(reason);
+    }
+
+    /// Takes the pending request to stop execution, if any.
+    pub fn take_halt(&mut self) -> Option<Halt> {
+        self.halt.take()
+    }
+
+    /// PC of the instruction on whose behalf the bus is accessed.
+    pub fn set_pc(&mut self, pc: u32) {
+        self.pc = pc;
+    }
+
+    pub(crate) fn check(
+        &self,
+        address: u32,
+        access: Access,
+        kind: AccessKind,
+    ) -> Result<(), MemoryError> {
+        let len = access.size();
+        let protection = self
+            .protections
+            .iter()
+            .rev()
+            .find(|p| p.overlaps(address, len))
+            .map(|p| (&p.name, p.permissions));
+        let found = protection.or_else(|| {
+            self.map
+                .regions
+                .iter()
+                .chain(self.devices.iter().map(|d| &d.region))
+                .find(|r| r.contains(address, len))
+                .map(|r| (&r.name, r.permissions))
+        });
+        match found {
+            Some((name, permissions)) if !permissions.allows(kind) => {
+                Err(MemoryError::AccessFault(Fault {
+                    pc: self.pc,
+                    address,
+                    access,
+                    kind,
+                    region: name.clone(),
+                    permissions,
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn device_at(&self, address: u32, len: u32) -> Option<(usize, u32)> {
+        self.devices
+            .iter()
+            .position(|d| d.region.contains(address, len))
+            .map(|idx| (idx, address - self.devices[idx].region.base))
+    }
+
+    /// Models an instruction cache in front of fetches from RAM and ROM.
+    pub fn set_icache(&mut self, config: CacheConfig) -> Result<(), MemoryError> {
+        if !config.is_valid() {
+            return Err(MemoryError::InvalidCache(config));
+        }
+        self.icache = Some(Cache::new(config));
+        Ok(())
+    }
+
+    /// Models a data cache in front of loads and stores to RAM and ROM.
+    pub fn set_dcache(&mut self, config: CacheConfig) -> Result<(), MemoryError> {
+        if !config.is_valid() {
+            return Err(MemoryError::InvalidCache(config));
+        }
+        self.dcache = Some(Cache::new(config));
+        Ok(())
+    }
+
+    pub fn icache(&self) -> Option<&Cache> {
+        self.icache.as_ref()
+    }
+
+    pub fn dcache(&self) -> Option<&Cache> {
+        self.dcache.as_ref()
+    }
+
+    /// Starts counting bus accesses per block of `granularity` bytes, e.g.
+    /// per page or per cache line.
+    pub fn enable_stats(&mut self, granularity: u32) -> Result<(), MemoryError> {
+        if !granularity.is_power_of_two() {
+            return Err(MemoryError::InvalidGranularity(granularity));
+        }
+        self.stats = Some(AccessStats::new(granularity));
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Option<&AccessStats> {
+        self.stats.as_ref()
+    }
+
+    /// RAM, ROM or device region holding `address`.
+    pub fn region_at(&self, address: u32) -> Option<&Region> {
+        self.map
+            .regions
+            .iter()
+            .chain(self.devices.iter().map(|d| &d.region))
+            .find(|r| r.contains(address, 1))
+    }
+
+    /// Starts tracking which bytes have been initialized.
+    ///
+    /// Everything is undefined until written by a store or [`Memory::load`],
+    /// so this should be enabled before the program image is loaded.
+    pub fn enable_shadow(&mut self) {
+        self.shadow.get_or_insert_with(ShadowMap::default);
+    }
+
+    /// Whether every byte in the range has been initialized.
+    ///
+    /// Always true when shadowing is off, and for device regions.
+    pub fn is_defined(&self, address: u32, len: u32) -> bool {
+        match &self.shadow {
+            Some(shadow) => {
+                self.device_at(address, len).is_some() || shadow.is_defined(address, len)
+            }
+            None => true,
+        }
+    }
+
+    pub(crate) fn mark(&mut self, address: u32, len: u32, defined: bool) {
+        if let Some(shadow) = self.shadow.as_mut() {
+            shadow.mark(address, len, defined);
+        }
+    }
+
+    /// Captures RAM and ROM contents.
+    ///
+    /// Pages are shared with the snapshot and only copied once written, so
+    /// taking one costs a reference per page.
+    pub fn snapshot(&self) -> MemorySnapshot {
+        MemorySnapshot {
+            map: self.map.clone(),
+            banks: self.banks.clone(),
+            shadow: self.shadow.clone(),
+        }
+    }
+
+    /// Rolls RAM and ROM contents back to `snapshot`.
+    ///
+    /// Devices, protections and watchpoints are left as they are.
+    pub fn restore(&mut self, snapshot: &MemorySnapshot) -> Result<(), MemoryError> {
+        if snapshot.map.regions != self.map.regions {
+            return Err(MemoryError::SnapshotMismatch);
+        }
+        self.banks.clone_from(&snapshot.banks);
+        self.shadow.clone_from(&snapshot.shadow);
+        Ok(())
+    }
+
+    /// Puts every attached device back in its power-on state.
+    pub fn reset_devices(&mut self) {
+        for attached in &mut self.devices {
+            attached.device.reset();
+        }
+    }
+
+    /// Copy sharing all pages with `self`.
+    ///
+    /// Devices and watchpoints hold host state that can't be duplicated, so
+    /// the fork starts without them.
+    pub fn fork(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+            banks: self.banks.clone(),
+            devices: Vec::new(),
+            protections: self.protections.clone(),
+            watches: Vec::new(),
+            next_watch: 0,
+            halt: None,
+            shadow: self.shadow.clone(),
+            icache: self.icache.clone(),
+            dcache: self.dcache.clone(),
+            files: Vec::new(),
+            stats: self.stats.clone(),
+            pc: self.pc,
+            hint: self.hint.clone(),
+        }
+    }
+
+    /// Pages not shared with any snapshot or fork, i.e. written since.
+    pub fn private_pages(&self) -> usize {
+        self.banks.iter().map(|b| b.private_pages()).sum()
+    }
+
+    /// Host memory currently backing the guest address space, in bytes.
+    pub fn resident(&self) -> u64 {
+        self.banks.iter().map(|b| b.resident()).sum()
+    }
+
+    fn locate(&self, address: u32, len: u32) -> Option<(usize, u32)> {
+        let regions = &self.map.regions;
+        let idx = match regions.get(self.hint.get()) {
+            Some(r) if r.contains(address, len) => self.hint.get(),
+            _ => {
+                let idx = regions.iter().position(|r| r.contains(address, len))?;
+                self.hint.set(idx);
+                idx
+            }
+        };
+        Some((idx, address - regions[idx].base))
+    }
+
+    fn read_bytes(&self, address: u32, buf: &mut [u8]) -> Option<()> {
+        let (bank, offset) = self.locate(address, buf.len() as u32)?;
+        self.banks[bank].read(offset, buf);
+        Some(())
+    }
+
+    fn write_bytes(&mut self, address: u32, data: &[u8]) -> Option<()> {
+        let (bank, offset) = self.locate(address, data.len() as u32)?;
+        if self.map.regions[bank].kind != RegionKind::Ram {
+            return None;
+        }
+        self.banks[bank].write(offset, data);
+        self.mark(address, data.len() as u32, true);
+        Some(())
+    }
+
+    /// Writes an image, ignoring ROM write protection.
+    pub fn load(&mut self, address: u32, data: &[u8]) -> Result<(), MemoryError> {
+        self.store(address, data, true)
+    }
+
+    pub fn byte_at(&self, address: u32) -> Result<u8, MemoryError> {
+        let mut data = [0; 1];
+        self.read_bytes(address, &mut data)
+            .ok_or(MemoryError::LoadAddress(Access::Byte))?;
+        Ok(data[0])
+    }
+
+    pub fn set_byte_at(&mut self, address: u32, b: u8) -> Result<(), MemoryError> {
+        self.write_bytes(address, &[b])
+            .ok_or(MemoryError::StoreAddress(Access::Byte))
+    }
+
+    pub fn hw_at(&self, address: u32) -> Result<u16, MemoryError> {
+        let mut data = [0; 2];
+        self.read_bytes(address, &mut data)
+            .ok_or(MemoryError::LoadAddress(Access::HalfWord))?;
+        let b1 = data[0] as u16;
+        let b2 = (data[1] as u16) << 8;
+        let res = b1 | b2;
+
+        #[cfg(feature = "trace")]
+        {
+            eprintln!("get m1: {:#018b} ({})", data[0], data[0]);
+            eprintln!("get m2: {:#018b} ({})", data[1], data[1]);
+            eprintln!("get 1: {:#018b} ({})", b1, b1);
+            eprintln!("get 2: {:#018b} ({})", b2, b2);
+            eprintln!("get e: {:#018b} ({})", res, res);
+        }
+        Ok(res)
+    }
+
+    #[allow(clippy::identity_op)] // readability
+    pub fn set_hw_at(&mut self, address: u32, hw: u16) -> Result<(), MemoryError> {
+        let b1 = hw as u8;
+        let b2 = (hw >> 8) as u8;
+        let data = [b1, b2];
+        self.write_bytes(address, &data)
+            .ok_or(MemoryError::StoreAddress(Access::HalfWord))?;
+        #[cfg(feature = "trace")]
+        {
+            let res = b1 as u16 | ((b2 as u16) << 8);
+            eprintln!("set v: {:#018b} ({})", hw, hw);
+            eprintln!("set 1: {:#010b} ({})", b1, b1);
+            eprintln!("set 2: {:#010b} ({})", b2, b2);
+            eprintln!("set e: {:#018b} ({})", res, res);
+            eprintln!("set m1: {:#018b} ({})", data[0], data[0]);
+            eprintln!("set m2: {:#018b} ({})", data[1], data[1]);
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::identity_op)] // readability
+    pub fn word_at(&self, address: u32) -> Result<u32, MemoryError> {
+        let (bank, offset) = self
+            .locate(address, 4)
+            .ok_or(MemoryError::LoadAddress(Access::Word))?;
+        let data = self.banks[bank].word(offset);
+        let b1 = (data[0] as u32) << 0;
+        let b2 = (data[1] as u32) << 8;
+        let b3 = (data[2] as u32) << 16;
+        let b4 = (data[3] as u32) << 24;
+        let res = b1 | b2 | b3 | b4;
+
+        #[cfg(feature = "trace")]
+        {
+            eprintln!("get m1: {:#010b} ({})", data[0], data[0]);
+            eprintln!("get m2: {:#010b} ({})", data[1], data[1]);
+            eprintln!("get m3: {:#010b} ({})", data[2], data[2]);
+            eprintln!("get m4: {:#010b} ({})", data[3], data[3]);
+            eprintln!("get 1:  {:#010b} ({})", b1, b1);
+            eprintln!("get 2:  {:#010b} ({})", b2, b2);
+            eprintln!("get 3:  {:#010b} ({})", b3, b3);
+            eprintln!("get 4:  {:#010b} ({})", b4, b4);
+            eprintln!("get e:  {:#034b} ({})", res, res);
+        }
+        Ok(res)
+    }
+
+    #[allow(clippy::identity_op)] // readability
+    pub fn set_word_at(&mut self, address: u32, hw: u32) -> Result<(), MemoryError> {
+        let b1 = (hw >> 0) as u8;
+        let b2 = (hw >> 8) as u8;
+        let b3 = (hw >> 16) as u8;
+        let b4 = (hw >> 24) as u8;
+        let data = [b1, b2, b3, b4];
+        let (bank, offset) = self
+            .locate(address, 4)
+            .filter(|&(bank, _)| self.map.regions[bank].kind == RegionKind::Ram)
+            .ok_or(MemoryError::StoreAddress(Access::Word))?;
+        self.banks[bank].set_word(offset, data);
+        self.mark(address, 4, true);
+
+        #[cfg(feature = "trace")]
+        {
+            eprintln!("set v:  {:#018b} ({})", hw, hw);
+            eprintln!("set 1:  {:#010b} ({})", b1, b1);
+            eprintln!("set 2:  {:#010b} ({})", b2, b2);
+            eprintln!("set 3:  {:#010b} ({})", b3, b3);
+            eprintln!("set 4:  {:#010b} ({})", b4, b4);
+            eprintln!("set m1: {:#018b} ({})", data[0], data[0]);
+            eprintln!("set m2: {:#018b} ({})", data[1], data[1]);
+            eprintln!("set m3: {:#018b} ({})", data[2], data[2]);
+            eprintln!("set m4: {:#018b} ({})", data[3], data[3]);
+        }
+        Ok(())
+    }
+}
+
+pub const DEFAULT_MEMORY_POOL_SIZE: u32 = 1024;
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEMORY_POOL_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod byte {
+    use super::*;
+
+    #[test]
+    fn memory_access_violation_get() {
+        let m = Memory::new(12);
+        assert!(m.byte_at(13).is_err(), "expected error");
+    }
+
+    #[test]
+    fn memory_access_violation_set() {
+        let mut m = Memory::new(12);
+        assert!(m.set_byte_at(13, 1).is_err(), "expected error");
+    }
+
+    #[test]
+    fn happy_path() {
+        let mut m = Memory::new(13);
+        m.set_byte_at(12, 161).unwrap();
+        assert_eq!(m.byte_at(12).unwrap(), 161);
+    }
+}
+
+#[cfg(test)]
+mod hw {
+    use super::*;
+
+    #[test]
+    fn memory_access_violation_get() {
+        let m = Memory::new(12);
+        assert!(m.hw_at(12).is_err(), "expected error");
+    }
+
+    #[test]
+    fn memory_access_violation_set() {
+        let mut m = Memory::new(12);
+        assert!(m.set_hw_at(12, 1).is_err(), "expected error");
+    }
+
+    #[test]
+    fn happy_path() {
+        let mut m = Memory::new(13);
+        m.set_hw_at(11, 1312).unwrap();
+        assert_eq!(m.hw_at(11).unwrap(), 1312);
+    }
+
+    #[test]
+    fn happy_path_large_number() {
+        let mut m = Memory::new(13);
+        m.set_hw_at(11, 65535).unwrap();
+        assert_eq!(m.hw_at(11).unwrap(), 65535);
+    }
+}
+
+#[cfg(test)]
+mod word {
+    use super::*;
+
+    #[test]
+    fn memory_access_violation_get() {
+        let m = Memory::new(12);
+        assert!(m.word_at(12).is_err(), "expected error");
+    }
+
+    #[test]
+    fn memory_access_violation_set() {
+        let mut m = Memory::new(12);
+        assert!(m.set_word_at(12, 1).is_err(), "expected error");
+    }
+
+    #[test]
+    fn happy_path() {
+        let mut m = Memory::new(13);
+        m.set_word_at(8, 1312).unwrap();
+        assert_eq!(m.word_at(8).unwrap(), 1312);
+    }
+
+    #[test]
+    fn happy_path_large_number() {
+        let mut m = Memory::new(13);
+        m.set_word_at(8, 4294967295).unwrap();
+        assert_eq!(m.word_at(8).unwrap(), 4294967295);
+    }
+
+    #[test]
+    fn straddles_page_boundary() {
+        let mut m = Memory::new(2 * PAGE_SIZE);
+        m.set_word_at(PAGE_SIZE - 2, 0x0403_0201).unwrap();
+        assert_eq!(m.word_at(PAGE_SIZE - 2).unwrap(), 0x0403_0201);
+        assert_eq!(m.hw_at(PAGE_SIZE - 2).unwrap(), 0x0201);
+        assert_eq!(m.hw_at(PAGE_SIZE).unwrap(), 0x0403);
+        assert!(m.word_at(2 * PAGE_SIZE - 2).is_err());
+    }
+}
+
+#[cfg(test)]
+mod map {
+    use super::*;
+
+    #[test]
+    fn regions_at_base_addresses() {
+        let mut m = Memory::with_map(MemoryMap::default().rom(0, 16).ram(0x8000_0000, 64))
+            .expect("valid map");
+        m.set_word_at(0x8000_0010, 1312).unwrap();
+        assert_eq!(m.word_at(0x8000_0010).unwrap(), 1312);
+        assert!(m.word_at(64).is_err());
+        assert!(m.byte_at(0x8000_0040).is_err());
+    }
+
+    #[test]
+    fn rom_is_read_only() {
+        let mut m = Memory::with_map(MemoryMap::default().rom(0, 16)).expect("valid map");
+        assert!(m.set_byte_at(4, 1).is_err());
+
+        m.load(4, &[161]).unwrap();
+        assert_eq!(m.byte_at(4).unwrap(), 161);
+    }
+
+    #[test]
+    fn access_across_regions_fails() {
+        let m = Memory::with_map(MemoryMap::default().ram(0, 16).ram(16, 16)).expect("valid map");
+        assert!(m.word_at(14).is_err());
+        assert!(m.word_at(16).is_ok());
+    }
+
+    #[test]
+    fn overlapping_regions_are_rejected() {
+        assert!(Memory::with_map(MemoryMap::default().ram(0, 16).rom(8, 16)).is_err());
+    }
+
+    #[test]
+    fn stack_top_is_end_of_highest_ram() {
+        let map = MemoryMap::default()
+            .ram(0, 16)
+            .rom(0x1000, 16)
+            .ram(0x8000_0000, 0x100);
+        assert_eq!(map.stack_top(), Some(0x8000_0100));
+        assert_eq!(MemoryMap::default().rom(0, 16).stack_top(), None);
+    }
+}
+
+#[cfg(test)]
+mod sparse {
+    use super::*;
+
+    #[test]
+    fn covers_full_address_space() {
+        let mut m = Memory::sparse();
+        m.set_word_at(0x8000_0000, 1312).unwrap();
+        m.set_word_at(0xffff_fffc, 161).unwrap();
+        assert_eq!(m.word_at(0x8000_0000).unwrap(), 1312);
+        assert_eq!(m.word_at(0xffff_fffc).unwrap(), 161);
+        assert_eq!(m.word_at(0x4000_0000).unwrap(), 0);
+        assert_eq!(m.resident(), 2 * PAGE_SIZE as u64);
+        assert!(m.word_at(0xffff_fffe).is_err());
+    }
+
+    #[test]
+    fn unmapped_addresses_fault() {
+        let m = Memory::with_map(MemoryMap::default().sparse_ram(0x8000_0000, 1 << 20))
+            .expect("valid map");
+        assert!(m.byte_at(0x7fff_ffff).is_err());
+        assert!(m.byte_at(0x8010_0000).is_err());
+        assert_eq!(m.byte_at(0x800f_ffff).unwrap(), 0);
+    }
+
+    #[test]
+    fn region_past_address_space_is_rejected() {
+        assert!(Memory::with_map(MemoryMap::default().sparse_ram(0x8000_0000, 1 << 32)).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use super::Access;
+
+/// Read/write/execute permissions of a memory range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const RWX: Self = Self::new(true, true, true);
+    pub const RW: Self = Self::new(true, true, false);
+    pub const RX: Self = Self::new(true, false, true);
+    pub const R: Self = Self::new(true, false, false);
+    pub const NONE: Self = Self::new(false, false, false);
+
+    pub const fn new(read: bool, write: bool, execute: bool) -> Self {
+        Self {
+            read,
+            write,
+            execute,
+        }
+    }
+
+    pub fn allows(&self, kind: AccessKind) -> bool {
+        match kind {
+            AccessKind::Read => self.read,
+            AccessKind::Write => self.write,
+            AccessKind::Execute => self.execute,
+        }
+    }
+}
+
+impl std::fmt::Display for Permissions {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            if self.read { 'r' } else { '-' },
+            if self.write { 'w' } else { '-' },
+            if self.execute { 'x' } else { '-' }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Execute,
+}
+
+/// Range with permissions overriding those of the region it lies in.
+#[derive(Debug, Clone)]
+pub(crate) struct Protection {
+    pub(crate) name: String,
+    pub(crate) base: u32,
+    pub(crate) size: u64,
+    pub(crate) permissions: Permissions,
+}
+
+impl Protection {
+    pub(crate) fn overlaps(&self, address: u32, len: u32) -> bool {
+        (address as u64) < self.base as u64 + self.size
+            && (self.base as u64) < address as u64 + len as u64
+    }
+}
+
+/// Access denied by region permissions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fault {
+    pub pc: u32,
+    pub address: u32,
+    pub access: Access,
+    pub kind: AccessKind,
+    pub region: String,
+    pub permissions: Permissions,
+}
+
+impl std::fmt::Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} access fault: {:?} at {:#010x} in {} ({}), PC {:#010x}",
+            self.kind, self.access, self.address, self.region, self.permissions, self.pc
+        )
+    }
+}
@@ -0,0 +1,69 @@
+use std::{collections::HashMap, sync::Arc};
+
+use super::PAGE_SIZE;
+
+const WORDS_PER_PAGE: usize = PAGE_SIZE as usize / 64;
+
+/// One bit per guest byte, set once the byte has been initialized.
+///
+/// Pages are allocated on first write, so untouched memory costs nothing.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ShadowMap {
+    pages: HashMap<u32, Arc<[u64; WORDS_PER_PAGE]>>,
+}
+
+impl ShadowMap {
+    pub(crate) fn mark(&mut self, address: u32, len: u32, defined: bool) {
+        for at in (0..len).map(|i| address.wrapping_add(i)) {
+            let (word, bit) = Self::position(at);
+            if defined {
+                let page = self
+                    .pages
+                    .entry(at / PAGE_SIZE)
+                    .or_insert_with(|| Arc::new([0; WORDS_PER_PAGE]));
+                Arc::make_mut(page)[word] |= bit;
+            } else if let Some(page) = self.pages.get_mut(&(at / PAGE_SIZE)) {
+                Arc::make_mut(page)[word] &= !bit;
+            }
+        }
+    }
+
+    pub(crate) fn is_defined(&self, address: u32, len: u32) -> bool {
+        (0..len).map(|i| address.wrapping_add(i)).all(|at| {
+            let (word, bit) = Self::position(at);
+            self.pages
+                .get(&(at / PAGE_SIZE))
+                .is_some_and(|page| page[word] & bit != 0)
+        })
+    }
+
+    fn position(address: u32) -> (usize, u64) {
+        let offset = address % PAGE_SIZE;
+        ((offset / 64) as usize, 1 << (offset % 64))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod shadow {
+    use super::*;
+
+    #[test]
+    fn bytes_start_undefined() {
+        let s = ShadowMap::default();
+        assert!(!s.is_defined(0, 1));
+        assert!(s.is_defined(0, 0));
+    }
+
+    #[test]
+    fn marking_is_per_byte() {
+        let mut s = ShadowMap::default();
+        s.mark(PAGE_SIZE - 2, 4, true);
+        assert!(s.is_defined(PAGE_SIZE - 2, 4));
+        assert!(!s.is_defined(PAGE_SIZE - 3, 2));
+
+        s.mark(PAGE_SIZE, 1, false);
+        assert!(s.is_defined(PAGE_SIZE - 2, 2));
+        assert!(!s.is_defined(PAGE_SIZE - 2, 4));
+    }
+}
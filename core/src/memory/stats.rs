@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use super::AccessKind;
+
+/// Accesses to one block of the address space.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AccessCounts {
+    pub reads: u64,
+    pub writes: u64,
+    pub fetches: u64,
+}
+
+impl AccessCounts {
+    pub fn total(&self) -> u64 {
+        self.reads + self.writes + self.fetches
+    }
+}
+
+/// Bus access counters per block of `granularity` bytes.
+#[derive(Debug, Clone)]
+pub struct AccessStats {
+    granularity: u32,
+    blocks: HashMap<u32, AccessCounts>,
+}
+
+impl AccessStats {
+    pub(crate) fn new(granularity: u32) -> Self {
+        Self {
+            granularity,
+            blocks: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, address: u32, kind: AccessKind) {
+        let counts = self.blocks.entry(self.block(address)).or_default();
+        match kind {
+            AccessKind::Read => counts.reads += 1,
+            AccessKind::Write => counts.writes += 1,
+            AccessKind::Execute => counts.fetches += 1,
+        }
+    }
+
+    pub fn granularity(&self) -> u32 {
+        self.granularity
+    }
+
+    /// Base address of the block holding `address`.
+    pub fn block(&self, address: u32) -> u32 {
+        address & !(self.granularity - 1)
+    }
+
+    pub fn get(&self, address: u32) -> AccessCounts {
+        self.blocks
+            .get(&self.block(address))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Touched blocks by base address, in address order.
+    pub fn blocks(&self) -> Vec<(u32, AccessCounts)> {
+        let mut blocks: Vec<_> = self.blocks.iter().map(|(&a, &c)| (a, c)).collect();
+        blocks.sort_by_key(|&(address, _)| address);
+        blocks
+    }
+
+    /// Touched blocks, busiest first.
+    pub fn hottest(&self) -> Vec<(u32, AccessCounts)> {
+        let mut blocks = self.blocks();
+        blocks.sort_by(|a, b| b.1.total().cmp(&a.1.total()).then(a.0.cmp(&b.0)));
+        blocks
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod stats {
+    use super::*;
+
+    #[test]
+    fn counts_per_block() {
+        let mut s = AccessStats::new(64);
+        s.record(0x100, AccessKind::Execute);
+        s.record(0x13c, AccessKind::Execute);
+        s.record(0x140, AccessKind::Read);
+        s.record(0x1000, AccessKind::Write);
+        s.record(0x1004, AccessKind::Write);
+        s.record(0x1008, AccessKind::Read);
+
+        assert_eq!(s.get(0x120).fetches, 2);
+        assert_eq!(
+            s.get(0x1000),
+            AccessCounts {
+                reads: 1,
+                writes: 2,
+                fetches: 0
+            }
+        );
+        assert_eq!(
+            s.blocks().iter().map(|b| b.0).collect::<Vec<_>>(),
+            [0x100, 0x140, 0x1000]
+        );
+        assert_eq!(s.hottest()[0].0, 0x1000);
+    }
+}
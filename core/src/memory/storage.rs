@@ -0,0 +1,197 @@
+use std::{collections::HashMap, sync::Arc};
+
+pub const PAGE_SIZE: u32 = 4096;
+
+/// How region contents are kept on the host.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    /// One contiguous allocation, sized up front.
+    #[default]
+    Flat,
+    /// 4 KiB pages, allocated on first write. Unwritten pages read as zero.
+    Sparse,
+}
+
+type Page = Arc<[u8; PAGE_SIZE as usize]>;
+
+/// Region contents, in reference-counted pages.
+///
+/// Cloning shares every page; a page is copied the first time either clone
+/// writes to it.
+#[derive(Debug, Clone)]
+pub(crate) enum Storage {
+    Flat(Vec<Page>),
+    Sparse(HashMap<u32, Page>),
+}
+
+impl Storage {
+    pub(crate) fn new(backend: Backend, size: u64) -> Self {
+        match backend {
+            Backend::Flat => Self::Flat(
+                (0..size.div_ceil(PAGE_SIZE as u64))
+                    .map(|_| Arc::new([0; PAGE_SIZE as usize]))
+                    .collect(),
+            ),
+            Backend::Sparse => Self::Sparse(HashMap::new()),
+        }
+    }
+
+    fn page(&self, idx: u32) -> Option<&Page> {
+        match self {
+            Self::Flat(pages) => pages.get(idx as usize),
+            Self::Sparse(pages) => pages.get(&idx),
+        }
+    }
+
+    fn page_mut(&mut self, idx: u32) -> &mut [u8; PAGE_SIZE as usize] {
+        let page = match self {
+            Self::Flat(pages) => &mut pages[idx as usize],
+            Self::Sparse(pages) => pages
+                .entry(idx)
+                .or_insert_with(|| Arc::new([0; PAGE_SIZE as usize])),
+        };
+        Arc::make_mut(page)
+    }
+
+    /// Fills `buf` from `offset`. Caller is responsible for bounds.
+    pub(crate) fn read(&self, offset: u32, buf: &mut [u8]) {
+        let mut done = 0;
+        while done < buf.len() {
+            let at = offset + done as u32;
+            let start = (at % PAGE_SIZE) as usize;
+            let n = (buf.len() - done).min(PAGE_SIZE as usize - start);
+            let chunk = &mut buf[done..done + n];
+            match self.page(at / PAGE_SIZE) {
+                Some(data) => chunk.copy_from_slice(&data[start..start + n]),
+                None => chunk.fill(0),
+            }
+            done += n;
+        }
+    }
+
+    /// Writes `data` at `offset`. Caller is responsible for bounds.
+    pub(crate) fn write(&mut self, offset: u32, data: &[u8]) {
+        let mut done = 0;
+        while done < data.len() {
+            let at = offset + done as u32;
+            let start = (at % PAGE_SIZE) as usize;
+            let n = (data.len() - done).min(PAGE_SIZE as usize - start);
+            self.page_mut(at / PAGE_SIZE)[start..start + n].copy_from_slice(&data[done..done + n]);
+            done += n;
+        }
+    }
+
+    /// Word at `offset`, indexed straight out of its page unless it straddles
+    /// two. Caller is responsible for bounds.
+    pub(crate) fn word(&self, offset: u32) -> [u8; 4] {
+        let mut data = [0; 4];
+        let start = (offset % PAGE_SIZE) as usize;
+        if start > PAGE_SIZE as usize - 4 {
+            self.read(offset, &mut data);
+        } else if let Some(page) = self.page(offset / PAGE_SIZE) {
+            data.copy_from_slice(&page[start..start + 4]);
+        }
+        data
+    }
+
+    /// Writes a word at `offset`, straight into its page unless it straddles
+    /// two. Caller is responsible for bounds.
+    pub(crate) fn set_word(&mut self, offset: u32, data: [u8; 4]) {
+        let start = (offset % PAGE_SIZE) as usize;
+        if start > PAGE_SIZE as usize - 4 {
+            self.write(offset, &data);
+        } else {
+            self.page_mut(offset / PAGE_SIZE)[start..start + 4].copy_from_slice(&data);
+        }
+    }
+
+    /// Number of host bytes backing the region, counting shared pages.
+    pub(crate) fn resident(&self) -> u64 {
+        let pages = match self {
+            Self::Flat(pages) => pages.len(),
+            Self::Sparse(pages) => pages.len(),
+        };
+        pages as u64 * PAGE_SIZE as u64
+    }
+
+    /// Pages not shared with any snapshot or fork.
+    pub(crate) fn private_pages(&self) -> usize {
+        let private = |p: &Page| Arc::strong_count(p) == 1;
+        match self {
+            Self::Flat(pages) => pages.iter().filter(|p| private(p)).count(),
+            Self::Sparse(pages) => pages.values().filter(|p| private(p)).count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod sparse {
+    use super::*;
+
+    #[test]
+    fn unwritten_pages_read_as_zero() {
+        let s = Storage::new(Backend::Sparse, 1 << 32);
+        let mut buf = [0xff; 4];
+        s.read(0xffff_fff0, &mut buf);
+        assert_eq!(buf, [0; 4]);
+        assert_eq!(s.resident(), 0);
+    }
+
+    #[test]
+    fn pages_are_allocated_on_write() {
+        let mut s = Storage::new(Backend::Sparse, 1 << 32);
+        s.write(0x8000_0004, &[1, 2, 3, 4]);
+        assert_eq!(s.resident(), PAGE_SIZE as u64);
+
+        let mut buf = [0; 4];
+        s.read(0x8000_0004, &mut buf);
+        assert_eq!(buf, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn access_across_page_boundary() {
+        let mut s = Storage::new(Backend::Sparse, 1 << 32);
+        s.write(PAGE_SIZE - 2, &[1, 2, 3, 4]);
+        assert_eq!(s.resident(), 2 * PAGE_SIZE as u64);
+
+        let mut buf = [0; 4];
+        s.read(PAGE_SIZE - 2, &mut buf);
+        assert_eq!(buf, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn words_within_and_across_pages() {
+        for backend in [Backend::Flat, Backend::Sparse] {
+            let mut s = Storage::new(backend, 2 * PAGE_SIZE as u64);
+            for offset in [0, PAGE_SIZE - 4, PAGE_SIZE - 2, PAGE_SIZE - 1] {
+                s.set_word(offset, [1, 2, 3, 4]);
+                assert_eq!(s.word(offset), [1, 2, 3, 4]);
+
+                let mut buf = [0; 4];
+                s.read(offset, &mut buf);
+                assert_eq!(buf, [1, 2, 3, 4]);
+            }
+            assert_eq!(s.word(PAGE_SIZE + 8), [0; 4]);
+        }
+    }
+
+    #[test]
+    fn clones_copy_pages_on_write() {
+        for backend in [Backend::Flat, Backend::Sparse] {
+            let mut s = Storage::new(backend, 4 * PAGE_SIZE as u64);
+            s.write(0, &[1; 8]);
+            s.write(PAGE_SIZE, &[2; 8]);
+            let snapshot = s.clone();
+            assert_eq!(s.private_pages(), 0);
+
+            s.write(4, &[3; 4]);
+            assert_eq!(s.private_pages(), 1);
+
+            let mut buf = [0; 8];
+            snapshot.read(0, &mut buf);
+            assert_eq!(buf, [1; 8]);
+            s.read(0, &mut buf);
+            assert_eq!(buf, [1, 1, 1, 1, 3, 3, 3, 3]);
+        }
+    }
+}
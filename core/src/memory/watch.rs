@@ -0,0 +1,75 @@
+use super::{Access, AccessKind};
+
+/// Which accesses trigger a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Watch {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Watch {
+    fn matches(&self, kind: AccessKind) -> bool {
+        matches!(
+            (self, kind),
+            (Self::Read, AccessKind::Read)
+                | (Self::Write, AccessKind::Write)
+                | (Self::ReadWrite, AccessKind::Read)
+                | (Self::ReadWrite, AccessKind::Write)
+        )
+    }
+}
+
+/// Access observed by a watchpoint.
+///
+/// For reads, `old` and `new` are both the value read. For writes to device
+/// regions, `old` is always 0, since reading a device may have side effects.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchEvent {
+    pub pc: u32,
+    pub address: u32,
+    pub access: Access,
+    pub kind: AccessKind,
+    pub old: u32,
+    pub new: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchAction {
+    Continue,
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchId(pub(crate) usize);
+
+pub(crate) type WatchCallback = Box<dyn FnMut(&WatchEvent) -> WatchAction>;
+
+pub(crate) struct Watchpoint {
+    pub(crate) id: WatchId,
+    pub(crate) base: u32,
+    pub(crate) size: u64,
+    pub(crate) on: Watch,
+    pub(crate) callback: WatchCallback,
+}
+
+impl Watchpoint {
+    pub(crate) fn triggers(&self, address: u32, len: u32, kind: AccessKind) -> bool {
+        self.on.matches(kind)
+            && (address as u64) < self.base as u64 + self.size
+            && (self.base as u64) < address as u64 + len as u64
+    }
+}
+
+impl std::fmt::Debug for Watchpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Watchpoint({:?} {:?} [{:#010x}..{:#010x}))",
+            self.id,
+            self.on,
+            self.base,
+            self.base as u64 + self.size
+        )
+    }
+}
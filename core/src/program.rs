@@ -1,11 +1,17 @@
 #[cfg(feature = "trace")]
 use crate::debug;
-use crate::{Instruction, InstructionError, Memory, Register, REGISTER_INCREMENT, VM};
+use crate::{
+    memory::MemoryError, Bus, Instruction, InstructionError, Register, REGISTER_INCREMENT, VM,
+};
 
+/// Executable image, staged until it gets loaded into the VM address space.
+///
+/// Code shares the address space with data: instructions are fetched from
+/// the same memory loads and stores go through.
 #[derive(Default)]
 pub struct Program {
-    end: usize,
-    rom: Memory,
+    base: u32,
+    image: Vec<u8>,
 }
 
 impl Program {
@@ -16,27 +22,58 @@ impl Program {
             {
                 eprintln!("{n}: {}", debug::binary(*x, 32));
             }
-            prg.rom
-                .set_word_at((n * 4) as u32, *x)
-                .expect("invalid memory access");
+            prg.write_slice((n * 4) as u32, &x.to_le_bytes());
         }
-        prg.end = asm.len();
         prg
     }
 
+    /// Address the image gets loaded at.
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    pub fn set_base(&mut self, base: u32) {
+        self.base = base;
+    }
+
+    /// Address right past the last instruction.
+    pub fn end(&self) -> u32 {
+        let words = (self.image.len() as u32).div_ceil(REGISTER_INCREMENT);
+        self.base + words * REGISTER_INCREMENT
+    }
+
+    /// Stages a byte at offset relative to program base.
     pub fn write(&mut self, pos: u32, byte: u8) {
-        self.rom
-            .set_byte_at(pos, byte)
-            .expect("Invalid memory access");
-        self.end = (pos / 4) as usize + 1;
+        let pos = pos as usize;
+        if pos >= self.image.len() {
+            self.image.resize(pos + 1, 0);
+        }
+        self.image[pos] = byte;
+    }
+
+    /// Stages `data` at `pos` bytes past the base.
+    pub fn write_slice(&mut self, pos: u32, data: &[u8]) {
+        let pos = pos as usize;
+        if pos + data.len() > self.image.len() {
+            self.image.resize(pos + data.len(), 0);
+        }
+        self.image[pos..pos + data.len()].copy_from_slice(data);
+    }
+
+    /// Copies the image into VM memory and points PC at its first instruction.
+    pub fn load(&self, vm: &mut VM) -> Result<(), MemoryError> {
+        vm.memory.load(self.base, &self.image)?;
+        vm.cpu.register.set(Register::PC, self.base);
+        Ok(())
     }
 
     pub fn is_done(&self, vm: &VM) -> bool {
-        (vm.cpu.register.get(Register::PC) / REGISTER_INCREMENT) as usize == self.end
+        vm.halted().is_some() || vm.cpu.register.get(Register::PC) == self.end()
     }
 
     pub fn run(&self, vm: &mut VM) -> Result<(), InstructionError> {
-        for x in 0..self.end {
+        let steps = (self.end() - self.base) / REGISTER_INCREMENT;
+        for x in 0..steps as usize {
             self.step(vm, x)?;
             if self.is_done(vm) {
                 break;
@@ -47,25 +84,151 @@ impl Program {
 
     pub fn peek(&self, vm: &VM) -> Result<Instruction, InstructionError> {
         let pc = vm.cpu.register.get(Register::PC);
-        let code = self.rom.word_at(pc).expect("invalid memory access");
+        let code = vm.memory.word_at(pc)?;
         Instruction::parse(code)
     }
 
     pub fn step(&self, vm: &mut VM, _iteration: usize) -> Result<(), InstructionError> {
         let pc = vm.cpu.register.get(Register::PC);
-        let code = self.rom.word_at(pc).expect("invalid memory access");
+        vm.memory.set_pc(pc);
+        let code = vm.memory.fetch(pc)?;
         #[cfg(feature = "trace")]
         {
             eprintln!("iteration {} :: PC: {}", _iteration, pc);
         }
 
-        let inst = Instruction::parse(code).expect("should parse");
+        let inst = Instruction::parse(code)?;
         #[cfg(feature = "trace")]
         {
             eprintln!("{}: {}", _iteration, debug::binary(code, 32));
             eprintln!("\t{:?}", inst);
         }
 
-        vm.execute(inst)
+        vm.execute(inst)?;
+        vm.memory.tick();
+        vm.poll_halt();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rv32i::{instr::builder::Builder, instr::operation::Operation, instr::part::Part};
+
+    #[test]
+    fn code_is_visible_to_loads() {
+        let mut vm: VM = Default::default();
+        let program = Program::from_asm(&[Builder::opcode(Operation::Load)
+            .pack(Part::Dest, Register::X12 as u32)
+            .pack(Part::Funct3, 0b010)
+            .pack(Part::Reg1, Register::X0 as u32)
+            .pack(Part::Imm110, 0)
+            .build()]);
+        program.load(&mut vm).expect("should load");
+
+        program.run(&mut vm).expect("should run");
+
+        assert_eq!(
+            vm.cpu.register.get(Register::X12),
+            vm.memory.word_at(0).expect("memory access")
+        );
+    }
+
+    #[test]
+    fn stores_modify_code() {
+        let addi = Builder::opcode(Operation::ImmediateMath)
+            .pack(Part::Dest, Register::X13 as u32)
+            .pack(Part::Funct3, 0b000)
+            .pack(Part::Reg1, Register::X0 as u32)
+            .pack(Part::Imm110, 161)
+            .build();
+        let mut vm: VM = Default::default();
+        let program = Program::from_asm(&[
+            Builder::opcode(Operation::Store)
+                .pack(Part::Imm40, 4)
+                .pack(Part::Funct3, 0b010)
+                .pack(Part::Reg1, Register::X0 as u32)
+                .pack(Part::Reg2, Register::X12 as u32)
+                .build(),
+            0,
+        ]);
+        program.load(&mut vm).expect("should load");
+        vm.cpu.register.set(Register::X12, addi);
+
+        program.run(&mut vm).expect("should run");
+
+        assert_eq!(vm.cpu.register.get(Register::X13), 161);
+        assert!(program.is_done(&vm));
+    }
+
+    #[test]
+    fn load_places_image_at_base() {
+        let mut vm: VM = Default::default();
+        let mut program = Program::from_asm(&[0x00d00093]);
+        program.set_base(64);
+        program.load(&mut vm).expect("should load");
+
+        assert_eq!(vm.cpu.register.get(Register::PC), 64);
+        assert_eq!(vm.memory.word_at(64).expect("memory access"), 0x00d00093);
+        assert_eq!(program.end(), 68);
+    }
+
+    #[test]
+    fn watchpoint_stops_run() {
+        use crate::memory::{Watch, WatchAction};
+        use crate::Halt;
+
+        let store = Builder::opcode(Operation::Store)
+            .pack(Part::Imm40, 0)
+            .pack(Part::Funct3, 0b010)
+            .pack(Part::Reg1, Register::X12 as u32)
+            .pack(Part::Reg2, Register::X0 as u32)
+            .build();
+        let mut vm: VM = Default::default();
+        let program = Program::from_asm(&[store, store, store]);
+        program.load(&mut vm).expect("should load");
+        vm.cpu.register.set(Register::X12, 512);
+        vm.memory.watch(512, 4, Watch::Write, |e| {
+            assert_eq!(e.pc, 0);
+            WatchAction::Stop
+        });
+
+        program.run(&mut vm).expect("should run");
+
+        assert_eq!(vm.halted(), Some(&Halt::Watchpoint(512)));
+        assert_eq!(vm.cpu.register.get(Register::PC), 4);
+        assert!(program.is_done(&vm));
+    }
+
+    #[test]
+    fn finisher_stops_run() {
+        use crate::devices::{TestFinisher, FINISHER_SIZE};
+        use crate::Halt;
+
+        let store = Builder::opcode(Operation::Store)
+            .pack(Part::Imm40, 0)
+            .pack(Part::Funct3, 0b010)
+            .pack(Part::Reg1, Register::X12 as u32)
+            .pack(Part::Reg2, Register::X13 as u32)
+            .build();
+        let mut vm: VM = Default::default();
+        let program = Program::from_asm(&[store, store]);
+        program.load(&mut vm).expect("should load");
+        vm.memory
+            .attach(
+                "test",
+                0x10_0000,
+                FINISHER_SIZE,
+                Box::new(TestFinisher::new()),
+            )
+            .expect("should attach");
+        vm.cpu.register.set(Register::X12, 0x10_0000);
+        vm.cpu.register.set(Register::X13, 0x0003_3333);
+
+        program.run(&mut vm).expect("should run");
+
+        assert_eq!(vm.halted(), Some(&Halt::Fail(3)));
+        assert_eq!(vm.cpu.register.get(Register::PC), 4);
     }
 }
@@ -79,7 +79,7 @@ mod test {
                 Part::Opcode => assert_eq!(0b00000000000000000000000001100000, part.get(instr)),
                 Part::Dest => assert_eq!(0b00000000000000000000000010000000, part.get(instr)),
                 Part::Imm3112 => assert_eq!(0b00000000000000000000000000000000, part.get(instr)),
-                _ => assert!(false, "should not happen"),
+                _ => panic!("should not happen"),
             }
         }
     }
@@ -112,11 +112,10 @@ mod test {
         printp("B12b", i.value(Part::B12b).expect("invalid B12b"));
 
         // B12b -> MSB
-        let value = 0
-            | (i.value(Part::B12b).expect("invalid B12b") << 11)
+        let value = (i.value(Part::B12b).expect("invalid B12b") << 11)
             | (i.value(Part::B11b).expect("invalid B11b") << 10)
             | (i.value(Part::Imm105).expect("invalid Imm105") << 4)
-            | (i.value(Part::Imm41).expect("invalid Imm41") << 0);
+            | i.value(Part::Imm41).expect("invalid Imm41");
         printp("value", value >> 1);
 
         assert_eq!(value, 0b000000001000, "CS61C Su18 - Lecture 7, page 45");
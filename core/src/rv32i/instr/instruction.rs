@@ -2,7 +2,7 @@ use super::format::Format;
 use super::operation::{Operation, OperationError};
 use super::part::Part;
 use crate::cpu::RegisterError;
-use crate::memory::MemoryError;
+use crate::memory::{Fault, MemoryError};
 
 #[derive(Debug, Clone)]
 pub struct Instruction {
@@ -56,6 +56,7 @@ pub enum InstructionError {
     InvalidArgument(Part),
     InvalidRegister,
     InvalidMemory,
+    AccessFault(Fault),
 }
 
 impl From<OperationError> for InstructionError {
@@ -73,8 +74,11 @@ impl From<RegisterError> for InstructionError {
 }
 
 impl From<MemoryError> for InstructionError {
-    fn from(_e: MemoryError) -> Self {
-        Self::InvalidMemory
+    fn from(e: MemoryError) -> Self {
+        match e {
+            MemoryError::AccessFault(fault) => Self::AccessFault(fault),
+            _ => Self::InvalidMemory,
+        }
     }
 }
 
@@ -86,6 +90,7 @@ impl From<InstructionError> for String {
             InstructionError::UnknownOperation(raw) => format!("Unknown operation: {}", raw),
             InstructionError::InvalidRegister => "Invalid register".to_owned(), // TODO: wat
             InstructionError::InvalidMemory => "Invalid memory".to_owned(),     // TODO: wat
+            InstructionError::AccessFault(fault) => fault.to_string(),
             InstructionError::Value => "Unable to extract value".to_owned(),
             #[cfg(test)]
             InstructionError::Get => "Unable to get part".to_owned(),
@@ -100,7 +105,9 @@ use super::builder::Builder;
 mod test {
     use super::*;
 
+    // Literals are grouped by instruction field
     #[test]
+    #[allow(clippy::unusual_byte_groupings)]
     fn add_immediate() {
         let raw = 0b011111111111_00010_000_00001_0010011; // ADDI rd=1 rs=2 imm=whatever
         let inst = Instruction::parse(raw).expect("valid instruction");
@@ -0,0 +1,346 @@
+use std::{
+    io::{self, Write},
+    sync::mpsc::Receiver,
+};
+
+use crate::{
+    devices::stdin_channel, ecall::Ecall, memory::Access,
+    rv32i::instr::instruction::InstructionError, Bus, Halt, Register, VM,
+};
+
+const LEGACY_PUTCHAR: u32 = 0x01;
+const LEGACY_GETCHAR: u32 = 0x02;
+const BASE: u32 = 0x10;
+const TIME: u32 = 0x5449_4d45;
+const IPI: u32 = 0x0073_5049;
+const RFENCE: u32 = 0x5246_4e43;
+const HSM: u32 = 0x0048_534d;
+const SRST: u32 = 0x5352_5354;
+
+const SUCCESS: i32 = 0;
+const ERR_NOT_SUPPORTED: i32 = -2;
+const ERR_INVALID_PARAM: i32 = -3;
+const ERR_ALREADY_AVAILABLE: i32 = -6;
+
+const SPEC_VERSION: u32 = 2 << 24;
+/// Not a registered implementation ID.
+const IMPL_ID: u32 = 0x6272_7274;
+const IMPL_VERSION: u32 = 1;
+
+const HART_STARTED: u32 = 0;
+const SUSPEND_RETENTIVE: u32 = 0;
+const RESET_SHUTDOWN: u32 = 0;
+const RESET_COLD_REBOOT: u32 = 1;
+const RESET_WARM_REBOOT: u32 = 2;
+const REASON_SYSTEM_FAILURE: u32 = 1;
+
+const CLINT_MSIP: u32 = 0x0000;
+const CLINT_MTIMECMP: u32 = 0x4000;
+
+/// SBI v2 firmware served by the emulator, for a single hart 0.
+///
+/// Implements the base, TIME, IPI, RFENCE, HSM and SRST extensions and the
+/// legacy console calls. The core has no privilege modes, so every ECALL is
+/// taken as coming from S-mode. Timers and IPIs are forwarded to a CLINT if
+/// one is set; stopping the hart or resetting the system halts the VM.
+pub struct Sbi {
+    output: Box<dyn Write>,
+    input: Option<Receiver<u8>>,
+    clint: Option<u32>,
+}
+
+impl Sbi {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Self {
+            output,
+            input: None,
+            clint: None,
+        }
+    }
+
+    /// Console on the host's stdout and stdin.
+    pub fn stdio() -> Self {
+        Self::new(Box::new(io::stdout())).with_input(stdin_channel())
+    }
+
+    pub fn with_input(mut self, input: Receiver<u8>) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    /// CLINT at `base` receiving timer deadlines and IPIs.
+    pub fn with_clint(mut self, base: u32) -> Self {
+        self.clint = Some(base);
+        self
+    }
+
+    fn clint_write(&self, vm: &mut VM, offset: u32, value: u32) {
+        if let Some(base) = self.clint {
+            // A missing CLINT just means nobody is listening
+            let _ = vm.memory.write(base + offset, Access::Word, value);
+        }
+    }
+
+    fn legacy(&mut self, eid: u32, a0: u32) -> u32 {
+        match eid {
+            LEGACY_PUTCHAR => {
+                // Console output is best effort, as for the UART
+                let _ = self.output.write_all(&[a0 as u8]);
+                let _ = self.output.flush();
+                0
+            }
+            _ => self
+                .input
+                .as_ref()
+                .and_then(|input| input.try_recv().ok())
+                .map_or(u32::MAX, |b| b as u32),
+        }
+    }
+
+    fn call(&mut self, vm: &mut VM, eid: u32, fid: u32, args: [u32; 3]) -> (i32, u32) {
+        match (eid, fid) {
+            (BASE, 0) => (SUCCESS, SPEC_VERSION),
+            (BASE, 1) => (SUCCESS, IMPL_ID),
+            (BASE, 2) => (SUCCESS, IMPL_VERSION),
+            (BASE, 3) => (SUCCESS, is_supported(args[0]) as u32),
+            // mvendorid, marchid and mimpid
+            (BASE, 4..=6) => (SUCCESS, 0),
+            (TIME, 0) => {
+                // High half first, so no spurious deadline passes in between
+                self.clint_write(vm, CLINT_MTIMECMP + 4, !0);
+                self.clint_write(vm, CLINT_MTIMECMP, args[0]);
+                self.clint_write(vm, CLINT_MTIMECMP + 4, args[1]);
+                (SUCCESS, 0)
+            }
+            (IPI, 0) => match targets_hart0(args[0], args[1]) {
+                Some(true) => {
+                    self.clint_write(vm, CLINT_MSIP, 1);
+                    (SUCCESS, 0)
+                }
+                Some(false) => (SUCCESS, 0),
+                None => (ERR_INVALID_PARAM, 0),
+            },
+            // FENCE.I and SFENCE.VMA have nothing to flush; there is no H extension
+            (RFENCE, 0..=2) => match targets_hart0(args[0], args[1]) {
+                Some(_) => (SUCCESS, 0),
+                None => (ERR_INVALID_PARAM, 0),
+            },
+            (HSM, 0) if args[0] == 0 => (ERR_ALREADY_AVAILABLE, 0),
+            (HSM, 1) => {
+                // Nothing could start the only hart again
+                vm.halt(Halt::PowerOff);
+                (SUCCESS, 0)
+            }
+            (HSM, 2) if args[0] == 0 => (SUCCESS, HART_STARTED),
+            (HSM, 0 | 2) => (ERR_INVALID_PARAM, 0),
+            // A retentive suspend resumes right away
+            (HSM, 3) if args[0] == SUSPEND_RETENTIVE => (SUCCESS, 0),
+            (HSM, 3) => (ERR_NOT_SUPPORTED, 0),
+            (SRST, 0) => match (args[0], args[1]) {
+                (RESET_SHUTDOWN, REASON_SYSTEM_FAILURE) => {
+                    vm.halt(Halt::Fail(REASON_SYSTEM_FAILURE as u16));
+                    (SUCCESS, 0)
+                }
+                (RESET_SHUTDOWN, _) => {
+                    vm.halt(Halt::PowerOff);
+                    (SUCCESS, 0)
+                }
+                (RESET_COLD_REBOOT | RESET_WARM_REBOOT, _) => {
+                    vm.halt(Halt::Reboot);
+                    (SUCCESS, 0)
+                }
+                _ => (ERR_INVALID_PARAM, 0),
+            },
+            _ => (ERR_NOT_SUPPORTED, 0),
+        }
+    }
+}
+
+fn is_supported(eid: u32) -> bool {
+    matches!(
+        eid,
+        LEGACY_PUTCHAR | LEGACY_GETCHAR | BASE | TIME | IPI | RFENCE | HSM | SRST
+    )
+}
+
+/// Whether a hart mask selects hart 0, or `None` if it names other harts.
+fn targets_hart0(mask: u32, base: u32) -> Option<bool> {
+    match base {
+        // Every hart
+        u32::MAX => Some(true),
+        0 if mask & !1 == 0 => Some(mask & 1 != 0),
+        _ if mask == 0 => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "trace")]
+fn extension_name(eid: u32) -> &'static str {
+    match eid {
+        LEGACY_PUTCHAR => "legacy putchar",
+        LEGACY_GETCHAR => "legacy getchar",
+        BASE => "base",
+        TIME => "time",
+        IPI => "ipi",
+        RFENCE => "rfence",
+        HSM => "hsm",
+        SRST => "srst",
+        _ => "unknown",
+    }
+}
+
+impl Ecall for Sbi {
+    fn ecall(&mut self, vm: &mut VM) -> Result<(), InstructionError> {
+        let reg = |r| vm.cpu.register.get(r);
+        let (eid, fid) = (reg(Register::X17), reg(Register::X16));
+        let args = [reg(Register::X10), reg(Register::X11), reg(Register::X12)];
+
+        if eid == LEGACY_PUTCHAR || eid == LEGACY_GETCHAR {
+            let value = self.legacy(eid, args[0]);
+            #[cfg(feature = "trace")]
+            eprintln!(
+                "sbi: {} ({:#x}) -> {:#x}",
+                extension_name(eid),
+                args[0],
+                value
+            );
+            vm.cpu.register.set(Register::X10, value);
+            return Ok(());
+        }
+
+        let (error, value) = self.call(vm, eid, fid, args);
+        #[cfg(feature = "trace")]
+        eprintln!(
+            "sbi: {} {:#x}/{} ({:#x}, {:#x}, {:#x}) -> {}, {:#x}",
+            extension_name(eid),
+            eid,
+            fid,
+            args[0],
+            args[1],
+            args[2],
+            error,
+            value
+        );
+        vm.cpu.register.set(Register::X10, error as u32);
+        vm.cpu.register.set(Register::X11, value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod sbi {
+    use super::*;
+    use crate::{
+        devices::{Clint, IrqLine, CLINT_SIZE},
+        testing::Sink,
+    };
+    use std::sync::mpsc;
+
+    fn call(sbi: &mut Sbi, vm: &mut VM, eid: u32, fid: u32, args: &[u32]) -> (i32, u32) {
+        vm.cpu.register.set(Register::X17, eid);
+        vm.cpu.register.set(Register::X16, fid);
+        for (i, &arg) in args.iter().enumerate() {
+            vm.cpu
+                .register
+                .set(Register::try_from(10 + i as u32).unwrap(), arg);
+        }
+        sbi.ecall(vm).expect("SBI call");
+        (
+            vm.cpu.register.get(Register::X10) as i32,
+            vm.cpu.register.get(Register::X11),
+        )
+    }
+
+    #[test]
+    fn base_extension_reports_version_and_probes() {
+        let mut vm: VM = Default::default();
+        let mut sbi = Sbi::new(Box::new(io::sink()));
+
+        assert_eq!(call(&mut sbi, &mut vm, BASE, 0, &[]), (SUCCESS, 2 << 24));
+        assert_eq!(call(&mut sbi, &mut vm, BASE, 3, &[HSM]), (SUCCESS, 1));
+        assert_eq!(
+            call(&mut sbi, &mut vm, BASE, 3, &[0x4442_434e]),
+            (SUCCESS, 0)
+        );
+        assert_eq!(
+            call(&mut sbi, &mut vm, 0x0a00_0000, 0, &[]).0,
+            ERR_NOT_SUPPORTED
+        );
+    }
+
+    #[test]
+    fn legacy_console() {
+        let sink = Sink::default();
+        let (tx, rx) = mpsc::channel();
+        let mut vm: VM = Default::default();
+        let mut sbi = Sbi::new(Box::new(sink.clone())).with_input(rx);
+
+        call(&mut sbi, &mut vm, LEGACY_PUTCHAR, 0, &[b'k' as u32]);
+        assert_eq!(*sink.0.borrow(), b"k");
+        assert_eq!(call(&mut sbi, &mut vm, LEGACY_GETCHAR, 0, &[]).0, -1);
+        tx.send(b'q').unwrap();
+        assert_eq!(
+            call(&mut sbi, &mut vm, LEGACY_GETCHAR, 0, &[]).0,
+            b'q' as i32
+        );
+    }
+
+    #[test]
+    fn timer_and_ipi_go_to_clint() {
+        let (soft, timer) = (IrqLine::default(), IrqLine::default());
+        let mut vm: VM = Default::default();
+        vm.memory
+            .attach(
+                "clint",
+                0x0200_0000,
+                CLINT_SIZE,
+                Box::new(
+                    Clint::new()
+                        .with_soft_irq(soft.clone())
+                        .with_timer_irq(timer.clone()),
+                ),
+            )
+            .expect("should attach");
+        let mut sbi = Sbi::new(Box::new(io::sink())).with_clint(0x0200_0000);
+
+        assert_eq!(call(&mut sbi, &mut vm, TIME, 0, &[1, 0]), (SUCCESS, 0));
+        assert!(!timer.is_raised());
+        vm.memory.tick();
+        assert!(timer.is_raised());
+
+        assert_eq!(call(&mut sbi, &mut vm, IPI, 0, &[1, 0]), (SUCCESS, 0));
+        assert!(soft.is_raised());
+        assert_eq!(
+            call(&mut sbi, &mut vm, IPI, 0, &[1, 1]).0,
+            ERR_INVALID_PARAM
+        );
+    }
+
+    #[test]
+    fn hart_state_and_system_reset() {
+        let mut vm: VM = Default::default();
+        let mut sbi = Sbi::new(Box::new(io::sink()));
+
+        assert_eq!(
+            call(&mut sbi, &mut vm, HSM, 2, &[0]),
+            (SUCCESS, HART_STARTED)
+        );
+        assert_eq!(
+            call(&mut sbi, &mut vm, HSM, 0, &[0]).0,
+            ERR_ALREADY_AVAILABLE
+        );
+        assert_eq!(call(&mut sbi, &mut vm, HSM, 2, &[1]).0, ERR_INVALID_PARAM);
+
+        call(&mut sbi, &mut vm, SRST, 0, &[RESET_COLD_REBOOT, 0]);
+        assert_eq!(vm.halted(), Some(&Halt::Reboot));
+        call(
+            &mut sbi,
+            &mut vm,
+            SRST,
+            0,
+            &[RESET_SHUTDOWN, REASON_SYSTEM_FAILURE],
+        );
+        assert_eq!(vm.halted(), Some(&Halt::Fail(1)));
+    }
+}
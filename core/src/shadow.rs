@@ -0,0 +1,242 @@
+use crate::{
+    bitops,
+    rv32i::instr::{instruction::Instruction, operation::Operation, part::Part},
+    Register, VM,
+};
+
+/// How an undefined value was used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Use {
+    Branch,
+    Address,
+    JumpTarget,
+}
+
+/// Undefined value used in a way that changes program behaviour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndefinedUse {
+    pub pc: u32,
+    pub register: Register,
+    pub usage: Use,
+}
+
+impl std::fmt::Display for UndefinedUse {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let usage = match self.usage {
+            Use::Branch => "branch condition",
+            Use::Address => "memory address",
+            Use::JumpTarget => "jump target",
+        };
+        write!(
+            f,
+            "{:#010x}: uninitialized value in {:?} used as {}",
+            self.pc, self.register, usage
+        )
+    }
+}
+
+/// Tracks which registers hold values derived from uninitialized memory.
+///
+/// Registers are considered defined when tracking starts. Taint comes from
+/// loads of undefined bytes and spreads through arithmetic and stores.
+#[derive(Debug, Clone, Default)]
+pub struct Shadow {
+    undefined: u32,
+    reports: Vec<UndefinedUse>,
+}
+
+impl Shadow {
+    pub fn is_defined(&self, register: Register) -> bool {
+        register == Register::PC || self.undefined & (1 << register as u32) == 0
+    }
+
+    pub(crate) fn set(&mut self, register: Register, defined: bool) {
+        if register == Register::X0 || register == Register::PC {
+            return;
+        }
+        if defined {
+            self.undefined &= !(1 << register as u32);
+        } else {
+            self.undefined |= 1 << register as u32;
+        }
+    }
+
+    pub fn reports(&self) -> &[UndefinedUse] {
+        &self.reports
+    }
+
+    fn check(&mut self, pc: u32, register: Option<Register>, usage: Use) {
+        if let Some(register) = register.filter(|&r| !self.is_defined(r)) {
+            let report = UndefinedUse {
+                pc,
+                register,
+                usage,
+            };
+            #[cfg(feature = "trace")]
+            eprintln!("{}", report);
+            self.reports.push(report);
+        }
+    }
+}
+
+/// Shadow effects of an instruction, applied once it has executed.
+pub(crate) struct Pending {
+    dest: Option<(Register, bool)>,
+    store: Option<(u32, u32)>,
+}
+
+impl VM {
+    /// Checks the operands of `i` and works out what it does to the shadow.
+    pub(crate) fn shadow_before(&mut self, i: &Instruction) -> Option<Pending> {
+        let shadow = self.shadow.as_mut()?;
+        let register = |part| i.value(part).ok().and_then(|x| Register::try_from(x).ok());
+        let (rd, rs1, rs2) = (
+            register(Part::Dest),
+            register(Part::Reg1),
+            register(Part::Reg2),
+        );
+        let defined = |shadow: &Shadow, r: Option<Register>| r.is_none_or(|r| shadow.is_defined(r));
+        let pc = self.cpu.register.get(Register::PC);
+        let base = rs1.map_or(0, |r| self.cpu.register.get(r));
+        let width = |f3: u32| match f3 & 0b011 {
+            0b000 => 1,
+            0b001 => 2,
+            _ => 4,
+        };
+
+        let mut pending = Pending {
+            dest: None,
+            store: None,
+        };
+        match i.opcode {
+            Operation::LUI | Operation::AUIPC | Operation::JAL => {
+                pending.dest = rd.map(|r| (r, true));
+            }
+            Operation::JALR => {
+                shadow.check(pc, rs1, Use::JumpTarget);
+                pending.dest = rd.map(|r| (r, true));
+            }
+            Operation::Branch => {
+                shadow.check(pc, rs1, Use::Branch);
+                shadow.check(pc, rs2, Use::Branch);
+            }
+            Operation::Math => {
+                let both = defined(shadow, rs1) && defined(shadow, rs2);
+                pending.dest = rd.map(|r| (r, both));
+            }
+            Operation::ImmediateMath => {
+                pending.dest = rd.map(|r| (r, defined(shadow, rs1)));
+            }
+            Operation::Load => {
+                shadow.check(pc, rs1, Use::Address);
+                let address =
+                    base.wrapping_add(bitops::sign_extend(i.value(Part::Imm110).ok()?, 12) as u32);
+                let len = width(i.value(Part::Funct3).ok()?);
+                pending.dest = rd.map(|r| (r, self.memory.is_defined(address, len)));
+            }
+            Operation::Store => {
+                shadow.check(pc, rs1, Use::Address);
+                if !defined(shadow, rs2) {
+                    let immediate =
+                        (i.value(Part::Imm115).ok()? << 5) | i.value(Part::Imm40).ok()?;
+                    let address = base.wrapping_add(bitops::sign_extend(immediate, 12) as u32);
+                    pending.store = Some((address, width(i.value(Part::Funct3).ok()?)));
+                }
+            }
+            _ => {}
+        }
+        Some(pending)
+    }
+
+    pub(crate) fn shadow_after(&mut self, pending: Pending) {
+        if let (Some(shadow), Some((rd, defined))) = (self.shadow.as_mut(), pending.dest) {
+            shadow.set(rd, defined);
+        }
+        if let Some((address, len)) = pending.store {
+            self.memory.mark(address, len, false);
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod shadow {
+    use super::*;
+    use crate::{memory::Access, rv32i::instr::builder::Builder, Bus, Program};
+
+    fn lw(rd: Register, offset: u32) -> u32 {
+        Builder::opcode(Operation::Load)
+            .pack(Part::Dest, rd as u32)
+            .pack(Part::Funct3, 0b010)
+            .pack(Part::Reg1, Register::X0 as u32)
+            .pack(Part::Imm110, offset)
+            .build()
+    }
+
+    fn beq(rs1: Register) -> u32 {
+        Builder::opcode(Operation::Branch)
+            .pack(Part::Funct3, 0b000)
+            .pack(Part::Reg1, rs1 as u32)
+            .pack(Part::Reg2, Register::X0 as u32)
+            .build()
+    }
+
+    #[test]
+    fn branch_on_uninitialized_load_is_reported() {
+        let mut vm: VM = Default::default();
+        vm.enable_shadow();
+        let program = Program::from_asm(&[
+            lw(Register::X5, 512),
+            Builder::opcode(Operation::ImmediateMath)
+                .pack(Part::Dest, Register::X6 as u32)
+                .pack(Part::Funct3, 0b000)
+                .pack(Part::Reg1, Register::X5 as u32)
+                .pack(Part::Imm110, 1)
+                .build(),
+            beq(Register::X6),
+            lw(Register::X7, 0),
+            beq(Register::X7),
+        ]);
+        program.load(&mut vm).expect("should load");
+
+        program.run(&mut vm).expect("should run");
+
+        let shadow = vm.shadow().expect("shadow enabled");
+        assert_eq!(
+            shadow.reports(),
+            &[UndefinedUse {
+                pc: 8,
+                register: Register::X6,
+                usage: Use::Branch,
+            }]
+        );
+        assert!(!shadow.is_defined(Register::X5));
+        assert!(shadow.is_defined(Register::X7));
+    }
+
+    #[test]
+    fn stores_propagate_definedness() {
+        let mut vm: VM = Default::default();
+        vm.enable_shadow();
+        let program = Program::from_asm(&[
+            lw(Register::X5, 512),
+            Builder::opcode(Operation::Store)
+                .pack(Part::Imm40, 0)
+                .pack(Part::Imm115, 0b10000)
+                .pack(Part::Funct3, 0b010)
+                .pack(Part::Reg1, Register::X0 as u32)
+                .pack(Part::Reg2, Register::X5 as u32)
+                .build(),
+        ]);
+        program.load(&mut vm).expect("should load");
+        vm.memory
+            .write(516, Access::Word, 0)
+            .expect("memory access");
+
+        program.run(&mut vm).expect("should run");
+
+        assert!(!vm.memory.is_defined(512, 1));
+        assert!(vm.memory.is_defined(516, 4));
+        assert!(vm.shadow().expect("shadow enabled").reports().is_empty());
+    }
+}
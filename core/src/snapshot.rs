@@ -0,0 +1,166 @@
+use crate::{
+    memory::{MemoryError, MemorySnapshot},
+    shadow::Shadow,
+    stack::StackMonitor,
+    Halt, CPU, VM,
+};
+
+/// VM state captured by [`VM::snapshot`].
+///
+/// Holds registers and memory contents, sharing memory pages with the VM.
+/// There are no CSRs to capture in this core yet.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    cpu: CPU,
+    memory: MemorySnapshot,
+    halt: Option<Halt>,
+    shadow: Option<Shadow>,
+    stack: Option<StackMonitor>,
+}
+
+impl VM {
+    /// Captures the current state. Cheap: memory pages are copied on write.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cpu: self.cpu.clone(),
+            memory: self.memory.snapshot(),
+            halt: self.halt.clone(),
+            shadow: self.shadow.clone(),
+            stack: self.stack.clone(),
+        }
+    }
+
+    /// Resets registers and memory to `snapshot`.
+    ///
+    /// Only pages written since the snapshot was taken need releasing, the
+    /// rest is a reference per page.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), MemoryError> {
+        self.memory.restore(&snapshot.memory)?;
+        self.cpu.clone_from(&snapshot.cpu);
+        self.halt.clone_from(&snapshot.halt);
+        self.shadow.clone_from(&snapshot.shadow);
+        self.stack.clone_from(&snapshot.stack);
+        Ok(())
+    }
+
+    /// Restores `snapshot` and resets devices and the ECALL handler, so the
+    /// guest starts over as if powered on.
+    pub fn reboot(&mut self, snapshot: &Snapshot) -> Result<(), MemoryError> {
+        self.restore(snapshot)?;
+        self.memory.reset_devices();
+        if let Some(handler) = &mut self.ecall {
+            handler.reset();
+        }
+        Ok(())
+    }
+
+    /// Independent VM sharing memory pages with this one.
+    ///
+    /// See [`crate::Memory::fork`] for what doesn't carry over; the ECALL
+    /// handler doesn't either.
+    pub fn fork(&self) -> Self {
+        Self {
+            cpu: self.cpu.clone(),
+            memory: self.memory.fork(),
+            halt: self.halt.clone(),
+            shadow: self.shadow.clone(),
+            stack: self.stack.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod snapshot {
+    use super::*;
+    use crate::{
+        devices::{Clint, CLINT_SIZE},
+        ecall::Ecall,
+        memory::{Access, PAGE_SIZE},
+        rv32i::instr::instruction::InstructionError,
+        Bus, MemoryMap, Register,
+    };
+    use std::{cell::Cell, rc::Rc};
+
+    fn vm() -> VM {
+        let mut vm =
+            VM::new(MemoryMap::default().ram(0, 64 * PAGE_SIZE as u64)).expect("valid map");
+        vm.memory.load(0, &[1; 64]).expect("in range");
+        vm.cpu.register.set(Register::X10, 1312);
+        vm
+    }
+
+    #[test]
+    fn restore_resets_registers_and_memory() {
+        let mut vm = vm();
+        let snapshot = vm.snapshot();
+
+        vm.cpu.register.set(Register::X10, 161);
+        vm.memory.set_word_at(8, 0).expect("in range");
+        vm.memory.set_word_at(PAGE_SIZE * 10, 7).expect("in range");
+        assert_eq!(vm.memory.private_pages(), 2);
+
+        vm.restore(&snapshot).expect("same map");
+        assert_eq!(vm.cpu.register.get(Register::X10), 1312);
+        assert_eq!(vm.memory.word_at(8).expect("in range"), 0x0101_0101);
+        assert_eq!(vm.memory.word_at(PAGE_SIZE * 10).expect("in range"), 0);
+        assert_eq!(vm.memory.private_pages(), 0);
+    }
+
+    #[test]
+    fn forks_are_independent() {
+        let parent = vm();
+        let mut child = parent.fork();
+
+        child.memory.set_byte_at(0, 2).expect("in range");
+        child.cpu.register.set(Register::X10, 0);
+
+        assert_eq!(parent.memory.byte_at(0).expect("in range"), 1);
+        assert_eq!(parent.cpu.register.get(Register::X10), 1312);
+        assert_eq!(child.memory.private_pages(), 1);
+    }
+
+    #[test]
+    fn reboot_resets_devices_and_handler() {
+        struct Counter(Rc<Cell<u32>>);
+
+        impl Ecall for Counter {
+            fn ecall(&mut self, _vm: &mut VM) -> Result<(), InstructionError> {
+                Ok(())
+            }
+
+            fn reset(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        const CLINT: u32 = 0x0200_0000;
+        let resets = Rc::new(Cell::new(0));
+        let mut vm = vm();
+        vm.memory
+            .attach("clint", CLINT, CLINT_SIZE, Box::new(Clint::new()))
+            .expect("should attach");
+        vm.set_ecall(Box::new(Counter(resets.clone())));
+        let boot = vm.snapshot();
+
+        vm.memory.write(CLINT + 0x4000, Access::Word, 7).unwrap();
+        vm.restore(&boot).expect("same map");
+        assert_eq!(vm.memory.read(CLINT + 0x4000, Access::Word).unwrap(), 7);
+        assert_eq!(resets.get(), 0);
+
+        vm.reboot(&boot).expect("same map");
+        assert_eq!(
+            vm.memory.read(CLINT + 0x4000, Access::Word).unwrap(),
+            u32::MAX
+        );
+        assert_eq!(resets.get(), 1);
+    }
+
+    #[test]
+    fn restore_requires_same_map() {
+        let snapshot = vm().snapshot();
+        let mut other = VM::default();
+        assert!(other.restore(&snapshot).is_err());
+    }
+}
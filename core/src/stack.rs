@@ -0,0 +1,257 @@
+use crate::{
+    memory::Permissions,
+    rv32i::instr::{instruction::Instruction, operation::Operation, part::Part},
+    Halt, Register, VM,
+};
+
+/// Name of the protection covering the guard zone, as shown in faults.
+pub const GUARD_NAME: &str = "stack guard";
+
+/// Most a stack grows to when none is configured.
+pub const DEFAULT_STACK_SIZE: u32 = 8 << 20;
+
+/// Stack region `[base, base + size)`, growing down from its top, with an
+/// inaccessible guard zone of `guard` bytes right below it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stack {
+    pub base: u32,
+    pub size: u32,
+    pub guard: u32,
+}
+
+impl Stack {
+    pub fn top(&self) -> u32 {
+        self.base + self.size
+    }
+}
+
+/// Stack pointer went below the stack region.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackOverflow {
+    pub pc: u32,
+    pub sp: u32,
+    pub high_water: u32,
+    pub depth: u32,
+}
+
+impl std::fmt::Display for StackOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "stack overflow at PC {:#010x}: SP {:#010x}, high-water mark {:#010x}, call depth {}",
+            self.pc, self.sp, self.high_water, self.depth
+        )
+    }
+}
+
+/// Watches the stack pointer and call depth of a running program.
+#[derive(Debug, Clone)]
+pub struct StackMonitor {
+    stack: Stack,
+    high_water: u32,
+    depth: u32,
+    max_depth: u32,
+}
+
+impl StackMonitor {
+    fn new(stack: Stack) -> Self {
+        Self {
+            stack,
+            high_water: stack.top(),
+            depth: 0,
+            max_depth: 0,
+        }
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    /// Lowest stack pointer seen so far.
+    pub fn high_water(&self) -> u32 {
+        self.high_water
+    }
+
+    /// Most bytes of stack in use at any point.
+    pub fn peak_usage(&self) -> u32 {
+        self.stack.top() - self.high_water
+    }
+
+    /// Current call depth, counting calls and returns through the link registers.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn max_depth(&self) -> u32 {
+        self.max_depth
+    }
+}
+
+fn is_link(register: u32) -> bool {
+    register == Register::X1 as u32 || register == Register::X5 as u32
+}
+
+/// Effect of an instruction on the call depth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Link {
+    Call,
+    Return,
+    None,
+}
+
+impl Link {
+    /// Classifies jumps by the link register convention of the calling ABI.
+    pub(crate) fn of(i: &Instruction) -> Self {
+        let rd = i.value(Part::Dest).unwrap_or(0);
+        match i.opcode {
+            Operation::JAL | Operation::JALR if is_link(rd) => Self::Call,
+            Operation::JALR if rd == 0 && is_link(i.value(Part::Reg1).unwrap_or(0)) => Self::Return,
+            _ => Self::None,
+        }
+    }
+}
+
+impl VM {
+    /// Monitors `stack` for overflows and points the stack pointer at its top.
+    ///
+    /// The guard zone is protected against any access, so stray pointers into
+    /// it fault even if the stack pointer never gets there.
+    pub fn set_stack(&mut self, stack: Stack) {
+        let guard = stack.guard.min(stack.base);
+        if guard > 0 {
+            self.memory.protect(
+                GUARD_NAME,
+                stack.base - guard,
+                guard as u64,
+                Permissions::NONE,
+            );
+        }
+        self.cpu.initialize(stack.top());
+        self.stack = Some(StackMonitor::new(stack));
+    }
+
+    pub fn stack(&self) -> Option<&StackMonitor> {
+        self.stack.as_ref()
+    }
+
+    /// Updates call depth and high-water mark after the instruction at `pc`
+    /// has executed.
+    pub(crate) fn track_stack(&mut self, link: Link, pc: u32) {
+        let Some(monitor) = self.stack.as_mut() else {
+            return;
+        };
+        match link {
+            Link::Call => {
+                monitor.depth += 1;
+                monitor.max_depth = monitor.max_depth.max(monitor.depth);
+            }
+            Link::Return => monitor.depth = monitor.depth.saturating_sub(1),
+            Link::None => {}
+        }
+
+        let sp = self.cpu.register.get(Register::X2);
+        if sp < monitor.high_water {
+            monitor.high_water = sp;
+            if sp < monitor.stack.base {
+                let overflow = StackOverflow {
+                    pc,
+                    sp,
+                    high_water: monitor.high_water,
+                    depth: monitor.depth,
+                };
+                #[cfg(feature = "trace")]
+                eprintln!("{}", overflow);
+                self.halt(Halt::StackOverflow(overflow));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod stack {
+    use super::*;
+    use crate::{rv32i::instr::builder::Builder, Program};
+
+    fn addi(rd: Register, rs1: Register, imm: u32) -> u32 {
+        Builder::opcode(Operation::ImmediateMath)
+            .pack(Part::Dest, rd as u32)
+            .pack(Part::Funct3, 0b000)
+            .pack(Part::Reg1, rs1 as u32)
+            .pack(Part::Imm110, imm)
+            .build()
+    }
+
+    #[test]
+    fn tracks_high_water_mark() {
+        let mut vm: VM = Default::default();
+        vm.set_stack(Stack {
+            base: 512,
+            size: 512,
+            guard: 64,
+        });
+        let program = Program::from_asm(&[
+            addi(Register::X2, Register::X2, 0xff0), // -16
+            addi(Register::X2, Register::X2, 16),
+        ]);
+        program.load(&mut vm).expect("should load");
+
+        program.run(&mut vm).expect("should run");
+
+        let monitor = vm.stack().expect("stack set");
+        assert_eq!(monitor.peak_usage(), 16);
+        assert_eq!(monitor.high_water(), 1008);
+        assert_eq!(vm.cpu.register.get(Register::X2), 1024);
+        assert!(vm.halted().is_none());
+    }
+
+    #[test]
+    fn overflow_halts() {
+        let mut vm: VM = Default::default();
+        vm.set_stack(Stack {
+            base: 1008,
+            size: 16,
+            guard: 64,
+        });
+        let program = Program::from_asm(&[
+            Builder::opcode(Operation::JAL)
+                .pack(Part::Dest, Register::X1 as u32)
+                .pack(Part::Imm101, 4)
+                .build(),
+            0,
+            addi(Register::X2, Register::X2, 0xfe0), // -32
+            addi(Register::X2, Register::X2, 32),
+        ]);
+        program.load(&mut vm).expect("should load");
+
+        while !program.is_done(&vm) {
+            program.step(&mut vm, 0).expect("should run");
+        }
+
+        assert_eq!(
+            vm.halted(),
+            Some(&Halt::StackOverflow(StackOverflow {
+                pc: 8,
+                sp: 992,
+                high_water: 992,
+                depth: 1,
+            }))
+        );
+    }
+
+    #[test]
+    fn guard_is_inaccessible() {
+        use crate::{memory::Access, Bus};
+
+        let mut vm: VM = Default::default();
+        vm.set_stack(Stack {
+            base: 512,
+            size: 512,
+            guard: 64,
+        });
+
+        assert!(vm.memory.read(448, Access::Word).is_err());
+        assert!(vm.memory.read(444, Access::Word).is_ok());
+        assert!(vm.memory.write(512, Access::Word, 1).is_ok());
+    }
+}
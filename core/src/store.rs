@@ -28,7 +28,10 @@ mod byte {
         assert_eq!(vm.cpu.register.get(Register::X13), expected);
         assert_eq!(vm.cpu.register.get(Register::X12), 1);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.byte_at(14).expect("memory access") as u32, expected);
+        assert_eq!(
+            vm.memory.byte_at(14).expect("memory access") as u32,
+            expected
+        );
     }
 
     #[test]
@@ -56,7 +59,10 @@ mod byte {
         assert_eq!(vm.cpu.register.get(Register::X13), expected);
         assert_eq!(vm.cpu.register.get(Register::X12), 161 + 32);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.byte_at(161).expect("memory access") as u32, expected);
+        assert_eq!(
+            vm.memory.byte_at(161).expect("memory access") as u32,
+            expected
+        );
     }
 }
 
@@ -90,7 +96,7 @@ mod half_word {
         assert_eq!(vm.cpu.register.get(Register::X13), expected);
         assert_eq!(vm.cpu.register.get(Register::X12), 1);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.hw_at(14).expect("memory access") as u32, expected);
+        assert_eq!(vm.memory.hw_at(14).expect("memory access") as u32, expected);
     }
 
     #[test]
@@ -118,7 +124,10 @@ mod half_word {
         assert_eq!(vm.cpu.register.get(Register::X13), expected);
         assert_eq!(vm.cpu.register.get(Register::X12), 161 + 32);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.hw_at(161).expect("memory access") as u32, expected);
+        assert_eq!(
+            vm.memory.hw_at(161).expect("memory access") as u32,
+            expected
+        );
     }
 }
 
@@ -152,7 +161,7 @@ mod word {
         assert_eq!(vm.cpu.register.get(Register::X13), expected);
         assert_eq!(vm.cpu.register.get(Register::X12), 1);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.word_at(14).expect("memory access") as u32, expected);
+        assert_eq!(vm.memory.word_at(14).expect("memory access"), expected);
     }
 
     #[test]
@@ -180,6 +189,35 @@ mod word {
         assert_eq!(vm.cpu.register.get(Register::X13), expected);
         assert_eq!(vm.cpu.register.get(Register::X12), 161 + 32);
         assert_eq!(vm.cpu.register.get(Register::PC), 4);
-        assert_eq!(vm.ram.word_at(161).expect("memory access") as u32, expected);
+        assert_eq!(vm.memory.word_at(161).expect("memory access"), expected);
+    }
+
+    #[test]
+    fn store_to_read_only_faults() {
+        let i = Instruction::parse(
+            Builder::opcode(Operation::Store)
+                .pack(Part::Imm40, 4)
+                .pack(Part::Funct3, 0b010)
+                .pack(Part::Reg1, Register::X12 as u32)
+                .pack(Part::Reg2, Register::X13 as u32)
+                .pack(Part::Imm115, 0b000)
+                .build(),
+        )
+        .expect("should parse");
+
+        let mut vm: VM = Default::default();
+        vm.memory.protect(".rodata", 16, 8, memory::Permissions::R);
+        vm.cpu.register.set(Register::X12, 12);
+        vm.cpu.register.set(Register::PC, 64);
+
+        match vm.execute(i) {
+            Err(InstructionError::AccessFault(fault)) => {
+                assert_eq!(fault.pc, 64);
+                assert_eq!(fault.address, 16);
+                assert_eq!(fault.region, ".rodata");
+            }
+            x => panic!("expected access fault, got {:?}", x),
+        }
+        assert_eq!(vm.cpu.register.get(Register::PC), 64);
     }
 }
@@ -0,0 +1,32 @@
+//! Fixtures shared by the unit tests.
+
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+use crate::{memory::Device, Memory};
+
+/// Output the test reads back afterwards.
+#[derive(Clone, Default)]
+pub struct Sink(pub Rc<RefCell<Vec<u8>>>);
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Small RAM with `device` attached as `name` at `base`.
+pub fn attach(name: &str, base: u32, size: u64, device: impl Device + 'static) -> Memory {
+    let mut m = Memory::new(16);
+    m.attach(name, base, size, Box::new(device))
+        .expect("should attach");
+    m
+}
@@ -0,0 +1,488 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::memory::FileMode;
+
+type Data = Rc<RefCell<Vec<u8>>>;
+
+/// Largest size an in-memory file can grow to.
+const MAX_FILE_SIZE: usize = 64 << 20;
+
+/// How a guest file is opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OpenFlags {
+    pub read: bool,
+    pub write: bool,
+    pub append: bool,
+    pub truncate: bool,
+    pub create: bool,
+    /// With `create`, fail if the file already exists.
+    pub exclusive: bool,
+}
+
+impl OpenFlags {
+    fn modifies(&self) -> bool {
+        self.write || self.append || self.truncate || self.create
+    }
+}
+
+/// What the guest gets to know about an open file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stat {
+    pub size: u64,
+    pub is_dir: bool,
+    /// Since the Unix epoch.
+    pub modified: Duration,
+}
+
+/// Open file inside a [`Vfs`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Handle(usize);
+
+enum Node {
+    Host(File),
+    Memory {
+        data: Data,
+        position: u64,
+        flags: OpenFlags,
+    },
+}
+
+/// Guest filesystem: at most one host directory as `/`, plus files held in
+/// memory, which take precedence.
+///
+/// Guest paths are resolved against `/`, and any that would leave the root,
+/// by `..` or by a host symlink, are refused. The root's [`FileMode`] decides
+/// what writes do: they fail, go to in-memory copies, or reach the host
+/// files. Without a root every file lives in memory.
+#[derive(Default)]
+pub struct Vfs {
+    root: Option<(PathBuf, FileMode)>,
+    files: HashMap<String, Data>,
+    nodes: Vec<Option<Node>>,
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves the host directory at `path` as `/`.
+    pub fn rooted(path: impl AsRef<Path>, mode: FileMode) -> io::Result<Self> {
+        let root = path.as_ref().canonicalize()?;
+        if !root.is_dir() {
+            return Err(io::ErrorKind::NotADirectory.into());
+        }
+        Ok(Self {
+            root: Some((root, mode)),
+            ..Default::default()
+        })
+    }
+
+    /// Adds an in-memory file at the guest path `path`, which must pass
+    /// [`guest_path`].
+    pub fn with_file(mut self, path: &str, data: Vec<u8>) -> io::Result<Self> {
+        let path = guest_path(path).ok_or(io::ErrorKind::PermissionDenied)?;
+        self.files.insert(path, Rc::new(RefCell::new(data)));
+        Ok(self)
+    }
+
+    pub fn open(&mut self, path: &str, flags: OpenFlags) -> io::Result<Handle> {
+        let path = guest_path(path).ok_or(io::ErrorKind::PermissionDenied)?;
+        if let Some(data) = self.files.get(&path) {
+            if flags.create && flags.exclusive {
+                return Err(io::ErrorKind::AlreadyExists.into());
+            }
+            return Ok(self.memory(data.clone(), flags));
+        }
+        let host = match &self.root {
+            Some((root, mode)) => Some((host_path(root, &path)?, *mode)),
+            None => None,
+        };
+        let node = match host {
+            Some((_, FileMode::ReadOnly)) if flags.modifies() => {
+                return Err(io::ErrorKind::ReadOnlyFilesystem.into())
+            }
+            Some((host, FileMode::Sync)) => OpenOptions::new()
+                .read(flags.read)
+                .write(flags.write)
+                .append(flags.append)
+                .truncate(flags.truncate)
+                .create(flags.create)
+                .create_new(flags.create && flags.exclusive)
+                .open(host)?,
+            Some((host, _)) if !flags.modifies() => File::open(host)?,
+            // Copied on first write, like a private mapping
+            host => {
+                let data = match host.map(|(host, _)| copy_in(&host)) {
+                    Some(Ok(_)) if flags.create && flags.exclusive => {
+                        return Err(io::ErrorKind::AlreadyExists.into())
+                    }
+                    Some(Ok(data)) => data,
+                    Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ if flags.create => Vec::new(),
+                    _ => return Err(io::ErrorKind::NotFound.into()),
+                };
+                let data = Rc::new(RefCell::new(data));
+                self.files.insert(path, data.clone());
+                return Ok(self.memory(data, flags));
+            }
+        };
+        Ok(self.insert(Node::Host(node)))
+    }
+
+    pub fn close(&mut self, handle: Handle) {
+        if let Some(node) = self.nodes.get_mut(handle.0) {
+            *node = None;
+        }
+    }
+
+    pub fn read(&mut self, handle: Handle, buf: &mut [u8]) -> io::Result<usize> {
+        match self.node(handle)? {
+            Node::Host(file) => file.read(buf),
+            Node::Memory { flags, .. } if !flags.read => {
+                Err(io::ErrorKind::PermissionDenied.into())
+            }
+            Node::Memory { data, position, .. } => {
+                let data = data.borrow();
+                let start = usize::try_from(*position).map_or(data.len(), |p| p.min(data.len()));
+                let n = buf.len().min(data.len() - start);
+                buf[..n].copy_from_slice(&data[start..start + n]);
+                *position += n as u64;
+                Ok(n)
+            }
+        }
+    }
+
+    pub fn write(&mut self, handle: Handle, buf: &[u8]) -> io::Result<usize> {
+        match self.node(handle)? {
+            Node::Host(file) => file.write(buf),
+            Node::Memory { flags, .. } if !flags.write && !flags.append => {
+                Err(io::ErrorKind::PermissionDenied.into())
+            }
+            Node::Memory {
+                data,
+                position,
+                flags,
+            } => {
+                let mut data = data.borrow_mut();
+                if flags.append {
+                    *position = data.len() as u64;
+                }
+                let start = usize::try_from(*position).or(Err(io::ErrorKind::FileTooLarge))?;
+                let end = start
+                    .checked_add(buf.len())
+                    .filter(|&end| end <= MAX_FILE_SIZE)
+                    .ok_or(io::ErrorKind::FileTooLarge)?;
+                if data.len() < end {
+                    data.resize(end, 0);
+                }
+                data[start..end].copy_from_slice(buf);
+                *position += buf.len() as u64;
+                Ok(buf.len())
+            }
+        }
+    }
+
+    pub fn seek(&mut self, handle: Handle, from: SeekFrom) -> io::Result<u64> {
+        match self.node(handle)? {
+            Node::Host(file) => file.seek(from),
+            Node::Memory { data, position, .. } => {
+                let target = match from {
+                    SeekFrom::Start(offset) => Some(offset),
+                    SeekFrom::Current(delta) => position.checked_add_signed(delta),
+                    SeekFrom::End(delta) => (data.borrow().len() as u64).checked_add_signed(delta),
+                };
+                *position = target.ok_or(io::ErrorKind::InvalidInput)?;
+                Ok(*position)
+            }
+        }
+    }
+
+    pub fn stat(&mut self, handle: Handle) -> io::Result<Stat> {
+        match self.node(handle)? {
+            Node::Host(file) => {
+                let meta = file.metadata()?;
+                Ok(Stat {
+                    size: meta.len(),
+                    is_dir: meta.is_dir(),
+                    modified: meta
+                        .modified()
+                        .ok()
+                        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                        .unwrap_or_default(),
+                })
+            }
+            Node::Memory { data, .. } => Ok(Stat {
+                size: data.borrow().len() as u64,
+                is_dir: false,
+                modified: Duration::ZERO,
+            }),
+        }
+    }
+
+    fn node(&mut self, handle: Handle) -> io::Result<&mut Node> {
+        self.nodes
+            .get_mut(handle.0)
+            .and_then(|n| n.as_mut())
+            .ok_or_else(|| io::ErrorKind::InvalidInput.into())
+    }
+
+    fn memory(&mut self, data: Data, flags: OpenFlags) -> Handle {
+        if flags.truncate {
+            data.borrow_mut().clear();
+        }
+        self.insert(Node::Memory {
+            data,
+            position: 0,
+            flags,
+        })
+    }
+
+    fn insert(&mut self, node: Node) -> Handle {
+        match self.nodes.iter().position(|n| n.is_none()) {
+            Some(i) => {
+                self.nodes[i] = Some(node);
+                Handle(i)
+            }
+            None => {
+                self.nodes.push(Some(node));
+                Handle(self.nodes.len() - 1)
+            }
+        }
+    }
+}
+
+/// Absolute, normalized form of a guest path, resolved against `/`, or
+/// `None` if `..` would climb above the root.
+pub fn guest_path(path: &str) -> Option<String> {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(format!("/{}", parts.join("/")))
+}
+
+/// Host file behind a normalized guest path, refusing symlinks that lead
+/// out of `root`.
+/// Contents of the host file at `path` for an in-memory copy. Only regular
+/// files are read, so a FIFO can't block the guest, and only up to the size
+/// an in-memory file may grow to.
+fn copy_in(path: &Path) -> io::Result<Vec<u8>> {
+    let metadata = fs::metadata(path)?;
+    if metadata.is_dir() {
+        return Err(io::ErrorKind::IsADirectory.into());
+    }
+    if !metadata.is_file() {
+        return Err(io::ErrorKind::InvalidInput.into());
+    }
+    let mut data = Vec::new();
+    File::open(path)?
+        .take(MAX_FILE_SIZE as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.len() > MAX_FILE_SIZE {
+        return Err(io::ErrorKind::FileTooLarge.into());
+    }
+    Ok(data)
+}
+
+fn host_path(root: &Path, path: &str) -> io::Result<PathBuf> {
+    let host = root.join(path.trim_start_matches('/'));
+    let resolved = match host.canonicalize() {
+        Ok(resolved) => resolved,
+        // Yet to be created: its directory has to be inside, and it can't be
+        // a dangling symlink that creating would follow out of the root
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let (Some(parent), Some(name)) = (host.parent(), host.file_name()) else {
+                return Err(e);
+            };
+            if host.symlink_metadata().is_ok() {
+                return Err(io::ErrorKind::PermissionDenied.into());
+            }
+            parent.canonicalize()?.join(name)
+        }
+        Err(e) => return Err(e),
+    };
+    if !resolved.starts_with(root) {
+        return Err(io::ErrorKind::PermissionDenied.into());
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod vfs {
+    use super::*;
+
+    const READ: OpenFlags = OpenFlags {
+        read: true,
+        write: false,
+        append: false,
+        truncate: false,
+        create: false,
+        exclusive: false,
+    };
+    const CREATE: OpenFlags = OpenFlags {
+        read: false,
+        write: true,
+        append: false,
+        truncate: true,
+        create: true,
+        exclusive: false,
+    };
+
+    fn root(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("brrrt-{}-vfs-{}", std::process::id(), name));
+        fs::create_dir_all(dir.join("etc")).expect("temp root");
+        fs::write(dir.join("etc/motd"), b"hello").expect("temp file");
+        dir
+    }
+
+    fn read_all(vfs: &mut Vfs, path: &str) -> io::Result<Vec<u8>> {
+        let handle = vfs.open(path, READ)?;
+        let mut buf = [0; 64];
+        let n = vfs.read(handle, &mut buf)?;
+        vfs.close(handle);
+        Ok(buf[..n].to_vec())
+    }
+
+    #[test]
+    fn normalizes_guest_paths() {
+        assert_eq!(
+            guest_path("etc/../etc/./motd").as_deref(),
+            Some("/etc/motd")
+        );
+        assert_eq!(guest_path("/").as_deref(), Some("/"));
+        assert_eq!(guest_path("/etc/../../secret"), None);
+    }
+
+    #[test]
+    fn root_refuses_traversal() {
+        let dir = root("traversal");
+        let mut vfs = Vfs::rooted(&dir, FileMode::ReadOnly).expect("root exists");
+
+        assert_eq!(read_all(&mut vfs, "/etc/motd").unwrap(), b"hello");
+        assert_eq!(read_all(&mut vfs, "etc/../etc/motd").unwrap(), b"hello");
+        let escape = format!(
+            "/../{}/etc/motd",
+            dir.file_name().unwrap().to_str().unwrap()
+        );
+        assert_eq!(
+            read_all(&mut vfs, &escape).unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/", dir.join("host")).ok();
+            assert_eq!(
+                read_all(&mut vfs, "/host/etc/hostname").unwrap_err().kind(),
+                io::ErrorKind::PermissionDenied
+            );
+        }
+        assert_eq!(
+            vfs.open("/etc/motd", CREATE).unwrap_err().kind(),
+            io::ErrorKind::ReadOnlyFilesystem
+        );
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn private_root_keeps_writes_in_memory() {
+        let dir = root("private");
+        let mut vfs = Vfs::rooted(&dir, FileMode::Private)
+            .expect("root exists")
+            .with_file("/proc/version", b"brrrt".to_vec())
+            .expect("inside the root");
+
+        let handle = vfs.open("/etc/motd", CREATE).expect("copied");
+        vfs.write(handle, b"bye").unwrap();
+        vfs.close(handle);
+        assert_eq!(read_all(&mut vfs, "/etc/motd").unwrap(), b"bye");
+        assert_eq!(fs::read(dir.join("etc/motd")).unwrap(), b"hello");
+
+        let handle = vfs.open("/tmp.txt", CREATE).expect("created in memory");
+        vfs.write(handle, b"scratch").unwrap();
+        assert_eq!(vfs.seek(handle, SeekFrom::End(-2)).unwrap(), 5);
+        assert_eq!(vfs.stat(handle).unwrap().size, 7);
+        assert!(!dir.join("tmp.txt").exists());
+        vfs.seek(handle, SeekFrom::Start(1 << 40)).unwrap();
+        assert_eq!(
+            vfs.write(handle, b"x").unwrap_err().kind(),
+            io::ErrorKind::FileTooLarge
+        );
+        assert_eq!(vfs.stat(handle).unwrap().size, 7);
+
+        assert_eq!(
+            read_all(&mut vfs, "/proc/../proc/version").unwrap(),
+            b"brrrt"
+        );
+        assert!(Vfs::new().with_file("/../version", Vec::new()).is_err());
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn private_copies_only_take_small_regular_files() {
+        let dir = root("copies");
+        let mut vfs = Vfs::rooted(&dir, FileMode::Private).expect("root exists");
+
+        File::create(dir.join("huge"))
+            .and_then(|f| f.set_len(MAX_FILE_SIZE as u64 + 1))
+            .expect("sparse file");
+        assert_eq!(
+            vfs.open("/huge", CREATE).unwrap_err().kind(),
+            io::ErrorKind::FileTooLarge
+        );
+        assert_eq!(
+            vfs.open("/etc", CREATE).unwrap_err().kind(),
+            io::ErrorKind::IsADirectory
+        );
+        #[cfg(unix)]
+        {
+            let _socket = std::os::unix::net::UnixListener::bind(dir.join("socket"));
+            assert_eq!(
+                vfs.open("/socket", CREATE).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn sync_root_writes_through() {
+        let dir = root("sync");
+        let mut vfs = Vfs::rooted(&dir, FileMode::Sync).expect("root exists");
+
+        let handle = vfs.open("/etc/new", CREATE).expect("created");
+        vfs.write(handle, b"data").unwrap();
+        vfs.close(handle);
+        assert_eq!(fs::read(dir.join("etc/new")).unwrap(), b"data");
+        assert_eq!(
+            vfs.open("/missing/new", CREATE).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        #[cfg(unix)]
+        {
+            let outside = dir.with_extension("outside");
+            std::os::unix::fs::symlink(&outside, dir.join("etc/link")).ok();
+            assert_eq!(
+                vfs.open("/etc/link", CREATE).unwrap_err().kind(),
+                io::ErrorKind::PermissionDenied
+            );
+            assert!(!outside.exists());
+        }
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -145,7 +145,7 @@ mod test {
             assert_eq!(reg, Register::X1);
             assert_eq!(val, 12);
         } else {
-            assert!(false, "unknown command");
+            panic!("unknown command");
         }
     }
 
@@ -158,7 +158,7 @@ mod test {
             assert_eq!(reg, Register::PC);
             assert_eq!(val, 13);
         } else {
-            assert!(false, "unknown command");
+            panic!("unknown command");
         }
     }
 
@@ -171,7 +171,7 @@ mod test {
             assert_eq!(reg, Register::X12);
             assert_eq!(val, 13);
         } else {
-            assert!(false, "unknown command");
+            panic!("unknown command");
         }
     }
 
@@ -184,7 +184,7 @@ mod test {
             assert_eq!(address, 1312);
             assert_eq!(byte, 161);
         } else {
-            assert!(false, "unknown command");
+            panic!("unknown command");
         }
     }
 
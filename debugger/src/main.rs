@@ -1,4 +1,4 @@
-use brrrt_cli::{load_execution_set, RuntimeError};
+use brrrt_cli::{load_execution_set_from, Options, RuntimeError};
 use brrrt_core::{Program, VM};
 use std::io;
 
@@ -10,14 +10,15 @@ use commands::{parse_command, Command};
 mod render;
 
 fn main() -> Result<(), RuntimeError> {
-    let mut vm: VM = Default::default();
-    let mut debug_vm: VM = Default::default();
+    let options = Options::from_env()?;
+    let mut vm = VM::new(options.memory.clone())?;
+    let mut debug_vm = VM::new(options.memory)?;
     let mut program: Program = Default::default();
 
-    vm.cpu.initialize();
-    debug_vm.cpu.initialize();
-    load_execution_set(&mut program, &mut vm)?;
-    load_execution_set(&mut program, &mut debug_vm)?;
+    vm.initialize();
+    debug_vm.initialize();
+    load_execution_set_from(&options.path, &mut program, &mut vm)?;
+    load_execution_set_from(&options.path, &mut program, &mut debug_vm)?;
 
     let mut quit = false;
     let mut outcome = Vec::new();
@@ -125,7 +126,7 @@ fn apply_command(input: &str, vm: &mut VM) -> Option<Action> {
             vm.cpu.register.set(reg, val);
         }
         Command::SetMemory(address, byte) => {
-            vm.ram.set_byte_at(address, byte).ok()?;
+            vm.memory.set_byte_at(address, byte).ok()?;
         }
         Command::DumpRegister(reg) => {
             return Some(Action::Inspect(render::register(reg, vm)));
@@ -154,9 +155,9 @@ mod test {
     #[test]
     fn apply_set_memory_command() {
         let mut vm: VM = Default::default();
-        assert_eq!(0, vm.ram.byte_at(161).unwrap());
+        assert_eq!(0, vm.memory.byte_at(161).unwrap());
 
         apply_command("!@ 161 13", &mut vm);
-        assert_eq!(13, vm.ram.byte_at(161).unwrap());
+        assert_eq!(13, vm.memory.byte_at(161).unwrap());
     }
 }
@@ -41,7 +41,7 @@ pub fn memory_at(start_addr: u32, vm: &VM) -> Vec<String> {
             col = 0;
         }
         let mut position = format!("{:04}:", pos).dark_grey();
-        let value = vm.ram.byte_at(pos);
+        let value = vm.memory.byte_at(pos);
         let displayable = if let Ok(value) = value {
             if value > 0 {
                 position = format!("{:04}:", pos).dark_red();
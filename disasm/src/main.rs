@@ -1,11 +1,12 @@
-use brrrt_cli::{load_execution_set, RuntimeError};
+use brrrt_cli::{load_execution_set_from, Options, RuntimeError};
 use brrrt_core::{Program, VM};
 use disasm::disassemble;
 
 fn main() -> Result<(), RuntimeError> {
-    let mut vm: VM = Default::default();
+    let options = Options::from_env()?;
+    let mut vm = VM::new(options.memory)?;
     let mut program: Program = Default::default();
-    load_execution_set(&mut program, &mut vm)?;
+    load_execution_set_from(&options.path, &mut program, &mut vm)?;
 
     while !program.is_done(&vm) {
         let instr = program.peek(&vm)?;
@@ -1,18 +1,353 @@
-use brrrt_cli::{load_execution_set, RuntimeError};
-use brrrt_core::{Program, VM};
+use std::{cell::RefCell, collections::HashMap, fs, io, rc::Rc};
 
-fn main() -> Result<(), RuntimeError> {
-    let mut vm: VM = Default::default();
-    let mut program: Program = Default::default();
+use brrrt_cli::{
+    image_end_from, load_execution_set_from, load_symbols_from, push_initial_stack_from, Machine,
+    Options, RuntimeError,
+};
+use brrrt_core::{
+    devices::{
+        Frame, Framebuffer, GoldfishRtc, SpiController, SpiFlash, TestFinisher, Uart16550,
+        VirtioBlock, FINISHER_SIZE, RTC_SIZE, SPI_SIZE, UART_SIZE, VIRTIO_SIZE,
+    },
+    elf32::Symbols,
+    linux::{Linux, LINUX_STACK_RESERVE},
+    machine::{Virt, VIRT_CLINT},
+    memory::{AccessStats, Cache, CacheStats, PAGE_SIZE},
+    sbi::Sbi,
+    vfs::Vfs,
+    Halt, Memory, Program, Register, VM,
+};
+
+/// Rows of the access table printed at exit.
+const HOTTEST: usize = 20;
 
-    vm.cpu.initialize();
-    load_execution_set(&mut program, &mut vm)?;
+fn main() {
+    let status = run().unwrap_or_else(|e| {
+        eprintln!("Error: {:?}", e);
+        e.exit_code()
+    });
+    std::process::exit(status);
+}
 
-    while !program.is_done(&vm) {
-        program.run(&mut vm)?;
+/// Runs the guest and returns the exit status it leaves behind.
+fn run() -> Result<i32, RuntimeError> {
+    let options = Options::from_env()?;
+    let image_end = image_end_from(&options.path)?;
+    let stack = options.stack(image_end);
+    let mut vm = VM::new(options.memory)?;
+    let mut program: Program = Default::default();
+
+    if options.shadow {
+        vm.enable_shadow();
+    }
+    if let Some(stack) = stack {
+        vm.set_stack(stack);
+    }
+    if let Some(base) = options.uart {
+        vm.memory
+            .attach("uart", base, UART_SIZE, Box::new(console(options.sbi)))?;
+    }
+    if let Some(base) = options.finisher {
+        vm.memory.attach(
+            "finisher",
+            base,
+            FINISHER_SIZE,
+            Box::new(TestFinisher::new()),
+        )?;
+    }
+    match options.machine {
+        Some(Machine::Virt) => {
+            let mut virt = Virt::new(console(options.sbi));
+            if let Some((_, clock)) = options.rtc {
+                virt = virt.with_clock(clock);
+            }
+            if let Some(bootargs) = &options.bootargs {
+                virt = virt.with_bootargs(bootargs);
+            }
+            for (path, mode) in &options.drives {
+                virt = virt.with_disk(VirtioBlock::open(path, *mode)?);
+            }
+            // The image can't run into the device tree above it
+            if image_end > virt.build(&mut vm)? {
+                return Err(RuntimeError::Load);
+            }
+        }
+        None => {
+            if let Some((base, clock)) = options.rtc {
+                vm.memory
+                    .attach("rtc", base, RTC_SIZE, Box::new(GoldfishRtc::new(clock)))?;
+            }
+        }
+    }
+    if options.sbi {
+        let mut sbi = Sbi::stdio();
+        if options.machine == Some(Machine::Virt) {
+            sbi = sbi.with_clint(VIRT_CLINT);
+        }
+        vm.set_ecall(Box::new(sbi));
+    }
+    if options.linux {
+        // Break and mappings share whatever lies between the image and the stack
+        let start = image_end.next_multiple_of(PAGE_SIZE);
+        let end = stack.map_or(start, |s| match s.base.saturating_sub(s.guard) {
+            floor if floor > start => floor,
+            _ => s.top().saturating_sub(LINUX_STACK_RESERVE),
+        });
+        let mut vfs = match &options.root {
+            Some((path, mode)) => Vfs::rooted(path, *mode)?,
+            None => Vfs::new(),
+        };
+        for (guest, host) in &options.guest_files {
+            vfs = vfs.with_file(guest, fs::read(host)?)?;
+        }
+        let linux = Linux::stdio(start..end.max(start)).with_vfs(vfs);
+        vm.set_ecall(Box::new(linux));
+    }
+    let last_frame: Rc<RefCell<Option<Frame>>> = Default::default();
+    if let Some((base, config)) = options.framebuffer {
+        let frames = options.frames.clone();
+        let last = last_frame.clone();
+        let fb = Framebuffer::new(config).on_present(move |frame| match &frames {
+            Some(path) if path.contains("%d") => {
+                let path = path.replace("%d", &frame.number.to_string());
+                if let Err(e) = fs::write(&path, frame.to_ppm()) {
+                    eprintln!("Cannot write {}: {}", path, e);
+                }
+            }
+            _ => *last.borrow_mut() = Some(frame),
+        });
+        vm.memory
+            .attach("framebuffer", base, config.size(), Box::new(fb))?;
+    }
+    for (i, (base, path, mode)) in options.disks.iter().enumerate() {
+        let disk = VirtioBlock::open(path, *mode)?;
+        vm.memory
+            .attach(&format!("virtio{}", i), *base, VIRTIO_SIZE, Box::new(disk))?;
+    }
+    for (i, (base, path, mode)) in options.spi_flash.iter().enumerate() {
+        let flash = SpiFlash::open(path, *mode)?;
+        let spi = SpiController::new().with_device(0, Box::new(flash));
+        vm.memory
+            .attach(&format!("spi{}", i), *base, SPI_SIZE, Box::new(spi))?;
+    }
+    for (base, path, mode) in &options.files {
+        vm.memory.map_file(path, *base, *mode)?;
+    }
+    if let Some(granularity) = options.stats {
+        vm.memory.enable_stats(granularity)?;
+    }
+    if let Some(config) = options.icache {
+        vm.memory.set_icache(config)?;
+    }
+    if let Some(config) = options.dcache {
+        vm.memory.set_dcache(config)?;
+    }
+    vm.initialize();
+    let guest = options
+        .machine
+        .is_none()
+        .then_some((options.args.as_slice(), options.env.as_slice()));
+    let result = boot(&options.path, guest, stack.is_some(), &mut program, &mut vm);
+    // Mapped files keep what the guest wrote, even when it crashed
+    vm.memory.sync_files()?;
+    let symbols = result?;
+    if let (Some(path), Some(frame)) = (&options.frames, last_frame.borrow_mut().take()) {
+        fs::write(path, frame.to_ppm())?;
     }
 
     eprintln!("{:?}", vm);
 
+    if let Some(reason) = vm.halted() {
+        eprintln!("Halted: {}", reason);
+    }
+    if let Some(monitor) = vm.stack() {
+        eprintln!(
+            "Peak stack usage: {} bytes (SP low {:#010x}), max call depth {}",
+            monitor.peak_usage(),
+            monitor.high_water(),
+            monitor.max_depth()
+        );
+    }
+
+    if let Some(shadow) = vm.shadow() {
+        for report in shadow.reports() {
+            eprintln!("{} in {}", report, symbols.describe(report.pc));
+        }
+    }
+
+    if let Some(stats) = vm.memory.stats() {
+        print_stats(&vm.memory, stats, &symbols);
+        if let Some(path) = &options.heatmap {
+            write_heatmap(path, &vm.memory, stats, &symbols)?;
+        }
+    }
+
+    if let Some(cache) = vm.memory.icache() {
+        print_cache("I-cache", cache, &symbols);
+    }
+    if let Some(cache) = vm.memory.dcache() {
+        print_cache("D-cache", cache, &symbols);
+    }
+
+    Ok(exit_status(vm.halted(), vm.cpu.register.get(Register::X10)))
+}
+
+/// Loads the program at `path` and runs it until it halts, starting over
+/// from the loaded image whenever the guest reboots. Returns its symbols.
+///
+/// `guest` holds the arguments and environment of a program started
+/// directly; a board boots firmware with its own calling convention.
+fn boot(
+    path: &str,
+    guest: Option<(&[String], &[String])>,
+    has_stack: bool,
+    program: &mut Program,
+    vm: &mut VM,
+) -> Result<Symbols, RuntimeError> {
+    load_execution_set_from(path, program, vm)?;
+    let symbols = load_symbols_from(path)?;
+    if let Some((args, env)) = guest {
+        if has_stack {
+            push_initial_stack_from(path, args, env, vm)?;
+        }
+        // Returning from the entry point ends the program
+        vm.cpu.register.set(Register::X1, program.end());
+    }
+
+    // Rebooting starts over from the freshly loaded image
+    let boot = vm.snapshot();
+    loop {
+        while !program.is_done(vm) {
+            program.run(vm)?;
+        }
+        if vm.halted() != Some(&Halt::Reboot) {
+            return Ok(symbols);
+        }
+        vm.reboot(&boot)?;
+    }
+}
+
+/// UART on stdio, leaving stdin to the SBI console if there is one.
+fn console(sbi: bool) -> Uart16550 {
+    if sbi {
+        Uart16550::new(Box::new(io::stdout()))
+    } else {
+        Uart16550::stdio()
+    }
+}
+
+/// Process exit status for the way the guest stopped, given its a0.
+fn exit_status(halt: Option<&Halt>, a0: u32) -> i32 {
+    match halt {
+        // Ran to the end: a0 is what the entry point returned
+        None => (a0 & 0xff) as i32,
+        // Truncated like a Linux exit status
+        Some(Halt::Exit(status)) => status & 0xff,
+        // Keep failures visible to the shell, which only sees the low byte
+        Some(Halt::Fail(code)) => (*code as i32).clamp(1, 255),
+        // Stopped by the runner rather than choosing a status
+        Some(Halt::StackOverflow(_) | Halt::Watchpoint(_)) => RuntimeError::Execution.exit_code(),
+        Some(Halt::PowerOff | Halt::Reboot) => 0,
+    }
+}
+
+fn print_cache(name: &str, cache: &Cache, symbols: &Symbols) {
+    let stats = cache.stats();
+    eprintln!(
+        "{}: {} hits, {} misses ({:.1}% hit rate), {} write-backs, {} penalty cycles",
+        name,
+        stats.hits,
+        stats.misses,
+        stats.hit_rate() * 100.0,
+        stats.writebacks,
+        stats.cycles
+    );
+
+    let mut functions: HashMap<String, CacheStats> = HashMap::new();
+    for (&pc, counts) in cache.stats_by_pc() {
+        let function = symbols
+            .lookup(pc)
+            .map_or_else(|| "?".to_owned(), |s| s.name.clone());
+        let total = functions.entry(function).or_default();
+        total.hits += counts.hits;
+        total.misses += counts.misses;
+        total.writebacks += counts.writebacks;
+        total.cycles += counts.cycles;
+    }
+    let mut functions: Vec<_> = functions.into_iter().collect();
+    functions.sort_by(|a, b| b.1.misses.cmp(&a.1.misses).then(a.0.cmp(&b.0)));
+    eprintln!(
+        "\t{:<24} {:>10} {:>10} {:>8} {:>10}",
+        "function", "hits", "misses", "hit %", "cycles"
+    );
+    for (function, counts) in functions {
+        eprintln!(
+            "\t{:<24} {:>10} {:>10} {:>8.1} {:>10}",
+            function,
+            counts.hits,
+            counts.misses,
+            counts.hit_rate() * 100.0,
+            counts.cycles
+        );
+    }
+}
+
+/// Symbol covering the block at `base`, or else the first one starting in it.
+fn block_symbol(symbols: &Symbols, base: u32, size: u32) -> String {
+    symbols
+        .lookup(base)
+        .or_else(|| {
+            symbols
+                .iter()
+                .find(|s| s.address >= base && s.address - base < size)
+        })
+        .map_or_else(String::new, |s| s.name.clone())
+}
+
+fn region_name(memory: &Memory, address: u32) -> String {
+    memory
+        .region_at(address)
+        .map_or_else(String::new, |r| r.name.clone())
+}
+
+fn print_stats(memory: &Memory, stats: &AccessStats, symbols: &Symbols) {
+    let size = stats.granularity();
+    eprintln!("Hottest {}-byte blocks:", size);
+    eprintln!(
+        "\t{:<10} {:<12} {:<24} {:>10} {:>10} {:>10}",
+        "address", "region", "symbol", "reads", "writes", "fetches"
+    );
+    for (base, counts) in stats.hottest().into_iter().take(HOTTEST) {
+        eprintln!(
+            "\t{:#010x} {:<12} {:<24} {:>10} {:>10} {:>10}",
+            base,
+            region_name(memory, base),
+            block_symbol(symbols, base, size),
+            counts.reads,
+            counts.writes,
+            counts.fetches
+        );
+    }
+}
+
+fn write_heatmap(
+    path: &str,
+    memory: &Memory,
+    stats: &AccessStats,
+    symbols: &Symbols,
+) -> Result<(), RuntimeError> {
+    let mut csv = String::from("address,region,symbol,reads,writes,fetches\n");
+    for (base, counts) in stats.blocks() {
+        csv.push_str(&format!(
+            "{:#010x},{},{},{},{},{}\n",
+            base,
+            region_name(memory, base),
+            block_symbol(symbols, base, stats.granularity()),
+            counts.reads,
+            counts.writes,
+            counts.fetches
+        ));
+    }
+    fs::write(path, csv)?;
     Ok(())
 }